@@ -1,17 +1,29 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use crud_crait::criteria::Criteria;
+use crud_crait::cursor::CursorPage;
 use crud_crait::CRUD;
-use engine_craits::EngineType;
+use engine_craits::{
+    BatchExecutor, BatchQuery, BatchResult, BlockStream, DataBlock, EngineRegistry, EngineType,
+    QueryEngine, QueryOptions, DEFAULT_ENGINE,
+};
+use query::{QueryBuilder, QueryError, SchemaChange, ValidationError};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, MySqlPool};
+use sqlx::{FromRow, Transaction};
 use util_crait::uuid_util;
 
+use crate::pre_aggregation::{PreAggregationResolver, SavedPreAggregation};
+
 use async_graphql::{InputObject, SimpleObject};
-use crud_crait::entity::{Entity, MySqlRepository, Page, PageRequest};
+use crud_crait::audit::StaleEntity;
+use crud_crait::backend::{Metadata, MetadataPool, MetadataRepository};
+use crud_crait::entity::{Entity, Page, PageRequest, Sort};
+use crud_crait::unit_of_work::UnitOfWork;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 ///The entity of Dataset
-#[derive(Debug, Deserialize, Serialize, Clone, FromRow)]
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, Entity)]
 pub struct Dataset {
     ///primary key
     pub id: String,
@@ -21,14 +33,33 @@ pub struct Dataset {
     pub display_name: String,
     ///the fields
     pub engine_type: String,
+    ///the registered engine connection which stores the table, empty for the default one
+    #[serde(default)]
+    pub engine_name: String,
     pub size: f64,
     pub count: i32,
+    ///milliseconds since the unix epoch
+    #[serde(default)]
+    #[entity(created_at)]
+    pub created_at: i64,
+    #[serde(default)]
+    #[entity(updated_at)]
+    pub updated_at: i64,
+    ///the owner
+    #[serde(default)]
+    #[entity(created_by)]
+    pub created_by: Option<String>,
+    #[serde(default)]
+    #[entity(deleted)]
+    pub deleted: bool,
+    ///the version which was read, an update of an older version is rejected
+    #[serde(default)]
+    #[entity(version)]
+    pub version: i32,
 }
 
 ::async_graphql::scalar!(Dataset);
 
-impl Entity for Dataset {}
-
 impl Default for Dataset {
     fn default() -> Self {
         Self {
@@ -36,8 +67,14 @@ impl Default for Dataset {
             name: "".to_string(),
             display_name: "".to_string(),
             engine_type: EngineType::ClickHouse.get_type(),
+            engine_name: "".to_string(),
             size: 0.0,
             count: 0,
+            created_at: 0,
+            updated_at: 0,
+            created_by: None,
+            deleted: false,
+            version: 0,
         }
     }
 }
@@ -52,14 +89,34 @@ pub enum DataType {
 impl DataType {
     pub fn get_type_name(&self) -> String {
         match self {
-            Text => "Test".to_string(),
-            Number => "Number".to_string(),
-            Date => "Date".to_string(),
+            DataType::Text => "Text".to_string(),
+            DataType::Number => "Number".to_string(),
+            DataType::Date => "Date".to_string(),
+        }
+    }
+
+    pub fn from_type_name(name: &str) -> Option<DataType> {
+        match name {
+            // "Test" was stored by earlier versions for every field
+            "Text" | "Test" => Some(DataType::Text),
+            "Number" => Some(DataType::Number),
+            "Date" => Some(DataType::Date),
+            _ => None,
+        }
+    }
+}
+
+impl From<DataType> for query::DataType {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::Text => query::DataType::Text,
+            DataType::Number => query::DataType::Number,
+            DataType::Date => query::DataType::Date,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, FromRow)]
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, Entity)]
 pub struct Field {
     pub id: String,
     pub name: String,
@@ -68,11 +125,34 @@ pub struct Field {
     pub field_type: String,
     pub display_name: String,
     pub formula: String,
+    #[serde(default)]
+    #[entity(created_at)]
+    pub created_at: i64,
+    #[serde(default)]
+    #[entity(updated_at)]
+    pub updated_at: i64,
+    #[serde(default)]
+    #[entity(created_by)]
+    pub created_by: Option<String>,
+    ///the fields are changed with their dataset, whose version is checked
+    #[serde(default)]
+    #[entity(deleted)]
+    pub deleted: bool,
 }
 
 ::async_graphql::scalar!(Field);
 
-impl Entity for Field {}
+impl Field {
+    ///the field used by the query model, unknown data types are treated as text
+    pub fn to_query_field(&self) -> query::Field {
+        let data_type = DataType::from_type_name(&self.data_type).unwrap_or(DataType::Text);
+        query::Field::new(
+            self.name.clone(),
+            data_type.into(),
+            self.display_name.clone(),
+        )
+    }
+}
 
 impl Default for Field {
     fn default() -> Self {
@@ -84,6 +164,10 @@ impl Default for Field {
             field_type: "".to_string(),
             display_name: "".to_string(),
             formula: "".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            created_by: None,
+            deleted: false,
         }
     }
 }
@@ -100,12 +184,21 @@ pub struct DataSetOutObject {
     pub fields: Vec<Field>,
 }
 
+///a query of a dashboard widget on a dataset
+#[derive(Debug, Clone)]
+pub struct WidgetQuery {
+    pub widget_id: String,
+    pub dataset_id: String,
+    pub query: QueryBuilder,
+}
+
 pub struct DataSetResolver;
 
 impl DataSetResolver {
     pub async fn create(
         dataset: &DataSetInputObject,
-        pool: &MySqlPool,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
     ) -> Result<DataSetOutObject> {
         let mut new_dataset = dataset.dataset.clone();
         let id = uuid_util::get_uuid();
@@ -114,8 +207,6 @@ impl DataSetResolver {
             new_dataset.name = "t_".to_string() + &uuid_util::get_short_uuid();
         }
 
-        MySqlRepository::add(&new_dataset, &pool).await?;
-
         let mut new_fields = vec![];
 
         dataset
@@ -135,41 +226,430 @@ impl DataSetResolver {
             .for_each(|field| {
                 new_fields.push(field);
             });
-        for field in &new_fields {
-            MySqlRepository::add(field, &pool).await?;
+
+        // the table is created first, so no dataset points to a missing table,
+        // and dropped again when the dataset is not saved
+        let engine = registry
+            .route(&new_dataset.engine_name, &new_dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        let undo = [SchemaChange::DropTable {
+            table: new_dataset.name.clone(),
+        }];
+        alter_table(
+            engine.as_ref(),
+            &[create_table(&new_dataset.name, &new_fields)],
+            &undo,
+        )
+        .await?;
+
+        let mut work = UnitOfWork::new();
+        work.add(&new_dataset);
+        work.add_all(&new_fields);
+        if let Err(error) = work.commit(pool).await {
+            return Err(revert(engine.as_ref(), &undo, error).await);
         }
-        Ok(DataSetOutObject {
-            dataset: new_dataset,
+        // the audit columns are filled in by the repository
+        Self::find_by_id(&id, pool).await
+    }
 
-            fields: new_fields,
-        })
+    ///update the dataset and its fields, and alter the table to match.
+    ///fields are matched by id, fields without an id are added. the version of the dataset
+    ///should be the one which was read, otherwise it is a `StaleEntity` error.
+    ///the dataset is locked from the version check until its changes are written,
+    ///so a table altered is not left with the fields of a concurrent update.
+    ///the table is changed back when the changes can not be written
+    pub async fn update(
+        dataset: &DataSetInputObject,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<DataSetOutObject> {
+        let id = &dataset.dataset.id;
+        let mut tx = pool.begin().await?;
+        let old = Self::lock_by_id(id, &mut tx).await?;
+        if dataset.dataset.version != old.dataset.version {
+            return Err(StaleEntity {
+                table: Dataset::table_name(),
+                id: id.clone(),
+            }
+            .into());
+        }
+        let mut new_dataset = dataset.dataset.clone();
+        if new_dataset.name.is_empty() {
+            new_dataset.name = old.dataset.name.clone();
+        }
+        if new_dataset.engine_type != old.dataset.engine_type
+            || new_dataset.engine_name != old.dataset.engine_name
+        {
+            return Err(anyhow!("dataset {} can not be moved to another engine", id));
+        }
+
+        let new_fields: Vec<Field> = dataset
+            .fields
+            .iter()
+            .map(|field| field.clone())
+            .map(|mut field| {
+                if field.name.is_empty() {
+                    field.name = "f_".to_string() + &uuid_util::get_short_uuid();
+                }
+                field.dataset_id = id.clone();
+                field
+            })
+            .collect();
+        check_field_ids(id, &old.fields, &new_fields)?;
+
+        let mut changes = diff_fields(&old.dataset.name, &old.fields, &new_fields);
+        if new_dataset.name != old.dataset.name {
+            changes.push(SchemaChange::RenameTable {
+                from: old.dataset.name.clone(),
+                to: new_dataset.name.clone(),
+            });
+        }
+        let engine = registry
+            .route(&old.dataset.engine_name, &old.dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        if engine.engine_type() == EngineType::ClickHouse {
+            check_sorting_key(&old.fields, &changes)?;
+        }
+        let undo = changes
+            .iter()
+            .map(|change| undo_change(change, &old.fields, &new_fields))
+            .collect::<Option<Vec<SchemaChange>>>()
+            .ok_or_else(|| anyhow!("the changes of dataset {} can not be undone", id))?;
+        let broken = PreAggregationResolver::broken(id, &changes, &mut tx).await?;
+
+        let mut work = UnitOfWork::new();
+        work.update(&new_dataset);
+        work.delete_by_ids::<SavedPreAggregation>(&broken)?;
+        let removed: Vec<String> = old
+            .fields
+            .iter()
+            .filter(|field| !new_fields.iter().any(|f| f.id == field.id))
+            .map(|field| field.id.clone())
+            .collect();
+        work.delete_by_ids::<Field>(&removed)?;
+        let fields: Vec<Field> = new_fields
+            .into_iter()
+            .map(|mut field| {
+                if field.id.is_empty() {
+                    field.id = uuid_util::get_uuid();
+                }
+                field
+            })
+            .collect();
+        work.upsert(&fields);
+
+        alter_table(engine.as_ref(), &changes, &undo).await?;
+        let written = match work.commit(&mut tx).await {
+            Ok(_) => tx.commit().await.map_err(anyhow::Error::from),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = written {
+            return Err(revert(engine.as_ref(), &undo, error).await);
+        }
+        Self::find_by_id(id, pool).await
     }
 
-    pub async fn find_by_id(id: &String, pool: &MySqlPool) -> Result<DataSetOutObject> {
-        let dataset = MySqlRepository::find_by_id::<Dataset>(id, pool)
+    ///drop the table and delete the dataset with its fields, the dataset is locked
+    ///so that it is not updated after its table is dropped
+    pub async fn delete(
+        id: &String,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<bool> {
+        let mut tx = pool.begin().await?;
+        let dataset = match MetadataRepository::lock_by_id::<Dataset>(id, &mut tx).await? {
+            Some(dataset) => dataset,
+            None => return Ok(false),
+        };
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        let change = SchemaChange::DropTable {
+            table: dataset.name.clone(),
+        };
+        engine
+            .alter_schema(change.clone())
+            .await
+            .map_err(|e| anyhow!(e.to_string()))?;
+        let broken = PreAggregationResolver::broken(id, &[change], &mut tx).await?;
+
+        let mut work = UnitOfWork::new();
+        work.delete_by_ids::<SavedPreAggregation>(&broken)?;
+        work.delete_where::<Field>(&Criteria::all().eq("dataset_id", id.as_str()))?;
+        work.delete_by_id::<Dataset>(id);
+        let deleted = work.commit(&mut tx).await? > 0;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    ///empty the table, the dataset and its fields are kept
+    pub async fn truncate(
+        id: &String,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<()> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        engine
+            .alter_schema(SchemaChange::TruncateTable {
+                table: dataset.name,
+            })
+            .await
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    pub async fn find_by_id(id: &String, pool: &MetadataPool) -> Result<DataSetOutObject> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
             .await?
-            .unwrap();
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
 
-        let mut params = BTreeMap::new();
-        params.insert(String::from("dataset_id"), id.clone());
-        let fields = MySqlRepository::query::<Field>(&params, pool).await?;
+        let criteria = Criteria::all().eq("dataset_id", id.as_str());
+        let fields = MetadataRepository::query::<Field>(&criteria, pool).await?;
 
         Ok(DataSetOutObject { dataset, fields })
     }
 
+    ///find the dataset and lock it until the transaction ends
+    async fn lock_by_id(
+        id: &String,
+        tx: &mut Transaction<'_, Metadata>,
+    ) -> Result<DataSetOutObject> {
+        let dataset = MetadataRepository::lock_by_id::<Dataset>(id, &mut *tx)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+
+        let criteria = Criteria::all().eq("dataset_id", id.as_str());
+        let fields = MetadataRepository::query::<Field>(&criteria, &mut *tx).await?;
+
+        Ok(DataSetOutObject { dataset, fields })
+    }
+
+    ///check the query against the table and fields of the dataset
+    pub async fn validate_query(
+        id: &String,
+        query_builder: &QueryBuilder,
+        pool: &MetadataPool,
+    ) -> Result<Vec<QueryError>> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+
+        let criteria = Criteria::all().eq("dataset_id", id.as_str());
+        let fields: Vec<query::Field> = MetadataRepository::query::<Field>(&criteria, pool)
+            .await?
+            .iter()
+            .map(|field| field.to_query_field())
+            .collect();
+
+        match query_builder.validate(&dataset.name, &fields) {
+            Ok(()) => Ok(vec![]),
+            Err(e) => Ok(e.errors),
+        }
+    }
+
+    ///run the query on the engine which the dataset is bound to, within the limits of `options`
+    pub async fn query(
+        id: &String,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<DataBlock> {
+        let (engine, query_builder) = Self::bind_query(id, query_builder, pool, registry).await?;
+        engine
+            .query(query_builder, options)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    ///like `query`, but returns the result in batches as the engine produces them
+    pub async fn stream(
+        id: &String,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<BlockStream> {
+        let (engine, query_builder) = Self::bind_query(id, query_builder, pool, registry).await?;
+        engine
+            .stream(query_builder, options)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    ///run the queries of a dashboard concurrently and return the results keyed by widget id.
+    ///a widget whose dataset is missing or whose query is invalid is reported in `errors`
+    ///like a failed query, the other widgets are still answered
+    pub async fn query_batch(
+        widgets: Vec<WidgetQuery>,
+        options: &QueryOptions,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+        executor: &BatchExecutor,
+    ) -> Result<BatchResult> {
+        let mut queries = vec![];
+        let mut errors = BTreeMap::new();
+        for widget in widgets {
+            match Self::bind_batch_query(&widget, pool, registry).await {
+                Ok(query) => queries.push(query),
+                Err(e) => {
+                    errors.insert(widget.widget_id, e.to_string());
+                }
+            }
+        }
+
+        let mut result = executor.run(registry, queries, options).await;
+        result.errors.extend(errors);
+        Ok(result)
+    }
+
+    async fn bind_batch_query(
+        widget: &WidgetQuery,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<BatchQuery> {
+        let (dataset, query) =
+            Self::bind_dataset(&widget.dataset_id, widget.query.clone(), pool).await?;
+        registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        let engine = if dataset.engine_name.is_empty() {
+            String::from(DEFAULT_ENGINE)
+        } else {
+            dataset.engine_name
+        };
+        Ok(BatchQuery {
+            id: widget.widget_id.clone(),
+            engine,
+            query,
+        })
+    }
+
+    ///route to the engine of the dataset and validate the query against its table
+    async fn bind_query(
+        id: &String,
+        query_builder: QueryBuilder,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<(Arc<dyn QueryEngine>, QueryBuilder)> {
+        let (dataset, query_builder) = Self::bind_dataset(id, query_builder, pool).await?;
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        Ok((engine, query_builder))
+    }
+
+    ///point the query to the table of the dataset and validate it
+    async fn bind_dataset(
+        id: &String,
+        query_builder: QueryBuilder,
+        pool: &MetadataPool,
+    ) -> Result<(Dataset, QueryBuilder)> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+        let query_builder = query_builder.table(dataset.name.clone());
+        let errors = Self::validate_query(id, &query_builder, pool).await?;
+        if !errors.is_empty() {
+            return Err(ValidationError { errors }.into());
+        }
+        Ok((dataset, query_builder))
+    }
+
+    ///append the rows of the block to the table of the dataset, by column name.
+    ///the cached results of the dataset are dropped by the engine
+    pub async fn append(
+        id: &String,
+        block: DataBlock,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<()> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        engine
+            .insert(&dataset.name, block)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    ///drop the cached results of the dataset, to be called after its table is loaded
+    ///outside of the engines. schema changes and `append` invalidate the cache already
+    pub async fn invalidate(
+        id: &String,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<()> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+        if let Some(cache) = registry.cache() {
+            cache.invalidate(&dataset.name);
+        }
+        Ok(())
+    }
+
+    ///cancel a running query of the dataset, returns false if the query is not running
+    pub async fn cancel(
+        id: &String,
+        query_id: &str,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<bool> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", id))?;
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        engine
+            .cancel(query_id)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    ///the datasets after the cursor with their fields, see `MetadataRepository::query_after`
+    pub async fn find_after(
+        criteria: &Criteria,
+        sort: &[Sort],
+        after: Option<&str>,
+        first: i64,
+        pool: &MetadataPool,
+    ) -> Result<CursorPage<DataSetOutObject>> {
+        let datasets =
+            MetadataRepository::query_after::<Dataset>(criteria, sort, after, first, pool).await?;
+
+        let mut edges = vec![];
+        for (cursor, dataset) in datasets.edges {
+            let criteria = Criteria::all().eq("dataset_id", dataset.id.as_str());
+            let fields = MetadataRepository::query::<Field>(&criteria, pool).await?;
+            edges.push((cursor, DataSetOutObject { dataset, fields }));
+        }
+        Ok(CursorPage {
+            edges,
+            has_next_page: datasets.has_next_page,
+        })
+    }
+
     pub async fn find_by_page(
         page_request: &PageRequest,
-        params: &BTreeMap<String, String>,
-        pool: &MySqlPool,
+        criteria: &Criteria,
+        pool: &MetadataPool,
     ) -> Result<Page<DataSetOutObject>> {
         let dataset_page =
-            MySqlRepository::query_page::<Dataset>(&params, &page_request, pool).await?;
+            MetadataRepository::query_page::<Dataset>(criteria, &page_request, pool).await?;
 
         let mut out_object = vec![];
         for dataset in dataset_page.context {
-            let mut params = BTreeMap::new();
-            params.insert(String::from("dataset_id"), dataset.id.clone());
-            let fields = MySqlRepository::query::<Field>(&params, pool).await?;
+            let criteria = Criteria::all().eq("dataset_id", dataset.id.as_str());
+            let fields = MetadataRepository::query::<Field>(&criteria, pool).await?;
             out_object.push(DataSetOutObject { dataset, fields })
         }
         let page = Page::new(
@@ -183,10 +663,189 @@ impl DataSetResolver {
     }
 }
 
+///the table of a dataset, ordered by its date fields
+fn create_table(table: &str, fields: &[Field]) -> SchemaChange {
+    SchemaChange::CreateTable {
+        table: table.to_string(),
+        fields: fields.iter().map(|f| f.to_query_field()).collect(),
+        order_by: sorting_key(fields),
+    }
+}
+
+///the date fields, which are the sorting key when the table is created
+fn sorting_key(fields: &[Field]) -> Vec<String> {
+    fields
+        .iter()
+        .map(|f| f.to_query_field())
+        .filter(|f| f.field_type == query::DataType::Date)
+        .map(|f| f.field_name)
+        .collect()
+}
+
+///ClickHouse can not drop, rename or convert the columns of the sorting key, they are
+///checked before the table is altered so that it is not left half changed.
+///date fields added after the table was created are not in the key, but are checked too
+fn check_sorting_key(fields: &[Field], changes: &[SchemaChange]) -> Result<()> {
+    let key = sorting_key(fields);
+    for change in changes {
+        let column = match change {
+            SchemaChange::DropColumn { column, .. } => column,
+            SchemaChange::RenameColumn { from, .. } => from,
+            SchemaChange::ModifyColumn { field, .. } => &field.field_name,
+            _ => continue,
+        };
+        if key.contains(column) {
+            return Err(anyhow!(
+                "field {} is in the sorting key of the table, it can not be dropped, renamed \
+                 or changed to another type",
+                column
+            ));
+        }
+    }
+    Ok(())
+}
+
+///apply the changes in order, when one of them fails the applied ones are undone
+async fn alter_table(
+    engine: &dyn QueryEngine,
+    changes: &[SchemaChange],
+    undo: &[SchemaChange],
+) -> Result<()> {
+    for (applied, change) in changes.iter().enumerate() {
+        let altered = engine
+            .alter_schema(change.clone())
+            .await
+            .map_err(|e| anyhow!(e.to_string()));
+        if let Err(error) = altered {
+            return Err(revert(engine, &undo[..applied], error).await);
+        }
+    }
+    Ok(())
+}
+
+///undo the applied changes in reverse order and return `error`. when an undo fails too,
+///the error names it, the table is left with the changes before it
+async fn revert(
+    engine: &dyn QueryEngine,
+    undo: &[SchemaChange],
+    error: anyhow::Error,
+) -> anyhow::Error {
+    for change in undo.iter().rev() {
+        let reverted = engine
+            .alter_schema(change.clone())
+            .await
+            .map_err(|e| e.to_string());
+        if let Err(e) = reverted {
+            return anyhow!(
+                "{}, and the table is not restored, {:?} failed: {}",
+                error,
+                change,
+                e
+            );
+        }
+    }
+    error
+}
+
+///the change which reverts `change` of the fields from `old` to `new`,
+///a dropped column is added back without its data
+fn undo_change(change: &SchemaChange, old: &[Field], new: &[Field]) -> Option<SchemaChange> {
+    let undo = match change {
+        SchemaChange::CreateTable { table, .. } => SchemaChange::DropTable {
+            table: table.clone(),
+        },
+        SchemaChange::AddColumn { table, field } => SchemaChange::DropColumn {
+            table: table.clone(),
+            column: field.field_name.clone(),
+        },
+        SchemaChange::DropColumn { table, column } => SchemaChange::AddColumn {
+            table: table.clone(),
+            field: old.iter().find(|f| &f.name == column)?.to_query_field(),
+        },
+        SchemaChange::RenameColumn { table, from, to } => SchemaChange::RenameColumn {
+            table: table.clone(),
+            from: to.clone(),
+            to: from.clone(),
+        },
+        // the column is renamed before its type is changed, so it has the new name
+        SchemaChange::ModifyColumn { table, field } => {
+            let id = &new.iter().find(|f| f.name == field.field_name)?.id;
+            let previous = Field {
+                name: field.field_name.clone(),
+                ..old.iter().find(|f| &f.id == id)?.clone()
+            };
+            SchemaChange::ModifyColumn {
+                table: table.clone(),
+                field: previous.to_query_field(),
+            }
+        }
+        SchemaChange::RenameTable { from, to } => SchemaChange::RenameTable {
+            from: to.clone(),
+            to: from.clone(),
+        },
+        _ => return None,
+    };
+    Some(undo)
+}
+
+///a field with an id should be one of the dataset, otherwise the field of another dataset
+///would be moved to this one
+fn check_field_ids(dataset_id: &str, old: &[Field], new: &[Field]) -> Result<()> {
+    for field in new {
+        if !field.id.is_empty() && !old.iter().any(|f| f.id == field.id) {
+            return Err(anyhow!(
+                "field {} is not a field of dataset {}",
+                field.id,
+                dataset_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+///the column changes from the old fields to the new ones, matched by id.
+///a changed data type converts the data of the column
+fn diff_fields(table: &str, old: &[Field], new: &[Field]) -> Vec<SchemaChange> {
+    let mut changes = vec![];
+    for field in old {
+        match new.iter().find(|f| !f.id.is_empty() && f.id == field.id) {
+            Some(f) => {
+                if f.name != field.name {
+                    changes.push(SchemaChange::RenameColumn {
+                        table: table.to_string(),
+                        from: field.name.clone(),
+                        to: f.name.clone(),
+                    });
+                }
+                if f.data_type != field.data_type {
+                    changes.push(SchemaChange::ModifyColumn {
+                        table: table.to_string(),
+                        field: f.to_query_field(),
+                    });
+                }
+            }
+            None => changes.push(SchemaChange::DropColumn {
+                table: table.to_string(),
+                column: field.name.clone(),
+            }),
+        }
+    }
+    for field in new {
+        let kept = old.iter().any(|f| !field.id.is_empty() && f.id == field.id);
+        if !kept {
+            changes.push(SchemaChange::AddColumn {
+                table: table.to_string(),
+                field: field.to_query_field(),
+            });
+        }
+    }
+    changes
+}
+
 // #[async_trait]
 // impl CRUD for Dataset {
 //     type Result = Dataset;
-//     type Pool = MySqlPool;
+//     type Pool = MetadataPool;
 //
 //     async fn create(dataset: Dataset, pool: &Self::Pool) -> Result<Dataset> {
 //         let mut new_dataset = dataset.clone();
@@ -307,7 +966,7 @@ impl DataSetResolver {
 #[async_trait]
 impl CRUD for Field {
     type Result = Field;
-    type Pool = MySqlPool;
+    type Pool = MetadataPool;
 
     async fn create(model: Self::Result, pool: &Self::Pool) -> Result<Field> {
         todo!()
@@ -330,26 +989,175 @@ impl CRUD for Field {
 mod tests {
     use super::*;
     use engine_craits::EngineType;
+    use memory_engine::MemoryEngine;
     use serde::de::DeserializeOwned;
     use serde::Serialize;
     use serde_json::{Map, Value};
     use std::env;
     use util_crait::uuid_util;
 
+    #[test]
+    fn test_to_query_field() {
+        let field = Field {
+            name: "f_1".to_string(),
+            data_type: DataType::Number.get_type_name(),
+            ..Field::default()
+        };
+        assert_eq!(field.to_query_field().field_type, query::DataType::Number);
+
+        let field = Field {
+            name: "f_2".to_string(),
+            data_type: "Test".to_string(),
+            ..Field::default()
+        };
+        assert_eq!(field.to_query_field().field_type, query::DataType::Text);
+    }
+
+    fn field(id: &str, name: &str, data_type: DataType) -> Field {
+        Field {
+            id: id.to_string(),
+            name: name.to_string(),
+            data_type: data_type.get_type_name(),
+            ..Field::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_fields() {
+        let old = vec![
+            field("1", "org", DataType::Text),
+            field("2", "day", DataType::Text),
+            field("3", "amount", DataType::Number),
+        ];
+        let new = vec![
+            field("1", "org_name", DataType::Text),
+            field("2", "day", DataType::Date),
+            field("", "price", DataType::Number),
+        ];
+
+        let sql: Vec<String> = diff_fields("t_1", &old, &new)
+            .iter()
+            .map(|change| change.to_sql(&query::ClickHouseDialect).unwrap())
+            .collect();
+        assert_eq!(
+            sql,
+            vec![
+                "alter table `t_1` rename column `org` to `org_name`",
+                "alter table `t_1` modify column `day` Nullable(Date)",
+                "alter table `t_1` drop column `amount`",
+                "alter table `t_1` add column `price` Nullable(Float64)",
+            ]
+        );
+
+        match create_table("t_1", &new) {
+            SchemaChange::CreateTable { order_by, .. } => assert_eq!(order_by, vec!["day"]),
+            _ => unreachable!(),
+        }
+
+        let changes = diff_fields("t_1", &old, &new);
+        assert!(check_sorting_key(&old, &changes).is_ok());
+        let error = check_sorting_key(&new, &diff_fields("t_1", &new, &old)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "field day is in the sorting key of the table, it can not be dropped, renamed \
+             or changed to another type"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alter_table() -> Result<()> {
+        let engine = MemoryEngine::new();
+        let old = vec![
+            field("1", "org", DataType::Text),
+            field("2", "amount", DataType::Number),
+        ];
+        alter_table(&engine, &[create_table("t_1", &old)], &[]).await?;
+
+        let new = vec![
+            field("1", "org_name", DataType::Text),
+            field("2", "amount", DataType::Text),
+            field("", "price", DataType::Number),
+        ];
+        let mut changes = diff_fields("t_1", &old, &new);
+        let undo: Vec<SchemaChange> = changes
+            .iter()
+            .map(|change| undo_change(change, &old, &new).unwrap())
+            .collect();
+        // the engine fails on the last change, the ones before it are undone
+        changes.push(SchemaChange::DropColumn {
+            table: "t_1".to_string(),
+            column: "day".to_string(),
+        });
+        let error = alter_table(&engine, &changes, &undo).await.unwrap_err();
+        assert_eq!(error.to_string(), "invalid query: unknown field: day");
+
+        // the table has the old fields again
+        for change in diff_fields("t_1", &old, &new) {
+            engine
+                .alter_schema(change)
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+
+        // an undo which fails is named in the error
+        let error = revert(
+            &engine,
+            &[SchemaChange::DropTable {
+                table: "t_1".to_string(),
+            }],
+            anyhow!("commit failed"),
+        )
+        .await;
+        assert_eq!(error.to_string(), "commit failed");
+        let error = revert(
+            &engine,
+            &[SchemaChange::DropColumn {
+                table: "t_1".to_string(),
+                column: "day".to_string(),
+            }],
+            anyhow!("commit failed"),
+        )
+        .await;
+        assert!(error
+            .to_string()
+            .starts_with("commit failed, and the table is not restored"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_field_ids() {
+        let old = vec![field("1", "org", DataType::Text)];
+        let new = vec![
+            field("1", "org_name", DataType::Text),
+            field("", "price", DataType::Number),
+        ];
+        assert!(check_field_ids("d_1", &old, &new).is_ok());
+
+        // the field of another dataset
+        let new = vec![
+            field("1", "org", DataType::Text),
+            field("9", "day", DataType::Date),
+        ];
+        let error = check_field_ids("d_1", &old, &new).unwrap_err();
+        assert_eq!(error.to_string(), "field 9 is not a field of dataset d_1");
+    }
+
     #[tokio::test]
     async fn test_add() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
         println!("db url is {} ", database_url);
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         let mut ds = Dataset {
             id: "".to_string(),
             name: "".to_string(),
             display_name: "测试数据集".to_string(),
-            engine_type: EngineType::ClickHouse.get_type(),
+            engine_type: EngineType::Memory.get_type(),
+            engine_name: "".to_string(),
             size: 0.0,
             count: 0,
+            ..Dataset::default()
         };
 
         let field1 = Field {
@@ -360,6 +1168,7 @@ mod tests {
             field_type: "".to_string(),
             display_name: "组织".to_string(),
             formula: "".to_string(),
+            ..Field::default()
         };
 
         let field2 = Field {
@@ -370,6 +1179,7 @@ mod tests {
             field_type: "".to_string(),
             display_name: "时间".to_string(),
             formula: "".to_string(),
+            ..Field::default()
         };
 
         let field3 = Field {
@@ -380,6 +1190,7 @@ mod tests {
             field_type: "".to_string(),
             display_name: "本期数".to_string(),
             formula: "".to_string(),
+            ..Field::default()
         };
 
         let fields = vec![field1, field2, field3];
@@ -389,9 +1200,14 @@ mod tests {
             fields,
         };
 
-        let output = DataSetResolver::create(&datasetInput, &db_pool).await?;
+        let registry =
+            EngineRegistry::new().register(DEFAULT_ENGINE, Arc::new(MemoryEngine::new()));
+        let output = DataSetResolver::create(&datasetInput, &db_pool, &registry).await?;
         println!("{:?}", output);
 
+        let deleted = DataSetResolver::delete(&output.dataset.id, &db_pool, &registry).await?;
+        assert!(deleted);
+
         Ok(())
     }
 
@@ -436,7 +1252,7 @@ mod tests {
     //     dotenv::dotenv().ok();
     //     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
     //     println!("db url is {} " , database_url);
-    //     let db_pool = MySqlPool::connect(&database_url).await?;
+    //     let db_pool = MetadataPool::connect(&database_url).await?;
     //
     //     let mut  ds = Dataset {
     //         id: "".to_string(),
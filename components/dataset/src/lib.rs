@@ -1,3 +1,9 @@
 pub mod dataset;
+pub mod pre_aggregation;
+pub mod saved_query;
 
-pub use self::dataset::{DataSetInputObject, DataSetOutObject, DataSetResolver, Dataset};
+pub use self::dataset::{
+    DataSetInputObject, DataSetOutObject, DataSetResolver, Dataset, WidgetQuery,
+};
+pub use self::pre_aggregation::{PreAggregationResolver, SavedPreAggregation};
+pub use self::saved_query::{SavedQuery, SavedQueryResolver};
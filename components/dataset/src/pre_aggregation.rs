@@ -0,0 +1,143 @@
+use crate::dataset::{DataSetResolver, Dataset};
+use anyhow::{anyhow, Result};
+use crud_crait::backend::{Metadata, MetadataPool, MetadataRepository};
+use crud_crait::criteria::Criteria;
+use crud_crait::entity::Entity;
+use engine_craits::EngineRegistry;
+use query::{PreAggregation, QueryBuilder, SchemaChange, ValidationError};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, FromRow};
+use util_crait::uuid_util;
+
+///The entity of a pre-aggregation, the definition is stored as json so that it can be
+///registered to the engine again after a restart
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, Entity)]
+pub struct SavedPreAggregation {
+    ///primary key
+    pub id: String,
+    ///the name of the rollup table
+    pub name: String,
+    ///the dataset which the rollup aggregates
+    pub dataset_id: String,
+    ///the pre-aggregation json
+    pub definition: String,
+}
+
+::async_graphql::scalar!(SavedPreAggregation);
+
+pub struct PreAggregationResolver;
+
+impl PreAggregationResolver {
+    ///declare a rollup of the dataset by the dimensions and measures of the query,
+    ///the rollup table is named after the table of the dataset and built by its engine
+    pub async fn create(
+        dataset_id: &String,
+        name: &str,
+        query_builder: &QueryBuilder,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<SavedPreAggregation> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(dataset_id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", dataset_id))?;
+        let query_builder = query_builder.clone().table(dataset.name.clone());
+        let pre = PreAggregation::from_query(format!("{}_{}", dataset.name, name), &query_builder);
+        pre.check()?;
+        let errors = DataSetResolver::validate_query(dataset_id, &pre.source_query(), pool).await?;
+        if !errors.is_empty() {
+            return Err(ValidationError { errors }.into());
+        }
+
+        let engine = registry
+            .route(&dataset.engine_name, &dataset.engine_type)
+            .map_err(|e| anyhow!(e))?;
+        engine
+            .create_pre_aggregation(pre.clone())
+            .await
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let saved = SavedPreAggregation {
+            id: uuid_util::get_uuid(),
+            name: pre.name.clone(),
+            dataset_id: dataset_id.clone(),
+            definition: serde_json::to_string(&pre)?,
+        };
+        MetadataRepository::add(&saved, pool).await?;
+        Ok(saved)
+    }
+
+    ///drop the rollup table, queries are answered by the table of the dataset again
+    pub async fn delete(
+        id: &String,
+        pool: &MetadataPool,
+        registry: &EngineRegistry,
+    ) -> Result<bool> {
+        let saved = match MetadataRepository::find_by_id::<SavedPreAggregation>(id, pool).await? {
+            Some(saved) => saved,
+            None => return Ok(false),
+        };
+        if let Some(dataset) =
+            MetadataRepository::find_by_id::<Dataset>(&saved.dataset_id, pool).await?
+        {
+            let engine = registry
+                .route(&dataset.engine_name, &dataset.engine_type)
+                .map_err(|e| anyhow!(e))?;
+            engine
+                .drop_pre_aggregation(&saved.name)
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+        MetadataRepository::delete_by_id::<SavedPreAggregation>(id, pool).await
+    }
+
+    pub async fn find_by_dataset(
+        dataset_id: &String,
+        pool: &MetadataPool,
+    ) -> Result<Vec<SavedPreAggregation>> {
+        let criteria = Criteria::all().eq("dataset_id", dataset_id.as_str());
+        MetadataRepository::query::<SavedPreAggregation>(&criteria, pool).await
+    }
+
+    ///the ids of the saved rollups which the engine dropped for the schema changes of the
+    ///dataset, they are deleted with the changes of the dataset
+    pub(crate) async fn broken<'c>(
+        dataset_id: &str,
+        changes: &[SchemaChange],
+        executor: impl Executor<'c, Database = Metadata>,
+    ) -> Result<Vec<String>> {
+        let criteria = Criteria::all().eq("dataset_id", dataset_id);
+        let mut broken = vec![];
+        for saved in MetadataRepository::query::<SavedPreAggregation>(&criteria, executor).await? {
+            let pre: PreAggregation = serde_json::from_str(&saved.definition)?;
+            if changes.iter().any(|change| pre.is_broken_by(change)) {
+                broken.push(saved.id);
+            }
+        }
+        Ok(broken)
+    }
+
+    ///register the saved rollups to the engines, called once at startup.
+    ///rollups of deleted datasets are skipped
+    pub async fn register_all(pool: &MetadataPool, registry: &EngineRegistry) -> Result<usize> {
+        let saved =
+            MetadataRepository::query::<SavedPreAggregation>(&Criteria::all(), pool).await?;
+        let mut registered = 0;
+        for saved in saved {
+            let dataset =
+                match MetadataRepository::find_by_id::<Dataset>(&saved.dataset_id, pool).await? {
+                    Some(dataset) => dataset,
+                    None => continue,
+                };
+            let pre: PreAggregation = serde_json::from_str(&saved.definition)?;
+            let engine = registry
+                .route(&dataset.engine_name, &dataset.engine_type)
+                .map_err(|e| anyhow!(e))?;
+            engine
+                .register_pre_aggregation(pre)
+                .await
+                .map_err(|e| anyhow!("pre-aggregation {}: {}", saved.name, e))?;
+            registered += 1;
+        }
+        Ok(registered)
+    }
+}
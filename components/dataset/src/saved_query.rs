@@ -0,0 +1,91 @@
+use crate::dataset::{DataSetResolver, Dataset};
+use anyhow::{anyhow, Result};
+use crud_crait::backend::{MetadataPool, MetadataRepository};
+use crud_crait::entity::Entity;
+use query::{QueryBuilder, ValidationError, QUERY_MODEL_VERSION};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use util_crait::uuid_util;
+
+///The entity of a saved query, the query model is stored as versioned json
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, Entity)]
+pub struct SavedQuery {
+    ///primary key
+    pub id: String,
+    ///the name to display
+    pub name: String,
+    ///the dataset which the query runs on
+    pub dataset_id: String,
+    ///the version of the query model when saved
+    pub version: i32,
+    ///the query model json
+    pub query: String,
+}
+
+::async_graphql::scalar!(SavedQuery);
+
+pub struct SavedQueryResolver;
+
+impl SavedQueryResolver {
+    ///save a new query, or update it if the id already exists
+    pub async fn save(
+        id: &String,
+        name: &String,
+        dataset_id: &String,
+        query_builder: &QueryBuilder,
+        pool: &MetadataPool,
+    ) -> Result<SavedQuery> {
+        let query_builder = Self::check(dataset_id, query_builder.clone(), pool).await?;
+
+        let mut saved_query = SavedQuery {
+            id: id.clone(),
+            name: name.clone(),
+            dataset_id: dataset_id.clone(),
+            version: QUERY_MODEL_VERSION as i32,
+            query: query_builder.to_json()?,
+        };
+
+        if saved_query.id.is_empty() {
+            saved_query.id = uuid_util::get_uuid();
+            MetadataRepository::add(&saved_query, pool).await?;
+        } else {
+            MetadataRepository::update(&saved_query, pool).await?;
+        }
+        Ok(saved_query)
+    }
+
+    pub async fn find_by_id(id: &String, pool: &MetadataPool) -> Result<SavedQuery> {
+        MetadataRepository::find_by_id::<SavedQuery>(id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("saved query {} not found", id))
+    }
+
+    ///load the query model and check it against the current fields of the dataset
+    pub async fn load(id: &String, pool: &MetadataPool) -> Result<QueryBuilder> {
+        let saved_query = Self::find_by_id(id, pool).await?;
+        let query_builder = QueryBuilder::from_json(&saved_query.query)?;
+        Self::check(&saved_query.dataset_id, query_builder, pool).await
+    }
+
+    pub async fn delete_by_id(id: &String, pool: &MetadataPool) -> Result<bool> {
+        MetadataRepository::delete_by_id::<SavedQuery>(id, pool).await
+    }
+
+    ///bind the query to the table of the dataset and validate it
+    async fn check(
+        dataset_id: &String,
+        query_builder: QueryBuilder,
+        pool: &MetadataPool,
+    ) -> Result<QueryBuilder> {
+        let dataset = MetadataRepository::find_by_id::<Dataset>(dataset_id, pool)
+            .await?
+            .ok_or_else(|| anyhow!("dataset {} not found", dataset_id))?;
+        let query_builder = query_builder.table(dataset.name);
+
+        let errors = DataSetResolver::validate_query(dataset_id, &query_builder, pool).await?;
+        if !errors.is_empty() {
+            return Err(ValidationError { errors }.into());
+        }
+        Ok(query_builder)
+    }
+}
@@ -255,6 +255,21 @@ impl FormulaEngine {
         Ok(())
     }
 
+    ///按id排序的公式id, 返回 `after` 之后的最多 `limit` 个
+    pub async fn list_ids(after: &str, limit: i64, graph: &Graph) -> Result<Vec<String>> {
+        let q = query("MATCH (n:Formula) WHERE n.formula_id > $after RETURN DISTINCT n.formula_id AS formula_id ORDER BY formula_id LIMIT $limit")
+            .param("after", after.to_string())
+            .param("limit", limit);
+        let mut result = graph.execute(q).await?;
+        let mut ids = vec![];
+        while let Ok(Some(row)) = result.next().await {
+            if let Some(id) = row.get::<String>("formula_id") {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
     ///返回公式的树形依赖结构
     async fn tree(&mut self, graph: &Graph) -> Result<FormulaTree> {
         FormulaEngine::tree_by_id(&self.id, graph).await
@@ -0,0 +1,31 @@
+use async_graphql::connection::{Connection, Edge};
+use async_graphql::{FieldResult, OutputType};
+use crud_crait::cursor::CursorPage;
+
+///the page size when `first` is absent
+pub const DEFAULT_FIRST: i32 = 20;
+///the largest page a client can request
+pub const MAX_FIRST: i32 = 100;
+
+///`first` within the limits, a non positive one is rejected
+pub fn first(first: Option<i32>) -> FieldResult<i64> {
+    match first.unwrap_or(DEFAULT_FIRST) {
+        n if n <= 0 => Err(format!("first should be positive, got {}", n).into()),
+        n => Ok(n.min(MAX_FIRST) as i64),
+    }
+}
+
+///Relay connection of the rows, only forward paging is supported,
+///so there is a previous page whenever the client passed a cursor
+pub fn connection<T: OutputType>(
+    page: CursorPage<T>,
+    after: &Option<String>,
+) -> Connection<String, T> {
+    let mut connection = Connection::new(after.is_some(), page.has_next_page);
+    connection.append(
+        page.edges
+            .into_iter()
+            .map(|(cursor, node)| Edge::new(cursor, node)),
+    );
+    connection
+}
@@ -1,19 +1,28 @@
+pub mod connection;
 pub mod mutation_root;
 pub mod query_dataset;
 pub mod query_formula;
+pub mod query_pre_aggregation;
 pub mod query_root;
+pub mod query_saved_query;
 pub mod query_user;
 
 pub use self::query_root::QueryRoot;
 use crate::query_root::MutationRoot;
 use async_graphql::{EmptySubscription, Schema};
+use crud_crait::backend::MetadataPool;
+use engine_craits::{BatchExecutor, EngineRegistry};
 use neo4rs::Graph;
-use sqlx::MySqlPool;
 use std::sync::Arc;
 
 pub type RootSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
 
-pub fn create_schema(pool: &MySqlPool, neo4j_pool: &Arc<Graph>) -> RootSchema {
+pub fn create_schema(
+    pool: &MetadataPool,
+    neo4j_pool: &Arc<Graph>,
+    registry: &Arc<EngineRegistry>,
+    executor: &Arc<BatchExecutor>,
+) -> RootSchema {
     Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
@@ -21,5 +30,7 @@ pub fn create_schema(pool: &MySqlPool, neo4j_pool: &Arc<Graph>) -> RootSchema {
     )
     .data(pool.clone())
     .data(neo4j_pool.clone())
+    .data(registry.clone())
+    .data(executor.clone())
     .finish()
 }
@@ -1,16 +1,83 @@
-use async_graphql::{Context, FieldResult, Object};
-use crud_crait::entity::{Page, PageRequest};
-use dataset::{DataSetInputObject, DataSetOutObject, DataSetResolver};
-use sqlx::MySqlPool;
-use std::collections::BTreeMap;
+use crate::connection;
+use async_graphql::connection::Connection;
+use async_graphql::{Context, FieldResult, InputObject, Object, OutputJson};
+use crud_crait::backend::MetadataPool;
+use crud_crait::criteria::Criteria;
+use crud_crait::entity::{Page, PageRequest, Sort};
+use dataset::{DataSetInputObject, DataSetOutObject, DataSetResolver, WidgetQuery};
+use engine_craits::{
+    BatchExecutor, BatchResult, CacheStats, DataBlock, EngineRegistry, QueryOptions,
+};
+use query::{QueryBuilder, QueryError};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Default)]
 pub struct QueryDataset;
 
+///the limits of a query, absent ones are not applied.
+///`query_id` is used to cancel the query
+#[derive(InputObject, Default)]
+pub struct QueryOptionsInput {
+    #[graphql(default)]
+    query_id: String,
+    timeout_ms: Option<u64>,
+    max_rows: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+impl From<QueryOptionsInput> for QueryOptions {
+    fn from(input: QueryOptionsInput) -> Self {
+        QueryOptions {
+            query_id: input.query_id,
+            timeout: input.timeout_ms.map(Duration::from_millis),
+            max_rows: input.max_rows.map(|n| n as usize),
+            max_bytes: input.max_bytes.map(|n| n as usize),
+        }
+    }
+}
+
+///the filters of the dataset list, absent ones are not applied
+#[derive(InputObject, Default)]
+pub struct DatasetFilterInput {
+    ///part of the table name or the display name
+    name: Option<String>,
+    engine_type: Option<String>,
+    engine_name: Option<String>,
+}
+
+impl From<DatasetFilterInput> for Criteria {
+    fn from(input: DatasetFilterInput) -> Self {
+        let mut criteria = Criteria::all();
+        if let Some(name) = input.name {
+            criteria = criteria.group(
+                Criteria::any()
+                    .contains("name", &name)
+                    .contains("display_name", &name),
+            );
+        }
+        if let Some(engine_type) = input.engine_type {
+            criteria = criteria.eq("engine_type", engine_type);
+        }
+        if let Some(engine_name) = input.engine_name {
+            criteria = criteria.eq("engine_name", engine_name);
+        }
+        criteria
+    }
+}
+
+///the query of a dashboard widget, `query` is the json of a QueryBuilder
+#[derive(InputObject)]
+pub struct WidgetInput {
+    id: String,
+    dataset_id: String,
+    query: String,
+}
+
 #[Object]
 impl QueryDataset {
     async fn datasets(&self, ctx: &Context<'_>) -> FieldResult<Vec<String>> {
-        // let pool = ctx.data_unchecked::<MySqlPool>();
+        // let pool = ctx.data_unchecked::<MetadataPool>();
         // let datasets = Dataset::find_all(pool).await?;
         // println!("datasets: {:?}", datasets);
         // Ok(datasets)
@@ -22,7 +89,7 @@ impl QueryDataset {
         ctx: &Context<'_>,
         id: String,
     ) -> FieldResult<DataSetOutObject> {
-        let pool = ctx.data_unchecked::<MySqlPool>();
+        let pool = ctx.data_unchecked::<MetadataPool>();
 
         let output = DataSetResolver::find_by_id(&id, pool).await?;
         Ok(output)
@@ -32,13 +99,100 @@ impl QueryDataset {
         &self,
         ctx: &Context<'_>,
         page: PageRequest,
+        #[graphql(default)] filter: DatasetFilterInput,
     ) -> FieldResult<Page<DataSetOutObject>> {
-        let pool = ctx.data_unchecked::<MySqlPool>();
-        let params = BTreeMap::new();
+        let pool = ctx.data_unchecked::<MetadataPool>();
 
-        let output = DataSetResolver::find_by_page(&page, &params, pool).await?;
+        let output = DataSetResolver::find_by_page(&page, &filter.into(), pool).await?;
         Ok(output)
     }
+
+    ///the datasets after the cursor, ordered by `sort` and then by id
+    async fn dataset_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        first: Option<i32>,
+        #[graphql(default)] sort: Vec<Sort>,
+        #[graphql(default)] filter: DatasetFilterInput,
+    ) -> FieldResult<Connection<String, DataSetOutObject>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let page = DataSetResolver::find_after(
+            &filter.into(),
+            &sort,
+            after.as_deref(),
+            connection::first(first)?,
+            pool,
+        )
+        .await?;
+        Ok(connection::connection(page, &after))
+    }
+
+    async fn validate_query(
+        &self,
+        ctx: &Context<'_>,
+        dataset_id: String,
+        query: String,
+    ) -> FieldResult<OutputJson<Vec<QueryError>>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let query_builder = QueryBuilder::from_json(&query)?;
+        let errors = DataSetResolver::validate_query(&dataset_id, &query_builder, pool).await?;
+        Ok(errors.into())
+    }
+
+    ///run the query on the engine of the dataset, within the limits of `options`
+    async fn query_dataset(
+        &self,
+        ctx: &Context<'_>,
+        dataset_id: String,
+        query: String,
+        #[graphql(default)] options: QueryOptionsInput,
+    ) -> FieldResult<OutputJson<DataBlock>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let query_builder = QueryBuilder::from_json(&query)?;
+        let block =
+            DataSetResolver::query(&dataset_id, query_builder, &options.into(), pool, registry)
+                .await?;
+        Ok(block.into())
+    }
+
+    ///load all widgets of a dashboard in one request, identical queries run once.
+    ///the limits of `options` apply to each query, failed widgets are reported in `errors`
+    async fn query_dashboard(
+        &self,
+        ctx: &Context<'_>,
+        widgets: Vec<WidgetInput>,
+        #[graphql(default)] options: QueryOptionsInput,
+    ) -> FieldResult<OutputJson<BatchResult>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let executor = ctx.data_unchecked::<Arc<BatchExecutor>>();
+        let widgets = widgets
+            .into_iter()
+            .map(|widget| {
+                Ok(WidgetQuery {
+                    widget_id: widget.id,
+                    dataset_id: widget.dataset_id,
+                    query: QueryBuilder::from_json(&widget.query)?,
+                })
+            })
+            .collect::<FieldResult<Vec<WidgetQuery>>>()?;
+        let result =
+            DataSetResolver::query_batch(widgets, &options.into(), pool, registry, executor)
+                .await?;
+        Ok(result.into())
+    }
+
+    ///hits and misses of the query result cache, all zero when the cache is disabled
+    async fn query_cache_stats(&self, ctx: &Context<'_>) -> FieldResult<OutputJson<CacheStats>> {
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let stats = registry
+            .cache()
+            .map(|cache| cache.stats())
+            .unwrap_or_default();
+        Ok(stats.into())
+    }
 }
 
 #[derive(Default)]
@@ -51,8 +205,55 @@ impl MutationDataset {
         ctx: &Context<'_>,
         dataset_object: DataSetInputObject,
     ) -> FieldResult<DataSetOutObject> {
-        let pool = ctx.data_unchecked::<MySqlPool>();
-        let output = DataSetResolver::create(&dataset_object, pool).await?;
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let output = DataSetResolver::create(&dataset_object, pool, registry).await?;
         Ok(output)
     }
+
+    async fn update_dataset(
+        &self,
+        ctx: &Context<'_>,
+        dataset_object: DataSetInputObject,
+    ) -> FieldResult<DataSetOutObject> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let output = DataSetResolver::update(&dataset_object, pool, registry).await?;
+        Ok(output)
+    }
+
+    async fn delete_dataset(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let deleted = DataSetResolver::delete(&id, pool, registry).await?;
+        Ok(deleted)
+    }
+
+    async fn cancel_query(
+        &self,
+        ctx: &Context<'_>,
+        dataset_id: String,
+        query_id: String,
+    ) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let cancelled = DataSetResolver::cancel(&dataset_id, &query_id, pool, registry).await?;
+        Ok(cancelled)
+    }
+
+    ///drop the cached results after the dataset table is reloaded or appended to
+    async fn invalidate_dataset(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        DataSetResolver::invalidate(&id, pool, registry).await?;
+        Ok(true)
+    }
+
+    ///remove all rows of the dataset table
+    async fn truncate_dataset(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        DataSetResolver::truncate(&id, pool, registry).await?;
+        Ok(true)
+    }
 }
@@ -1,4 +1,7 @@
+use crate::connection;
+use async_graphql::connection::Connection;
 use async_graphql::{Context, FieldResult, Object, OutputJson};
+use crud_crait::cursor::{self, CursorPage};
 use formula::formula_engine::FormulaEngine;
 use formula::formula_node::*;
 use neo4rs::Graph;
@@ -19,6 +22,36 @@ impl QueryFormula {
         Ok(ft.into())
     }
 
+    ///the ids of the formulas after the cursor, ordered by id
+    async fn formula_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> FieldResult<Connection<String, String>> {
+        let graph = ctx.data_unchecked::<std::sync::Arc<Graph>>();
+        let first = connection::first(first)?;
+        let from = match &after {
+            Some(after) => cursor::decode(after)?
+                .first()
+                .and_then(|id| id.as_str())
+                .map(String::from)
+                .ok_or_else(|| format!("invalid cursor {}", after))?,
+            None => String::new(),
+        };
+        let mut ids = FormulaEngine::list_ids(&from, first + 1, graph).await?;
+        let has_next_page = ids.len() as i64 > first;
+        ids.truncate(first as usize);
+        let page = CursorPage {
+            edges: ids
+                .into_iter()
+                .map(|id| (cursor::encode(&[id.clone().into()]), id))
+                .collect(),
+            has_next_page,
+        };
+        Ok(connection::connection(page, &after))
+    }
+
     async fn formula_calculate(&self, ctx: &Context<'_>, formula: String) -> FieldResult<String> {
         let graph = ctx.data_unchecked::<std::sync::Arc<Graph>>(); //Arc<neo4rs::graph::Graph>
 
@@ -0,0 +1,53 @@
+use async_graphql::{Context, FieldResult, Object};
+use crud_crait::backend::MetadataPool;
+use dataset::{PreAggregationResolver, SavedPreAggregation};
+use engine_craits::EngineRegistry;
+use query::QueryBuilder;
+use std::sync::Arc;
+
+#[derive(Default)]
+pub struct QueryPreAggregation;
+
+#[Object]
+impl QueryPreAggregation {
+    async fn find_pre_aggregations(
+        &self,
+        ctx: &Context<'_>,
+        dataset_id: String,
+    ) -> FieldResult<Vec<SavedPreAggregation>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let output = PreAggregationResolver::find_by_dataset(&dataset_id, pool).await?;
+        Ok(output)
+    }
+}
+
+#[derive(Default)]
+pub struct MutationPreAggregation;
+
+#[Object]
+impl MutationPreAggregation {
+    ///build a rollup of the dataset by the dimensions and measures of `query`,
+    ///queries which it can answer are routed to it afterwards
+    async fn create_pre_aggregation(
+        &self,
+        ctx: &Context<'_>,
+        dataset_id: String,
+        name: String,
+        query: String,
+    ) -> FieldResult<SavedPreAggregation> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let query_builder = QueryBuilder::from_json(&query)?;
+        let output =
+            PreAggregationResolver::create(&dataset_id, &name, &query_builder, pool, registry)
+                .await?;
+        Ok(output)
+    }
+
+    async fn delete_pre_aggregation(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let registry = ctx.data_unchecked::<Arc<EngineRegistry>>();
+        let deleted = PreAggregationResolver::delete(&id, pool, registry).await?;
+        Ok(deleted)
+    }
+}
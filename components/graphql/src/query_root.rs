@@ -1,10 +1,18 @@
 use crate::query_dataset::{MutationDataset, QueryDataset};
 use crate::query_formula::QueryFormula;
+use crate::query_pre_aggregation::{MutationPreAggregation, QueryPreAggregation};
+use crate::query_saved_query::{MutationSavedQuery, QuerySavedQuery};
 use crate::query_user::QueryUser;
 use async_graphql::MergedObject;
 
 #[derive(MergedObject, Default)]
-pub struct QueryRoot(QueryUser, QueryDataset, QueryFormula);
+pub struct QueryRoot(
+    QueryUser,
+    QueryDataset,
+    QueryFormula,
+    QuerySavedQuery,
+    QueryPreAggregation,
+);
 
 #[derive(MergedObject, Default)]
-pub struct MutationRoot(MutationDataset);
+pub struct MutationRoot(MutationDataset, MutationSavedQuery, MutationPreAggregation);
@@ -0,0 +1,49 @@
+use async_graphql::{Context, FieldResult, Object};
+use crud_crait::backend::MetadataPool;
+use dataset::{SavedQuery, SavedQueryResolver};
+use query::QueryBuilder;
+
+#[derive(Default)]
+pub struct QuerySavedQuery;
+
+#[Object]
+impl QuerySavedQuery {
+    async fn find_saved_query_by_id(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> FieldResult<SavedQuery> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        // validate against the current fields of the dataset
+        SavedQueryResolver::load(&id, pool).await?;
+        let output = SavedQueryResolver::find_by_id(&id, pool).await?;
+        Ok(output)
+    }
+}
+
+#[derive(Default)]
+pub struct MutationSavedQuery;
+
+#[Object]
+impl MutationSavedQuery {
+    async fn save_query(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] id: String,
+        name: String,
+        dataset_id: String,
+        query: String,
+    ) -> FieldResult<SavedQuery> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let query_builder = QueryBuilder::from_json(&query)?;
+        let output =
+            SavedQueryResolver::save(&id, &name, &dataset_id, &query_builder, pool).await?;
+        Ok(output)
+    }
+
+    async fn delete_saved_query(&self, ctx: &Context<'_>, id: String) -> FieldResult<bool> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let deleted = SavedQueryResolver::delete_by_id(&id, pool).await?;
+        Ok(deleted)
+    }
+}
@@ -1,5 +1,8 @@
+use crate::connection;
+use async_graphql::connection::Connection;
 use async_graphql::{Context, FieldResult, Object};
-use sqlx::MySqlPool;
+use crud_crait::backend::{MetadataPool, MetadataRepository};
+use crud_crait::criteria::Criteria;
 use user::User;
 
 #[derive(Default)]
@@ -8,9 +11,28 @@ pub struct QueryUser;
 #[Object]
 impl QueryUser {
     async fn users(&self, ctx: &Context<'_>) -> FieldResult<Vec<User>> {
-        let pool = ctx.data_unchecked::<MySqlPool>();
+        let pool = ctx.data_unchecked::<MetadataPool>();
         let users = User::find_all(pool).await?;
         println!("users: {:?}", users);
         Ok(users)
     }
+
+    ///the users after the cursor, ordered by id
+    async fn user_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        first: Option<i32>,
+    ) -> FieldResult<Connection<String, User>> {
+        let pool = ctx.data_unchecked::<MetadataPool>();
+        let page = MetadataRepository::query_after::<User>(
+            &Criteria::all(),
+            &[],
+            after.as_deref(),
+            connection::first(first)?,
+            pool,
+        )
+        .await?;
+        Ok(connection::connection(page, &after))
+    }
 }
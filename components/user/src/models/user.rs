@@ -1,12 +1,15 @@
 use anyhow::Result;
+use crud_crait::backend::{MetadataPool, MetadataRepository};
+use crud_crait::criteria::Criteria;
+use crud_crait::entity::Entity;
 use serde::{Deserialize, Serialize};
-use sqlx::mysql::MySqlRow;
 use sqlx::FromRow;
-use sqlx::{MySqlPool, Row};
 
-#[derive(Debug, Deserialize, Serialize, Clone, FromRow)]
+#[derive(Debug, Deserialize, Serialize, Clone, FromRow, Entity)]
 pub struct User {
-    id: u64,
+    ///auto increment
+    #[entity(read_only)]
+    id: i64,
     name: Option<String>,
     age: Option<i32>,
 }
@@ -18,60 +21,27 @@ impl User {
         User { id: 0, name, age }
     }
 
-    pub fn new_all(id: u64, name: Option<String>, age: Option<i32>) -> User {
+    pub fn new_all(id: i64, name: Option<String>, age: Option<i32>) -> User {
         User { id, name, age }
     }
 
-    pub async fn create(user: User, pool: &MySqlPool) -> Result<u64> {
-        let mut tx = pool.begin().await?;
-        let user_id = sqlx::query(" INSERT INTO t_lighting_user (name, age) VALUES (?, ?)")
-            .bind(user.name)
-            .bind(user.age)
-            .execute(&mut tx)
-            .await?
-            .last_insert_id();
-
-        tx.commit().await?;
-        Ok(user_id)
+    pub async fn create(user: User, pool: &MetadataPool) -> Result<i64> {
+        MetadataRepository::add_returning_id(&user, pool).await
     }
 
-    pub async fn update(user: User, pool: &MySqlPool) -> Result<bool> {
-        let mut tx = pool.begin().await?;
-        let rows_affected =
-            sqlx::query("UPDATE t_lighting_user SET name = ?, age = ? WHERE id = ?")
-                .bind(&user.name)
-                .bind(user.age)
-                .bind(user.id)
-                .execute(&mut tx)
-                .await?
-                .rows_affected();
-
-        tx.commit().await?;
-        Ok(rows_affected > 0)
+    pub async fn update(user: User, pool: &MetadataPool) -> Result<bool> {
+        MetadataRepository::update(&user, pool).await
     }
 
-    pub async fn delete(id: u64, pool: &MySqlPool) -> Result<bool> {
-        let deleted = sqlx::query("DELETE FROM t_lighting_user WHERE id = ?")
-            .bind(id)
-            .execute(pool)
-            .await?
-            .rows_affected();
-
+    pub async fn delete(id: i64, pool: &MetadataPool) -> Result<bool> {
+        let criteria = Criteria::all().eq("id", id);
+        let deleted = MetadataRepository::delete_where::<User>(&criteria, pool).await?;
         Ok(deleted > 0)
     }
 
-    pub async fn find_all(pool: &MySqlPool) -> Result<Vec<User>> {
-        let users = sqlx::query("SELECT id, name, age FROM t_lighting_user ORDER BY id")
-            .try_map(|row: MySqlRow| {
-                Ok(User {
-                    id: row.try_get::<u64, _>(0)?,
-                    name: row.try_get::<Option<String>, _>(1)?,
-                    age: row.try_get::<Option<i32>, _>(2)?,
-                })
-            })
-            .fetch_all(pool)
-            .await?;
-
+    pub async fn find_all(pool: &MetadataPool) -> Result<Vec<User>> {
+        let mut users = MetadataRepository::query::<User>(&Criteria::all(), pool).await?;
+        users.sort_by_key(|user| user.id);
         Ok(users)
     }
 }
@@ -86,7 +56,7 @@ mod tests {
     async fn test_crud() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         //create
         let user_id =
@@ -117,7 +87,7 @@ mod tests {
     async fn test_create() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         let user_id =
             User::create(User::new(Some(String::from("nauu1")), Some(18)), &db_pool).await?;
@@ -129,7 +99,7 @@ mod tests {
     async fn test_find_all() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         let users = User::find_all(&db_pool).await?;
         for u in users.iter() {
@@ -142,7 +112,7 @@ mod tests {
     async fn test_update() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         let updated = User::update(
             User::new_all(1, Some(String::from("nauu111")), Some(28)),
@@ -157,7 +127,7 @@ mod tests {
     async fn test_delete() -> Result<()> {
         dotenv::dotenv().ok();
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-        let db_pool = MySqlPool::connect(&database_url).await?;
+        let db_pool = MetadataPool::connect(&database_url).await?;
 
         let deleted = User::delete(3, &db_pool).await?;
         println!("deleted:{}", deleted);
@@ -1,3 +1,7 @@
 mod tokio_test;
+mod scheduler;
 mod workpool;
 mod compute_test;
+
+pub use self::scheduler::{JobError, JobHandle, Scheduler};
+pub use self::workpool::WorkPool;
@@ -0,0 +1,260 @@
+use crate::workpool::WorkPool;
+use crossbeam::deque::Injector;
+use futures::channel::oneshot;
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+///任务没有返回结果的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobError {
+    ///任务panic, 包含panic的信息, 不影响其他任务
+    Panicked(String),
+    ///调度器已经关闭, 任务没有执行
+    Shutdown,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Panicked(message) => write!(f, "job panicked: {}", message),
+            JobError::Shutdown => write!(f, "scheduler is shut down"),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+///任务的结果, 可以阻塞等待也可以在异步代码中await
+pub struct JobHandle<R> {
+    receiver: oneshot::Receiver<thread::Result<R>>,
+}
+
+impl<R> JobHandle<R> {
+    ///阻塞当前线程直到任务完成, 不要在异步代码中调用
+    pub fn join(self) -> Result<R, JobError> {
+        futures::executor::block_on(self)
+    }
+}
+
+impl<R> Future for JobHandle<R> {
+    type Output = Result<R, JobError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(Ok(value)) => Ok(value),
+                Ok(Err(payload)) => Err(JobError::Panicked(panic_message(payload))),
+                Err(oneshot::Canceled) => Err(JobError::Shutdown),
+            })
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic")
+    }
+}
+
+struct Shared {
+    ///已提交还没有被取走的任务数
+    pending: AtomicUsize,
+    shutdown: AtomicBool,
+    lock: Mutex<()>,
+    idle: Condvar,
+}
+
+///基于 `WorkPool` 的工作窃取调度器, 每个工作线程有自己的队列, 空闲时从全局队列和其他线程窃取任务.
+///
+///关闭时先执行完已提交的任务再退出
+pub struct Scheduler {
+    global: Arc<Injector<Job>>,
+    shared: Arc<Shared>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Scheduler {
+    pub fn new(threads: usize) -> Self {
+        let pool = WorkPool::<Job>::new();
+        let shared = Arc::new(Shared {
+            pending: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            idle: Condvar::new(),
+        });
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let pool = pool.clone();
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("lightingbi-worker-{}", i))
+                    .spawn(move || run_worker(pool, &shared))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        Scheduler {
+            global: pool.global(),
+            shared,
+            workers: Mutex::new(workers),
+        }
+    }
+
+    ///按cpu核数创建工作线程
+    pub fn with_available_parallelism() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(threads)
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.lock().expect("Poisoned workers").len()
+    }
+
+    ///提交闭包, panic只影响该任务
+    pub fn submit<F, R>(&self, f: F) -> JobHandle<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+            sender.send(result).ok();
+        });
+
+        // checked under the lock, so no job is queued after the workers exit
+        let _guard = self.shared.lock.lock().expect("Poisoned scheduler lock");
+        if !self.shared.shutdown.load(Ordering::SeqCst) {
+            self.shared.pending.fetch_add(1, Ordering::SeqCst);
+            self.global.push(job);
+            self.shared.idle.notify_one();
+        }
+        // otherwise the job and its sender are dropped, the handle returns `JobError::Shutdown`
+        JobHandle { receiver }
+    }
+
+    ///在工作线程上执行future直到完成, 适合不依赖tokio运行时的计算
+    pub fn spawn<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.submit(move || futures::executor::block_on(future))
+    }
+
+    ///不再接受新任务, 等待已提交的任务执行完成后工作线程退出
+    pub fn shutdown(&self) {
+        {
+            let _guard = self.shared.lock.lock().expect("Poisoned scheduler lock");
+            self.shared.shutdown.store(true, Ordering::SeqCst);
+            self.shared.idle.notify_all();
+        }
+        let workers: Vec<JoinHandle<()>> = self
+            .workers
+            .lock()
+            .expect("Poisoned workers")
+            .drain(..)
+            .collect();
+        for worker in workers {
+            worker.join().ok();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run_worker(pool: WorkPool<Job>, shared: &Shared) {
+    loop {
+        if let Some(job) = pool.get_work() {
+            shared.pending.fetch_sub(1, Ordering::SeqCst);
+            job();
+            continue;
+        }
+
+        let guard = shared.lock.lock().expect("Poisoned scheduler lock");
+        if shared.pending.load(Ordering::SeqCst) > 0 {
+            // the job is being moved between queues, look again
+            drop(guard);
+            thread::yield_now();
+        } else if shared.shutdown.load(Ordering::SeqCst) {
+            return;
+        } else {
+            drop(shared.idle.wait(guard).expect("Poisoned scheduler lock"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_submit() {
+        let scheduler = Scheduler::new(4);
+        assert_eq!(scheduler.threads(), 4);
+        let handles: Vec<JobHandle<u64>> = (0..100_u64)
+            .map(|i| scheduler.submit(move || i * i))
+            .collect();
+        let sum: u64 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(sum, (0..100_u64).map(|i| i * i).sum());
+    }
+
+    #[test]
+    fn test_panic() {
+        let scheduler = Scheduler::new(2);
+        let failed = scheduler.submit(|| -> u32 { panic!("bad formula") });
+        let ok = scheduler.submit(|| 1);
+        assert_eq!(
+            failed.join(),
+            Err(JobError::Panicked(String::from("bad formula")))
+        );
+        assert_eq!(ok.join(), Ok(1));
+        // the worker survives the panic
+        assert_eq!(scheduler.submit(|| 2).join(), Ok(2));
+    }
+
+    #[test]
+    fn test_spawn() {
+        let scheduler = Scheduler::new(2);
+        let inner = scheduler.submit(|| 20);
+        let handle = scheduler.spawn(async move { inner.await.unwrap() + 1 });
+        assert_eq!(futures::executor::block_on(handle), Ok(21));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let scheduler = Scheduler::new(2);
+        let handles: Vec<JobHandle<usize>> = (0..20)
+            .map(|i| {
+                scheduler.submit(move || {
+                    thread::sleep(Duration::from_millis(1));
+                    i
+                })
+            })
+            .collect();
+        scheduler.shutdown();
+        assert_eq!(scheduler.threads(), 0);
+        let done: Vec<usize> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(done, (0..20).collect::<Vec<_>>());
+        assert_eq!(scheduler.submit(|| 1).join(), Err(JobError::Shutdown));
+    }
+}
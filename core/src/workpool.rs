@@ -1,48 +1,57 @@
 use crossbeam::deque::{Injector, Steal, Stealer, Worker};
-use crossbeam::queue::{ArrayQueue, SegQueue};
-use crossbeam::scope;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
-use std::thread;
+
+///工作队列的编号, 删除队列时用于找到它的stealer
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+type Stealers<T> = Arc<RwLock<Vec<(usize, Stealer<T>)>>>;
 
 pub struct WorkPool<T> {
+    id: usize,
     global: Arc<Injector<T>>,
     local: Worker<T>,
-    stealers: Arc<RwLock<Vec<Stealer<T>>>>,
+    stealers: Stealers<T>,
 }
 
 impl<T> WorkPool<T> {
     pub fn new() -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
         let local = Worker::new_fifo();
-        let stealers = Arc::new(RwLock::new(vec![local.stealer()]));
+        let stealers = Arc::new(RwLock::new(vec![(id, local.stealer())]));
 
         Self {
+            id,
             local,
             stealers,
             global: Arc::new(Injector::new()),
         }
     }
 
+    ///依次从本地队列, 全局队列和其他工作队列取任务, 取到时批量移到本地队列
     pub fn get_work(&self) -> Option<T> {
         if let Some(work) = self.local.pop() {
             return Some(work);
         }
 
-        match self.global.steal_batch_and_pop(&self.local) {
-            Steal::Success(work) => return Some(work),
-            Steal::Retry => return self.get_work(),
-            Steal::Empty => {
-                let mut retry = false;
-                while retry {
-                    let g = self.stealers.read().expect("Poisoned work stealers");
-                    for s in g.iter() {
-                        match s.steal_batch_and_pop(&self.local) {
-                            Steal::Success(work) => return Some(work),
-                            Steal::Retry => retry = true,
-                            _ => {}
-                        }
-                    }
+        loop {
+            match self.global.steal_batch_and_pop(&self.local) {
+                Steal::Success(work) => return Some(work),
+                Steal::Retry => continue,
+                Steal::Empty => {}
+            }
+
+            let mut retry = false;
+            let stealers = self.stealers.read().expect("Poisoned work stealers");
+            for (_, stealer) in stealers.iter() {
+                match stealer.steal_batch_and_pop(&self.local) {
+                    Steal::Success(work) => return Some(work),
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
                 }
-                None
+            }
+            if !retry {
+                return None;
             }
         }
     }
@@ -56,14 +65,22 @@ impl<T> WorkPool<T> {
     }
 }
 
+impl<T> Default for WorkPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Clone for WorkPool<T> {
     fn clone(&self) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
         let local = Worker::new_fifo();
         self.stealers
             .write()
             .expect("Poisoned work stealers")
-            .push(local.stealer());
+            .push((id, local.stealer()));
         Self {
+            id,
             local,
             global: self.global.clone(),
             stealers: self.stealers.clone(),
@@ -71,82 +88,63 @@ impl<T> Clone for WorkPool<T> {
     }
 }
 
+///本地队列剩下的任务放回全局队列, 并且不再从该队列窃取
+impl<T> Drop for WorkPool<T> {
+    fn drop(&mut self) {
+        while let Some(work) = self.local.pop() {
+            self.global.push(work);
+        }
+        if let Ok(mut stealers) = self.stealers.write() {
+            stealers.retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-
     use super::*;
 
-    const THREADS: usize = 2;
-    const MESSAGES: usize = 50;
-
-    // #[test]
-    // fn it_works() {
-    //     let wp = WorkPool::<String>::new();
-    //     let mut wps = Vec::<&WorkPool<String>>::new();
-    //     scope(move |s| {
-    //         for i in 0..THREADS {
-    //             let wp = wp.clone();
-    //             wps.push(&wp);
-    //             s.spawn(move |_| {
-    //                 for y in 0..MESSAGES {
-    //                     wp.push_work(format!("push:{}-{}", thread::current().name().unwrap(), y));
-    //                 }
-    //             });
-    //         }
-    //
-    //         // for wb in wps.iter() {
-    //         //     for i in 0..THREADS {
-    //         //         s.spawn(move |_| {
-    //         //             for y in 0..MESSAGES {
-    //         //                 println!(
-    //         //                     "push:{}-{}",
-    //         //                     thread::current().name().unwrap(),
-    //         //                     wp.get_work().unwrap()
-    //         //                 );
-    //         //             }
-    //         //         });
-    //         //         // println!("{}", i);
-    //         //     }
-    //         // }
-    //     })
-    //     .unwrap();
-    // }
-
-    // #[test]
-    // fn mpsc() {
-    //     let wp = WorkPool::<String>::new();
-    //     let mut wps = Vec::<WorkPool<String>>::new();
-    //     let q = SegQueue::<&WorkPool<String>>::new();
-    //
-    //     scope(|scope| {
-    //         for _ in 0..THREADS {
-    //             let wp = wp.clone();
-    //             q.push(&wp);
-    //             scope.spawn(move |_| {
-    //                 for y in 0..MESSAGES {
-    //                     wp.push_work(format!("push:{}-{}", thread::current().name().unwrap(), y));
-    //                 }
-    //             });
-    //         }
-    //     })
-    //     .unwrap();
-    //
-    //     // thread::spawn(move || {
-    //     //     for i in 0..THREADS {
-    //     //         let w = wp.clone();
-    //     //         for y in 0..MESSAGES {
-    //     //             w.push_work(format!("push:{}-{}", thread::current().name().unwrap(), y));
-    //     //         }
-    //     //         wps.push(w);
-    //     //     }
-    //     // });
-    //
-    //     // scope(move |s| {
-    //
-    //     // for wb in wps.iter() {
-    //     //     for y in 0..MESSAGES {
-    //     //         println!("get:{}", wp.get_work().unwrap());
-    //     //     }
-    //     // }
-    // }
+    #[test]
+    fn test_get_work() {
+        let pool = WorkPool::new();
+        let peer = pool.clone();
+        for i in 0..10 {
+            pool.push_work(i);
+        }
+
+        // takes a batch from the global queue into its local queue
+        let first = pool.get_work().unwrap();
+        let mut works = vec![first];
+        while let Some(work) = peer.get_work() {
+            works.push(work);
+        }
+        // the rest of the batch is stolen from the local queue of the pool
+        assert!(pool.get_work().is_none());
+        works.sort_unstable();
+        assert_eq!(works, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop() {
+        let pool = WorkPool::new();
+        let peer = pool.clone();
+        for _ in 0..10 {
+            drop(pool.clone());
+        }
+        assert_eq!(pool.stealers.read().unwrap().len(), 2);
+
+        // the works left in the local queue go back to the global queue
+        for i in 0..10 {
+            pool.push_work(i);
+        }
+        assert_eq!(peer.get_work(), Some(0));
+        drop(peer);
+        assert_eq!(pool.stealers.read().unwrap().len(), 1);
+        let mut works = vec![];
+        while let Some(work) = pool.get_work() {
+            works.push(work);
+        }
+        works.sort_unstable();
+        assert_eq!(works, (1..10).collect::<Vec<_>>());
+    }
 }
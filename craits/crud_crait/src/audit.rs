@@ -0,0 +1,73 @@
+use std::fmt;
+use std::future::Future;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+///the columns of an entity which the repository maintains, see `#[derive(Entity)]`.
+///
+///- `created_at`, `updated_at` milliseconds since the unix epoch, as `i64`
+///- `created_by` the actor of the task which inserted the row, see `with_actor`
+///- `deleted` a `bool`, a deleted row is kept but excluded from queries
+///- `version` an integer which `update` checks and increments
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Audit {
+    pub created_at: Option<&'static str>,
+    pub updated_at: Option<&'static str>,
+    pub created_by: Option<&'static str>,
+    pub deleted: Option<&'static str>,
+    pub version: Option<&'static str>,
+}
+
+tokio::task_local! {
+    static ACTOR: String;
+}
+
+///run `future` on behalf of `actor`, the rows it inserts are `created_by` the actor
+pub async fn with_actor<F: Future>(actor: String, future: F) -> F::Output {
+    ACTOR.scope(actor, future).await
+}
+
+///the actor of the current task, none outside of `with_actor`
+pub fn actor() -> Option<String> {
+    ACTOR.try_with(|actor| actor.clone()).ok()
+}
+
+///milliseconds since the unix epoch
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+///an update or delete of a versioned entity which was changed or deleted by someone else
+///since it was read. it is returned inside `anyhow::Error`, find it with `downcast_ref`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleEntity {
+    pub table: String,
+    pub id: String,
+}
+
+impl fmt::Display for StaleEntity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} was changed by someone else, reload it and try again",
+            self.table, self.id
+        )
+    }
+}
+
+impl std::error::Error for StaleEntity {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_actor() {
+        assert_eq!(actor(), None);
+        let inner = with_actor(String::from("analyst"), async { actor() }).await;
+        assert_eq!(inner, Some(String::from("analyst")));
+        assert!(now() > 0);
+    }
+}
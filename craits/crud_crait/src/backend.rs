@@ -0,0 +1,402 @@
+use crate::entity::Repository;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::database::HasArguments;
+use sqlx::{Arguments, Database, MySql, Pool, Sqlite};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+#[cfg(all(feature = "metadata-postgres", feature = "metadata-sqlite"))]
+compile_error!("enable only one of the features metadata-postgres and metadata-sqlite");
+
+///the database of the metadata, mysql unless the feature `metadata-postgres`
+///or `metadata-sqlite` is enabled
+#[cfg(not(any(feature = "metadata-postgres", feature = "metadata-sqlite")))]
+pub type Metadata = MySql;
+#[cfg(feature = "metadata-postgres")]
+pub type Metadata = sqlx::Postgres;
+#[cfg(all(feature = "metadata-sqlite", not(feature = "metadata-postgres")))]
+pub type Metadata = Sqlite;
+
+pub type MetadataPool = Pool<Metadata>;
+
+pub type MetadataRepository = Repository<Metadata>;
+
+///a value bound to a placeholder. a null keeps the type of its column,
+///postgres does not compare or insert a null of another type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Param {
+    Bool(Option<bool>),
+    I32(Option<i32>),
+    I64(Option<i64>),
+    U64(Option<u64>),
+    F32(Option<f32>),
+    F64(Option<f64>),
+    Text(Option<String>),
+}
+
+macro_rules! param_from {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl From<$t> for Param {
+                fn from(value: $t) -> Self {
+                    Param::$variant(Some(value.into()))
+                }
+            }
+
+            impl From<Option<$t>> for Param {
+                fn from(value: Option<$t>) -> Self {
+                    Param::$variant(value.map(Into::into))
+                }
+            }
+        )*
+    };
+}
+
+param_from!(
+    bool => Bool,
+    i8 => I32,
+    i16 => I32,
+    i32 => I32,
+    u8 => I32,
+    u16 => I32,
+    u32 => I64,
+    i64 => I64,
+    u64 => U64,
+    f32 => F32,
+    f64 => F64,
+    String => Text,
+    &str => Text
+);
+
+impl From<&String> for Param {
+    fn from(value: &String) -> Self {
+        Param::Text(Some(value.clone()))
+    }
+}
+
+///the values of criteria, arrays and objects are bound as json text
+impl From<&Value> for Param {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Null => Param::Text(None),
+            Value::Bool(b) => Param::Bool(Some(*b)),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => Param::I64(Some(i)),
+                (None, Some(u)) => Param::U64(Some(u)),
+                _ => Param::F64(n.as_f64()),
+            },
+            Value::String(s) => Param::Text(Some(s.clone())),
+            value => Param::Text(Some(value.to_string())),
+        }
+    }
+}
+
+impl Param {
+    ///postgres and sqlite have no unsigned 64 bit integer
+    fn signed(value: Option<u64>) -> Result<Option<i64>> {
+        value
+            .map(|v| i64::try_from(v).map_err(|_| anyhow!("{} is out of the range of i64", v)))
+            .transpose()
+    }
+}
+
+///the sql dialect and the binds of a database which stores the metadata.
+///the repository renders the sql of mysql: `?` placeholders and identifiers in backticks,
+///a backend rewrites it with `translate` when its syntax differs
+#[async_trait]
+pub trait Backend: Database {
+    ///the name of the migrations directory of the backend
+    const NAME: &'static str;
+
+    ///the placeholders a statement binds at most
+    const MAX_PLACEHOLDERS: usize;
+
+    fn translate(sql: &str) -> Cow<'_, str> {
+        Cow::Borrowed(sql)
+    }
+
+    ///the end of an insert into `table` which updates `columns` of the row when `id_name`
+    ///exists, and increments `version`. the version of the row is qualified by the table,
+    ///postgres can not tell it from the one of `excluded` otherwise
+    fn upsert(table: &str, id_name: &str, columns: &[&str], version: Option<&str>) -> String {
+        if columns.is_empty() && version.is_none() {
+            return format!(" on conflict (`{}`) do nothing", id_name);
+        }
+        let assignments: Vec<String> = columns
+            .iter()
+            .map(|column| format!("`{}` = excluded.`{}`", column, column))
+            .chain(version.map(|v| format!("`{0}` = {1}.`{0}` + 1", v, table)))
+            .collect();
+        format!(
+            " on conflict (`{}`) do update set {}",
+            id_name,
+            assignments.join(",")
+        )
+    }
+
+    ///the end of an insert which returns the generated id, none when the id is read
+    ///from the result by `last_insert_id`
+    fn returning(_id_name: &str) -> Option<String> {
+        None
+    }
+
+    fn last_insert_id(result: &Self::QueryResult) -> Option<i64>;
+
+    fn rows_affected(result: &Self::QueryResult) -> u64;
+
+    fn bind<'q>(args: &mut <Self as HasArguments<'q>>::Arguments, param: Param) -> Result<()>;
+
+    ///counts the tables named by the placeholder in the current schema
+    fn table_exists() -> &'static str;
+
+    ///the end of a select which locks the rows read until the transaction ends
+    fn for_update() -> &'static str {
+        " for update"
+    }
+
+    ///wait for the lock named `name`, which is held until `unlock` or the connection closes
+    async fn lock(_conn: &mut Self::Connection, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn unlock(_conn: &mut Self::Connection, _name: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+///seconds to wait for a lock
+const LOCK_TIMEOUT: i32 = 60;
+
+#[async_trait]
+impl Backend for MySql {
+    const NAME: &'static str = "mysql";
+
+    const MAX_PLACEHOLDERS: usize = 65535;
+
+    fn upsert(_table: &str, id_name: &str, columns: &[&str], version: Option<&str>) -> String {
+        let columns = if columns.is_empty() && version.is_none() {
+            vec![id_name]
+        } else {
+            columns.to_vec()
+        };
+        let assignments: Vec<String> = columns
+            .iter()
+            .map(|column| format!("`{}` = values(`{}`)", column, column))
+            .chain(version.map(|v| format!("`{0}` = `{0}` + 1", v)))
+            .collect();
+        format!(" on duplicate key update {}", assignments.join(","))
+    }
+
+    fn last_insert_id(result: &Self::QueryResult) -> Option<i64> {
+        i64::try_from(result.last_insert_id()).ok()
+    }
+
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+
+    fn bind<'q>(args: &mut <Self as HasArguments<'q>>::Arguments, param: Param) -> Result<()> {
+        match param {
+            Param::Bool(v) => args.add(v),
+            Param::I32(v) => args.add(v),
+            Param::I64(v) => args.add(v),
+            Param::U64(v) => args.add(v),
+            Param::F32(v) => args.add(v),
+            Param::F64(v) => args.add(v),
+            Param::Text(v) => args.add(v),
+        }
+        Ok(())
+    }
+
+    fn table_exists() -> &'static str {
+        "select count(*) from information_schema.tables \
+         where table_schema = database() and table_name = ?"
+    }
+
+    async fn lock(conn: &mut Self::Connection, name: &str) -> Result<()> {
+        let locked: Option<i64> = sqlx::query_scalar("select get_lock(?, ?)")
+            .bind(name)
+            .bind(LOCK_TIMEOUT)
+            .fetch_one(conn)
+            .await?;
+        if locked != Some(1) {
+            return Err(anyhow!("timeout waiting for the lock {}", name));
+        }
+        Ok(())
+    }
+
+    async fn unlock(conn: &mut Self::Connection, name: &str) -> Result<()> {
+        sqlx::query("select release_lock(?)")
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+///sqlite accepts the mysql sql of the repository. a database file is locked while
+///it is written, so the lock of migrations is not needed
+#[async_trait]
+impl Backend for Sqlite {
+    const NAME: &'static str = "sqlite";
+
+    const MAX_PLACEHOLDERS: usize = 32766;
+
+    fn last_insert_id(result: &Self::QueryResult) -> Option<i64> {
+        Some(result.last_insert_rowid())
+    }
+
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+
+    fn bind<'q>(args: &mut <Self as HasArguments<'q>>::Arguments, param: Param) -> Result<()> {
+        match param {
+            Param::Bool(v) => args.add(v),
+            Param::I32(v) => args.add(v),
+            Param::I64(v) => args.add(v),
+            Param::U64(v) => args.add(Param::signed(v)?),
+            Param::F32(v) => args.add(v),
+            Param::F64(v) => args.add(v),
+            Param::Text(v) => args.add(v),
+        }
+        Ok(())
+    }
+
+    fn table_exists() -> &'static str {
+        "select count(*) from sqlite_master where type = 'table' and name = ?"
+    }
+
+    ///sqlite has no row locks, a transaction locks the database when it writes
+    fn for_update() -> &'static str {
+        ""
+    }
+}
+
+///postgres numbers the placeholders and quotes identifiers with double quotes
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl Backend for sqlx::Postgres {
+    const NAME: &'static str = "postgres";
+
+    const MAX_PLACEHOLDERS: usize = 65535;
+
+    fn translate(sql: &str) -> Cow<'_, str> {
+        let mut translated = String::with_capacity(sql.len() + 16);
+        let mut index = 0;
+        for c in sql.chars() {
+            match c {
+                '?' => {
+                    index += 1;
+                    translated.push_str(&format!("${}", index));
+                }
+                '`' => translated.push('"'),
+                c => translated.push(c),
+            }
+        }
+        Cow::Owned(translated)
+    }
+
+    fn returning(id_name: &str) -> Option<String> {
+        Some(format!(" returning `{}`", id_name))
+    }
+
+    fn last_insert_id(_result: &Self::QueryResult) -> Option<i64> {
+        None
+    }
+
+    fn rows_affected(result: &Self::QueryResult) -> u64 {
+        result.rows_affected()
+    }
+
+    fn bind<'q>(args: &mut <Self as HasArguments<'q>>::Arguments, param: Param) -> Result<()> {
+        match param {
+            Param::Bool(v) => args.add(v),
+            Param::I32(v) => args.add(v),
+            Param::I64(v) => args.add(v),
+            Param::U64(v) => args.add(Param::signed(v)?),
+            Param::F32(v) => args.add(v),
+            Param::F64(v) => args.add(v),
+            Param::Text(v) => args.add(v),
+        }
+        Ok(())
+    }
+
+    fn table_exists() -> &'static str {
+        "select count(*) from information_schema.tables \
+         where table_schema = current_schema() and table_name = ?"
+    }
+
+    async fn lock(conn: &mut Self::Connection, name: &str) -> Result<()> {
+        sqlx::query("select pg_advisory_lock(hashtext($1))")
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn unlock(conn: &mut Self::Connection, name: &str) -> Result<()> {
+        sqlx::query("select pg_advisory_unlock(hashtext($1))")
+            .bind(name)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_param() {
+        assert_eq!(Param::from(Some(3)), Param::I32(Some(3)));
+        assert_eq!(Param::from(None::<String>), Param::Text(None));
+        assert_eq!(Param::from("a"), Param::Text(Some("a".to_string())));
+        assert_eq!(Param::from(&json!(-1)), Param::I64(Some(-1)));
+        assert_eq!(Param::from(&json!(u64::MAX)), Param::U64(Some(u64::MAX)));
+        assert_eq!(Param::from(&json!(1.5)), Param::F64(Some(1.5)));
+        assert!(Param::signed(Some(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn test_upsert() {
+        assert_eq!(
+            MySql::upsert("t_good", "id", &["name"], None),
+            " on duplicate key update `name` = values(`name`)"
+        );
+        assert_eq!(
+            MySql::upsert("t_good", "id", &[], None),
+            " on duplicate key update `id` = values(`id`)"
+        );
+        assert_eq!(
+            Sqlite::upsert("t_good", "id", &["name", "count"], None),
+            " on conflict (`id`) do update set `name` = excluded.`name`,`count` = excluded.`count`"
+        );
+        assert_eq!(
+            Sqlite::upsert("t_good", "id", &[], Some("version")),
+            " on conflict (`id`) do update set `version` = t_good.`version` + 1"
+        );
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_postgres() {
+        use sqlx::Postgres;
+
+        let upsert = Postgres::upsert("t_good", "id", &["name"], Some("version"));
+        let sql = format!("insert into t_good (`id`,`name`) values (?,?){}", upsert);
+        assert_eq!(
+            Postgres::translate(&sql),
+            "insert into t_good (\"id\",\"name\") values ($1,$2) on conflict (\"id\") \
+             do update set \"name\" = excluded.\"name\",\"version\" = t_good.\"version\" + 1"
+        );
+        assert_eq!(
+            Postgres::returning("id").as_deref(),
+            Some(" returning `id`")
+        );
+    }
+}
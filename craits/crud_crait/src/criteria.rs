@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+///comparison operator of a condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    In,
+    IsNull,
+    IsNotNull,
+    Between,
+}
+
+///where conditions of a query, the values are bound as parameters.
+///
+/// ```ignore
+/// // name like '%sales%' and (engine_type = 'clickhouse' or count between 10 and 20)
+/// let criteria = Criteria::all().contains("name", "sales").group(
+///     Criteria::any()
+///         .eq("engine_type", "clickhouse")
+///         .between("count", 10, 20),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Criteria {
+    Condition {
+        column: String,
+        operator: Operator,
+        values: Vec<Value>,
+    },
+    ///all of the criteria match, an empty group matches every row
+    And(Vec<Criteria>),
+    ///any of the criteria matches, an empty group is ignored
+    Or(Vec<Criteria>),
+}
+
+impl Default for Criteria {
+    fn default() -> Self {
+        Criteria::all()
+    }
+}
+
+///the params joined by AND, as the repository accepted them before
+impl From<&BTreeMap<String, String>> for Criteria {
+    fn from(params: &BTreeMap<String, String>) -> Self {
+        params.iter().fold(Criteria::all(), |criteria, (k, v)| {
+            criteria.eq(k, v.as_str())
+        })
+    }
+}
+
+impl Criteria {
+    pub fn all() -> Self {
+        Criteria::And(vec![])
+    }
+
+    pub fn any() -> Self {
+        Criteria::Or(vec![])
+    }
+
+    pub fn condition(column: &str, operator: Operator, values: Vec<Value>) -> Self {
+        Criteria::Condition {
+            column: column.to_string(),
+            operator,
+            values,
+        }
+    }
+
+    ///add to the group, a single condition becomes an AND group
+    pub fn group(self, criteria: Criteria) -> Self {
+        match self {
+            Criteria::And(mut group) => {
+                group.push(criteria);
+                Criteria::And(group)
+            }
+            Criteria::Or(mut group) => {
+                group.push(criteria);
+                Criteria::Or(group)
+            }
+            condition => Criteria::And(vec![condition, criteria]),
+        }
+    }
+
+    fn compare<V: Into<Value>>(self, column: &str, operator: Operator, value: V) -> Self {
+        self.group(Criteria::condition(column, operator, vec![value.into()]))
+    }
+
+    pub fn eq<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Eq, value)
+    }
+
+    pub fn ne<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Ne, value)
+    }
+
+    pub fn lt<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Lt, value)
+    }
+
+    pub fn le<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Le, value)
+    }
+
+    pub fn gt<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Gt, value)
+    }
+
+    pub fn ge<V: Into<Value>>(self, column: &str, value: V) -> Self {
+        self.compare(column, Operator::Ge, value)
+    }
+
+    ///`pattern` is used as is, `%` and `_` are wildcards and `!` escapes them.
+    ///`!` is the escape character of every backend, sqlite has no default one
+    pub fn like(self, column: &str, pattern: &str) -> Self {
+        self.compare(column, Operator::Like, pattern)
+    }
+
+    ///the column contains `text`, wildcards in it are matched literally
+    pub fn contains(self, column: &str, text: &str) -> Self {
+        let escaped = text
+            .replace('!', "!!")
+            .replace('%', "!%")
+            .replace('_', "!_");
+        self.like(column, &format!("%{}%", escaped))
+    }
+
+    ///an empty list matches no row
+    pub fn is_in<V: Into<Value>>(self, column: &str, values: Vec<V>) -> Self {
+        let values = values.into_iter().map(Into::into).collect();
+        self.group(Criteria::condition(column, Operator::In, values))
+    }
+
+    pub fn is_null(self, column: &str) -> Self {
+        self.group(Criteria::condition(column, Operator::IsNull, vec![]))
+    }
+
+    pub fn is_not_null(self, column: &str) -> Self {
+        self.group(Criteria::condition(column, Operator::IsNotNull, vec![]))
+    }
+
+    pub fn between<V: Into<Value>>(self, column: &str, from: V, to: V) -> Self {
+        let values = vec![from.into(), to.into()];
+        self.group(Criteria::condition(column, Operator::Between, values))
+    }
+
+    ///whether a condition is on the column
+    pub fn refers_to(&self, column: &str) -> bool {
+        match self {
+            Criteria::Condition { column: c, .. } => c == column,
+            Criteria::And(group) | Criteria::Or(group) => group.iter().any(|c| c.refers_to(column)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Criteria::Condition { .. } => false,
+            Criteria::And(group) | Criteria::Or(group) => group.iter().all(Criteria::is_empty),
+        }
+    }
+
+    ///render the where clause without the `where` keyword, empty when nothing is filtered.
+    ///the columns are checked to be identifiers and, when `columns` is not empty, known ones
+    pub fn to_sql(&self, columns: &[&str]) -> Result<(String, Vec<&Value>)> {
+        let mut values = vec![];
+        let sql = self.render(columns, &mut values)?;
+        Ok((sql, values))
+    }
+
+    fn render<'a>(&'a self, columns: &[&str], params: &mut Vec<&'a Value>) -> Result<String> {
+        let (group, separator) = match self {
+            Criteria::Condition {
+                column,
+                operator,
+                values,
+            } => return render_condition(column, *operator, values, columns, params),
+            Criteria::And(group) => (group, " and "),
+            Criteria::Or(group) => (group, " or "),
+        };
+        let mut parts = vec![];
+        for criteria in group.iter().filter(|c| !c.is_empty()) {
+            let sql = criteria.render(columns, params)?;
+            parts.push(match criteria {
+                Criteria::Condition { .. } => sql,
+                _ => format!("({})", sql),
+            });
+        }
+        Ok(parts.join(separator))
+    }
+}
+
+fn render_condition<'a>(
+    column: &str,
+    operator: Operator,
+    values: &'a [Value],
+    columns: &[&str],
+    params: &mut Vec<&'a Value>,
+) -> Result<String> {
+    let column = quote_column(column, columns)?;
+    let arity = match operator {
+        Operator::IsNull | Operator::IsNotNull => Some(0),
+        Operator::Between => Some(2),
+        Operator::In => None,
+        _ => Some(1),
+    };
+    if matches!(arity, Some(n) if n != values.len()) {
+        return Err(anyhow!(
+            "{:?} on {} takes {} values, got {}",
+            operator,
+            column,
+            arity.unwrap_or_default(),
+            values.len()
+        ));
+    }
+    if let Some(value) = values
+        .iter()
+        .find(|v| !(v.is_string() || v.is_number() || v.is_boolean()))
+    {
+        return Err(anyhow!("{} can not be compared with {}", column, value));
+    }
+    params.extend(values);
+
+    let sql = match operator {
+        Operator::Eq => format!("{} = ?", column),
+        Operator::Ne => format!("{} <> ?", column),
+        Operator::Lt => format!("{} < ?", column),
+        Operator::Le => format!("{} <= ?", column),
+        Operator::Gt => format!("{} > ?", column),
+        Operator::Ge => format!("{} >= ?", column),
+        Operator::Like => format!("{} like ? escape '!'", column),
+        Operator::In if values.is_empty() => String::from("1 = 0"),
+        Operator::In => format!("{} in ({})", column, vec!["?"; values.len()].join(",")),
+        Operator::IsNull => format!("{} is null", column),
+        Operator::IsNotNull => format!("{} is not null", column),
+        Operator::Between => format!("{} between ? and ?", column),
+    };
+    Ok(sql)
+}
+
+///check the column and quote it with backticks
+pub fn quote_column(column: &str, columns: &[&str]) -> Result<String> {
+    let valid = matches!(column.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && column
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(anyhow!("{} is not a valid column", column));
+    }
+    if !columns.is_empty() && !columns.contains(&column) {
+        return Err(anyhow!("unknown column {}", column));
+    }
+    Ok(format!("`{}`", column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_sql() -> Result<()> {
+        let criteria = Criteria::all()
+            .contains("name", "50%_off")
+            .group(
+                Criteria::any()
+                    .eq("engine_type", "clickhouse")
+                    .between("count", 10, 20)
+                    .is_null("engine_name"),
+            )
+            .is_in("id", vec!["a", "b"])
+            .ne("size", 1.5);
+        let (sql, values) = criteria.to_sql(&[])?;
+        assert_eq!(
+            sql,
+            "`name` like ? escape '!' and (`engine_type` = ? or `count` between ? and ? or `engine_name` is null) \
+             and `id` in (?,?) and `size` <> ?"
+        );
+        assert_eq!(
+            values,
+            vec![
+                &json!("%50!%!_off%"),
+                &json!("clickhouse"),
+                &json!(10),
+                &json!(20),
+                &json!("a"),
+                &json!("b"),
+                &json!(1.5)
+            ]
+        );
+
+        assert!(criteria.refers_to("engine_name"));
+        assert!(!criteria.refers_to("deleted"));
+
+        assert_eq!(Criteria::all().to_sql(&[])?.0, "");
+        assert_eq!(
+            Criteria::all()
+                .group(Criteria::any())
+                .is_in::<i32>("id", vec![])
+                .to_sql(&[])?
+                .0,
+            "1 = 0"
+        );
+
+        let mut params = BTreeMap::new();
+        params.insert(String::from("dataset_id"), String::from("d1"));
+        params.insert(String::from("name"), String::from("amount"));
+        assert_eq!(
+            Criteria::from(&params).to_sql(&[])?.0,
+            "`dataset_id` = ? and `name` = ?"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() {
+        let columns = ["id", "name"];
+        assert!(Criteria::all().eq("name", "a").to_sql(&columns).is_ok());
+        assert!(Criteria::all().eq("size", 1).to_sql(&columns).is_err());
+        assert!(Criteria::all().eq("id = id or 1", 1).to_sql(&[]).is_err());
+        assert!(Criteria::all().eq("id", Value::Null).to_sql(&[]).is_err());
+        assert!(Criteria::all()
+            .group(Criteria::condition("id", Operator::Between, vec![json!(1)]))
+            .to_sql(&[])
+            .is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::criteria::{Criteria, Operator};
+use crate::entity::{Direction, Sort};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+///rows after a cursor, each row with the cursor pointing at it
+#[derive(Debug)]
+pub struct CursorPage<T> {
+    pub edges: Vec<(String, T)>,
+    pub has_next_page: bool,
+}
+
+impl<T> CursorPage<T> {
+    pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> CursorPage<U> {
+        CursorPage {
+            edges: self
+                .edges
+                .into_iter()
+                .map(|(cursor, node)| (cursor, f(node)))
+                .collect(),
+            has_next_page: self.has_next_page,
+        }
+    }
+}
+
+///the sort keys followed by the id, so that every row has a distinct position
+pub fn keys(sort: &[Sort], id_name: &str) -> Vec<Sort> {
+    let mut keys = sort.to_vec();
+    if !keys.iter().any(|key| key.field == id_name) {
+        keys.push(Sort::asc(id_name));
+    }
+    keys
+}
+
+///encode the values of the sort keys of a row, clients should treat the cursor as opaque
+pub fn encode(values: &[Value]) -> String {
+    Value::Array(values.to_vec())
+        .to_string()
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn decode(cursor: &str) -> Result<Vec<Value>> {
+    let invalid = || anyhow!("invalid cursor {}", cursor);
+    let bytes = cursor
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    match serde_json::from_slice(&bytes).map_err(|_| invalid())? {
+        Value::Array(values) => Ok(values),
+        _ => Err(invalid()),
+    }
+}
+
+///the rows after the position of `values` in the order of `keys`:
+///`k1 > v1 or (k1 = v1 and k2 > v2) or ...`, with `<` for descending keys.
+///the keys should not be nullable, null values can not be compared
+pub fn after(keys: &[Sort], values: &[Value]) -> Result<Criteria> {
+    if keys.len() != values.len() {
+        return Err(anyhow!("the cursor does not match the sort keys"));
+    }
+    let mut criteria = Criteria::any();
+    for (i, key) in keys.iter().enumerate() {
+        let mut position = Criteria::all();
+        for (previous, value) in keys.iter().zip(values).take(i) {
+            position = position.eq(&previous.field, value.clone());
+        }
+        let operator = match key.direction {
+            Direction::Asc => Operator::Gt,
+            Direction::Desc => Operator::Lt,
+        };
+        position = position.group(Criteria::condition(
+            &key.field,
+            operator,
+            vec![values[i].clone()],
+        ));
+        criteria = criteria.group(position);
+    }
+    Ok(criteria)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_encode() -> Result<()> {
+        let values = vec![json!("销售"), json!(12.5), json!(3)];
+        let cursor = encode(&values);
+        assert!(cursor.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(decode(&cursor)?, values);
+        assert!(decode("7b7d").is_err());
+        assert!(decode("7g").is_err());
+        assert!(decode("5b5").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_after() -> Result<()> {
+        let keys = keys(&[Sort::desc("count"), Sort::asc("name")], "id");
+        assert_eq!(keys.last(), Some(&Sort::asc("id")));
+
+        let criteria = after(&keys, &[json!(10), json!("sales"), json!("d1")])?;
+        let (sql, values) = criteria.to_sql(&[])?;
+        assert_eq!(
+            sql,
+            "(`count` < ?) or (`count` = ? and `name` > ?) \
+             or (`count` = ? and `name` = ? and `id` > ?)"
+        );
+        assert_eq!(values.len(), 6);
+        assert!(after(&keys, &[json!(10)]).is_err());
+        Ok(())
+    }
+}
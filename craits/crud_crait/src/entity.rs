@@ -1,22 +1,60 @@
+use crate::audit::{self, Audit, StaleEntity};
+use crate::backend::{Backend, Param};
+use crate::criteria::{quote_column, Criteria};
+use crate::cursor::{self, CursorPage};
 use anyhow::{anyhow, Result};
-use async_graphql::{InputObject, OutputType, SimpleObject};
-use async_trait::async_trait;
+use async_graphql::{Enum, InputObject, OutputType, SimpleObject};
+pub use crud_derive::Entity;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
-use sqlx::mysql::{MySqlArguments, MySqlRow};
-use sqlx::Arguments;
-use sqlx::{FromRow, MySqlPool, Row};
-use std::collections::BTreeMap;
+use sqlx::database::HasArguments;
+use sqlx::IntoArguments;
+use sqlx::{Acquire, Executor, FromRow, MySql, Sqlite};
 use std::env;
 use std::fmt::Debug;
+use std::marker::PhantomData;
+
+///sort direction
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+///sort key, the field must be a column of the entity
+#[derive(InputObject, Clone, Debug, PartialEq)]
+pub struct Sort {
+    pub field: String,
+    #[graphql(default)]
+    pub direction: Direction,
+}
+
+impl Sort {
+    pub fn asc(field: &str) -> Self {
+        Sort {
+            field: field.to_string(),
+            direction: Direction::Asc,
+        }
+    }
+
+    pub fn desc(field: &str) -> Self {
+        Sort {
+            field: field.to_string(),
+            direction: Direction::Desc,
+        }
+    }
+}
 
 ///page request
 #[derive(InputObject)]
 pub struct PageRequest {
     pub size: i64,
     pub num: i64,
-    pub sort: String,
+    ///sort keys in order of priority
+    #[graphql(default)]
+    pub sort: Vec<Sort>,
 }
 
 impl Default for PageRequest {
@@ -24,11 +62,31 @@ impl Default for PageRequest {
         Self {
             size: 10,
             num: 0,
-            sort: "".to_string(),
+            sort: vec![],
         }
     }
 }
 
+///render the sort keys as an order by clause, empty when there is no sort key
+pub fn order_by(sort: &[Sort], columns: &[&str]) -> Result<String> {
+    let mut keys = vec![];
+    for key in sort {
+        let direction = match key.direction {
+            Direction::Asc => "asc",
+            Direction::Desc => "desc",
+        };
+        keys.push(format!(
+            "{} {}",
+            quote_column(&key.field, columns)?,
+            direction
+        ));
+    }
+    if keys.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(format!(" order by {}", keys.join(",")))
+}
+
 ///page response
 #[derive(Debug, SimpleObject)]
 pub struct Page<T: OutputType> {
@@ -68,139 +126,204 @@ impl<T: OutputType> Default for Page<T> {
     }
 }
 
-pub async fn to_snake_name(name: &str) -> Result<String> {
-    let chs = name.chars();
-    let mut new_name = String::new();
-    let mut index = 0;
-    let chs_len = name.len();
-    for x in chs {
-        if x.is_uppercase() {
-            if index != 0 && (index + 1) != chs_len {
-                new_name.push_str("_");
-            }
-            new_name.push_str(x.to_lowercase().to_string().as_str());
-        } else {
-            new_name.push(x);
-        }
-        index += 1;
-    }
-    Ok(new_name)
+///the prefix of the metadata tables, `TABLE_NAMESPACE` or `t_lighting` by default
+pub fn namespace() -> String {
+    env::var("TABLE_NAMESPACE").unwrap_or_else(|_| "t_lighting".to_string())
+}
+
+///the table name with the `TABLE_NAMESPACE` prefix
+pub fn namespaced(name: &str) -> String {
+    format!("{}_{}", namespace(), name)
 }
 
-#[async_trait]
+///the table of an entity and the values of its columns, implemented by `#[derive(Entity)]`
 pub trait Entity: Send + Sync + Serialize + DeserializeOwned + Debug + OutputType {
-    async fn table_name() -> Result<String> {
-        let type_name = std::any::type_name::<Self>();
-        let mut name = type_name.to_string();
-        let names: Vec<&str> = name.split("::").collect();
-        name = names.get(names.len() - 1).unwrap_or(&"").to_string();
-        let pre = env::var("TABLE_NAMESPACE").unwrap_or("t_lighting".to_string());
-        name = to_snake_name(&name).await?;
-        Ok(pre + "_" + name.as_str())
-    }
-
-    async fn id_name() -> Result<String> {
-        Ok("id".to_string())
-    }
-
-    async fn columns() -> Result<Vec<String>> {
-        let json = serde_json::json!("{}");
-        let object = json.as_object().unwrap();
-        let mut columns = vec![];
-        for (k, v) in object {
-            columns.push(k.clone())
+    fn table_name() -> String;
+
+    fn id_name() -> &'static str;
+
+    ///the columns which are read, criteria and sort keys on other columns are rejected
+    fn columns() -> &'static [&'static str];
+
+    ///the columns written by insert, in the order of `insert_params`
+    fn insert_columns() -> &'static [&'static str];
+
+    ///the columns written by update, in the order of `update_params`, the id is not updated
+    fn update_columns() -> &'static [&'static str];
+
+    fn insert_params(&self) -> Vec<Param>;
+
+    fn update_params(&self) -> Vec<Param>;
+
+    fn id_param(&self) -> Param;
+
+    ///the columns maintained by the repository, none by default
+    fn audit() -> Audit {
+        Audit::default()
+    }
+
+    ///the value of a column as json, used for the cursor of keyset pagination
+    fn column_value(&self, column: &str) -> Option<Value>;
+}
+
+///a statement in the sql of mysql with the values of its placeholders,
+///it is translated for the backend when it is executed
+#[derive(Debug, Clone)]
+pub(crate) struct Statement {
+    pub sql: String,
+    pub params: Vec<Param>,
+    ///the entity of a versioned update, which is stale when no row is affected
+    pub checked: Option<StaleEntity>,
+}
+
+impl Statement {
+    fn new(sql: String, params: Vec<Param>) -> Self {
+        Statement {
+            sql,
+            params,
+            checked: None,
         }
-        Ok(columns)
     }
 }
 
-pub struct MySqlRepository {}
-
-impl MySqlRepository {
-    // async fn find_by_id_1<'e, 'c: 'e, T, E>(id : &String, executor: &E) -> Result<Option<T>>
-    //     where
-    //         T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
-    //         E: 'e + Executor<'c, Database = MySql>,
-    // {
-    //     if id.is_empty(){
-    //         return Ok(None);
-    //     }
-    //     let table_name = T::table_name().await?;
-    //     let id_name = T::id_name().await?;
-    //     // let columns : Vec<String> = T::columns().await?;
-    //     let sql = format!("select * from {} where {} = ? " , table_name , id_name);
-    //
-    //     let entity : Option<T> = sqlx::query_as::<_ , T>(sql.as_str()).bind(&id).fetch_optional(executor).await?;
-    //
-    //     Ok(entity)
-    // }
+///rows of an insert or ids of a delete in one statement, larger batches are split
+pub const BATCH_SIZE: usize = 1000;
+
+fn chunk_size<DB: Backend>(placeholders_per_row: usize) -> usize {
+    BATCH_SIZE
+        .min(DB::MAX_PLACEHOLDERS / placeholders_per_row.max(1))
+        .max(1)
+}
+
+///the functions run on any executor of the backend: a pool, a connection or a transaction,
+///e.g. `MySqlRepository::add(&entity, &mut tx)`, see `UnitOfWork` to write several entities
+///at once. `MetadataRepository` is the repository of the configured metadata database
+pub struct Repository<DB: Backend> {
+    backend: PhantomData<DB>,
+}
+
+pub type MySqlRepository = Repository<MySql>;
+
+pub type SqliteRepository = Repository<Sqlite>;
+
+#[cfg(feature = "postgres")]
+pub type PgRepository = Repository<sqlx::Postgres>;
+
+impl<DB: Backend> Repository<DB>
+where
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    pub async fn find_by_id<'c, T>(
+        id: &String,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<Option<T>>
+    where
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+    {
+        Self::find_one(id, "", executor).await
+    }
+
+    ///find the entity and lock its row until the transaction of `executor` ends,
+    ///so that it is not changed by others in between
+    pub async fn lock_by_id<'c, T>(
+        id: &str,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<Option<T>>
+    where
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+    {
+        Self::find_one(id, DB::for_update(), executor).await
+    }
 
-    pub async fn find_by_id<T>(id: &String, pool: &MySqlPool) -> Result<Option<T>>
+    async fn find_one<'c, T>(
+        id: &str,
+        suffix: &str,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<Option<T>>
     where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
     {
         if id.is_empty() {
             return Ok(None);
         }
-        let table_name = T::table_name().await?;
-        let id_name = T::id_name().await?;
-        // let columns : Vec<String> = T::columns().await?;
-        let sql = format!("select * from {} where {} = ? ", table_name, id_name);
-
-        let entity: Option<T> = sqlx::query_as::<_, T>(sql.as_str())
-            .bind(&id)
-            .fetch_optional(pool)
+        let criteria = Self::visible::<T>(&Criteria::all().eq(T::id_name(), id));
+        let sql = format!("select * from {}", T::table_name());
+        let (mut sql, params) = Self::add_criteria_to_sql(sql, &criteria, T::columns())?;
+        sql.push_str(suffix);
+        let sql = DB::translate(&sql);
+        let arg = Self::arguments(params)?;
+
+        let entity: Option<T> = sqlx::query_as_with::<DB, T, _>(&sql, arg)
+            .fetch_optional(executor)
             .await?;
 
         Ok(entity)
     }
 
-    pub async fn query<T>(params: &BTreeMap<String, String>, pool: &MySqlPool) -> Result<Vec<T>>
+    pub async fn query<'c, T>(
+        criteria: &Criteria,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<Vec<T>>
     where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
     {
-        let table_name = T::table_name().await?;
+        let table_name = T::table_name();
         let sql = format!("select * from {} ", table_name);
-        let (sql_with_param, mut param_values) = Self::add_params_to_sql(sql, params).await?;
-        let arg = Self::covert_param_values_to_arg(param_values).await?;
-        let mut result = sqlx::query_as_with::<_, T, MySqlArguments>(&sql_with_param, arg)
-            .fetch_all(pool)
+        let criteria = Self::visible::<T>(criteria);
+        let (sql_with_param, params) = Self::add_criteria_to_sql(sql, &criteria, T::columns())?;
+        let sql_with_param = DB::translate(&sql_with_param);
+        let arg = Self::arguments(params)?;
+        let result = sqlx::query_as_with::<DB, T, _>(&sql_with_param, arg)
+            .fetch_all(executor)
             .await?;
         Ok(result)
     }
 
-    pub async fn query_page<T>(
-        params: &BTreeMap<String, String>,
+    ///the count and the page are read on the same connection
+    pub async fn query_page<'c, T>(
+        criteria: &Criteria,
         page_request: &PageRequest,
-        pool: &MySqlPool,
+        db: impl Acquire<'c, Database = DB>,
     ) -> Result<Page<T>>
     where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
     {
-        let table_name = T::table_name().await?;
+        if page_request.size <= 0 || page_request.num < 0 {
+            return Err(anyhow!(
+                "page size should be positive and page number not negative, got {} and {}",
+                page_request.size,
+                page_request.num
+            ));
+        }
+        let start = page_request
+            .num
+            .checked_mul(page_request.size)
+            .ok_or_else(|| anyhow!("page {} is out of range", page_request.num))?;
+
+        let mut conn = db.acquire().await?;
+        let table_name = T::table_name();
+        let columns = T::columns();
+        let criteria = &Self::visible::<T>(criteria);
         // total count
-        let count = Self::query_count(&table_name, params, pool).await?;
+        let count = Self::query_count(&table_name, criteria, &mut *conn).await?;
 
-        let mut sql = format!("select *  from {} ", table_name);
-        let (mut sql_with_param, mut param_values) = Self::add_params_to_sql(sql, params).await?;
+        let sql = format!("select *  from {} ", table_name);
+        let (mut sql_with_param, mut params) = Self::add_criteria_to_sql(sql, criteria, columns)?;
 
-        if !page_request.sort.is_empty() {
-            sql_with_param.push_str(" order by ");
-            sql_with_param.push_str(page_request.sort.as_str());
-        }
-        sql_with_param.push_str(" limit ?,?");
+        sql_with_param.push_str(&order_by(&page_request.sort, columns)?);
+        sql_with_param.push_str(" limit ? offset ?");
 
-        let mut arg = Self::covert_param_values_to_arg(param_values).await?;
-        let start = page_request.num * page_request.size;
-        arg.add(start);
-        arg.add(page_request.size);
+        params.push(Param::from(page_request.size));
+        params.push(Param::from(start));
 
-        println!("page sql is {} ", sql_with_param);
-
-        let context: Vec<T> = sqlx::query_as_with::<_, T, MySqlArguments>(&sql_with_param, arg)
-            .fetch_all(pool)
+        let sql_with_param = DB::translate(&sql_with_param);
+        let arg = Self::arguments(params)?;
+        let context: Vec<T> = sqlx::query_as_with::<DB, T, _>(&sql_with_param, arg)
+            .fetch_all(&mut *conn)
             .await?;
-        let total_page = (count + page_request.size - 1) / page_request.size;
+        let total_page = count / page_request.size + (count % page_request.size != 0) as i64;
 
         Ok(Page {
             size: page_request.size,
@@ -211,189 +334,459 @@ impl MySqlRepository {
         })
     }
 
-    pub async fn query_count(
+    ///keyset pagination: the first rows after the cursor in the order of `sort`, the id is
+    ///added as the last sort key. unlike `query_page` it is stable under concurrent inserts
+    pub async fn query_after<'c, T>(
+        criteria: &Criteria,
+        sort: &[Sort],
+        after: Option<&str>,
+        first: i64,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<CursorPage<T>>
+    where
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+    {
+        if first <= 0 {
+            return Err(anyhow!("first should be positive, got {}", first));
+        }
+        let table_name = T::table_name();
+        let columns = T::columns();
+        let keys = cursor::keys(sort, T::id_name());
+
+        let mut criteria = Criteria::all().group(Self::visible::<T>(criteria));
+        if let Some(after) = after {
+            criteria = criteria.group(cursor::after(&keys, &cursor::decode(after)?)?);
+        }
+        let sql = format!("select * from {} ", table_name);
+        let (mut sql_with_param, mut params) = Self::add_criteria_to_sql(sql, &criteria, columns)?;
+        sql_with_param.push_str(&order_by(&keys, columns)?);
+        sql_with_param.push_str(" limit ?");
+
+        // one more row to know whether there is a next page
+        params.push(Param::from(first + 1));
+        let sql_with_param = DB::translate(&sql_with_param);
+        let arg = Self::arguments(params)?;
+        let mut rows: Vec<T> = sqlx::query_as_with::<DB, T, _>(&sql_with_param, arg)
+            .fetch_all(executor)
+            .await?;
+        let has_next_page = rows.len() as i64 > first;
+        rows.truncate(first as usize);
+
+        let mut edges = vec![];
+        for row in rows {
+            let values = keys
+                .iter()
+                .map(|key| {
+                    row.column_value(&key.field)
+                        .ok_or_else(|| anyhow!("{} is not a field of {}", key.field, table_name))
+                })
+                .collect::<Result<Vec<Value>>>()?;
+            edges.push((cursor::encode(&values), row));
+        }
+        Ok(CursorPage {
+            edges,
+            has_next_page,
+        })
+    }
+
+    pub async fn query_count<'c>(
         table_name: &String,
-        params: &BTreeMap<String, String>,
-        pool: &MySqlPool,
-    ) -> Result<i64> {
-        let mut sql = format!("select count(*) as count from {} ", table_name);
-        let (mut sql_with_param, mut param_values) = Self::add_params_to_sql(sql, params).await?;
-        let arg = Self::covert_param_values_to_arg(param_values).await?;
-        let row: MySqlRow = sqlx::query_with::<_, MySqlArguments>(&sql_with_param, arg)
-            .fetch_one(pool)
+        criteria: &Criteria,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<i64>
+    where
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+    {
+        let sql = format!("select count(*) as count from {} ", table_name);
+        let (sql_with_param, params) = Self::add_criteria_to_sql(sql, criteria, &[])?;
+        let sql_with_param = DB::translate(&sql_with_param);
+        let arg = Self::arguments(params)?;
+        let count = sqlx::query_scalar_with::<DB, i64, _>(&sql_with_param, arg)
+            .fetch_one(executor)
             .await?;
 
-        Ok(row.get(0))
+        Ok(count)
     }
 
-    async fn covert_param_values_to_arg(params: Vec<&String>) -> Result<MySqlArguments> {
-        let mut arg = MySqlArguments::default();
-        if !params.is_empty() {
-            params.iter().for_each(|v| {
-                arg.add(v);
-                ()
-            });
+    ///the criteria without the deleted rows, unless it is on the `deleted` column itself
+    fn visible<T: Entity>(criteria: &Criteria) -> Criteria {
+        match T::audit().deleted {
+            Some(deleted) if !criteria.refers_to(deleted) => match criteria {
+                Criteria::And(_) => criteria.clone().eq(deleted, false),
+                _ => Criteria::all().group(criteria.clone()).eq(deleted, false),
+            },
+            _ => criteria.clone(),
         }
-        Ok(arg)
     }
 
-    async fn add_params_to_sql(
+    fn add_criteria_to_sql(
         mut sql: String,
-        params: &BTreeMap<String, String>,
-    ) -> Result<(String, Vec<&String>)> {
-        let mut param_values = vec![];
-        if !params.is_empty() {
+        criteria: &Criteria,
+        columns: &[&str],
+    ) -> Result<(String, Vec<Param>)> {
+        let (condition, param_values) = criteria.to_sql(columns)?;
+        if !condition.is_empty() {
             sql.push_str(" where ");
-            for (k, v) in params {
-                sql.push_str(k.as_str());
-                sql.push_str(" = ? and ");
-                param_values.push(v);
-            }
-            sql.truncate(sql.len() - 4);
+            sql.push_str(&condition);
         }
-        println!("sql is {} ", sql);
-        Ok((sql, param_values))
-    }
-
-    async fn covert_object_to_arg(params: Vec<&Value>) -> Result<MySqlArguments> {
-        let mut arg = MySqlArguments::default();
-        for value in params {
-            match value {
-                Value::Bool(_) => {
-                    arg.add(value.as_bool().unwrap_or(false));
-                    ()
-                }
-                Value::Number(_) => {
-                    if value.is_i64() {
-                        arg.add(value.as_i64().unwrap_or(0));
-                    } else if value.is_u64() {
-                        arg.add(value.as_u64().unwrap_or(0));
-                    } else if value.is_f64() {
-                        arg.add(value.as_f64().unwrap_or(0.0));
-                    }
-                    ()
-                }
-                Value::String(_) => {
-                    arg.add(value.as_str().unwrap_or(""));
-                    ()
-                }
-                _ => (),
-            };
+        Ok((sql, param_values.into_iter().map(Param::from).collect()))
+    }
+
+    fn arguments<'q>(params: Vec<Param>) -> Result<<DB as HasArguments<'q>>::Arguments> {
+        let mut arg = <DB as HasArguments<'q>>::Arguments::default();
+        for param in params {
+            DB::bind(&mut arg, param)?;
         }
         Ok(arg)
     }
 
-    pub async fn add<T>(entity: &T, pool: &MySqlPool) -> Result<bool>
+    pub(crate) async fn execute<'c>(
+        statement: Statement,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<u64> {
+        let sql = DB::translate(&statement.sql);
+        let arg = Self::arguments(statement.params)?;
+        let result = sqlx::query_with::<DB, _>(&sql, arg)
+            .execute(executor)
+            .await?;
+        let rows_affected = DB::rows_affected(&result);
+        match statement.checked {
+            Some(stale) if rows_affected == 0 => Err(stale.into()),
+            _ => Ok(rows_affected),
+        }
+    }
+
+    ///run the statements in one transaction, a savepoint when `db` is a transaction already
+    pub(crate) async fn execute_all<'c>(
+        statements: Vec<Statement>,
+        db: impl Acquire<'c, Database = DB>,
+    ) -> Result<u64>
     where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
     {
-        let table_name = T::table_name().await?;
-        let json = serde_json::json!(entity);
-        if !json.is_object() {
-            return Err(anyhow!("save error , not a entity object"));
+        if statements.is_empty() {
+            return Ok(0);
         }
+        let mut tx = db.begin().await?;
+        let mut rows_affected = 0;
+        for statement in statements {
+            rows_affected += Self::execute(statement, &mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(rows_affected)
+    }
 
-        let object = json.as_object().unwrap();
-        let mut columns = String::new();
-        let mut place_holder = String::new();
-        let mut values: Vec<&Value> = vec![];
-        for (column, value) in object {
-            columns.push_str(column);
-            columns.push(',');
-
-            place_holder.push('?');
-            place_holder.push(',');
+    fn quote_columns(columns: &[&str], suffix: &str) -> String {
+        columns
+            .iter()
+            .map(|column| format!("`{}`{}", column, suffix))
+            .collect::<Vec<String>>()
+            .join(",")
+    }
 
-            values.push(value);
-        }
-        columns.pop();
-        place_holder.pop();
+    pub(crate) fn insert_statement<T: Entity>(entity: &T) -> Statement {
+        let columns = T::insert_columns();
         let insert_sql = format!(
             "insert into {} ({}) values ({})",
-            table_name, columns, place_holder
+            T::table_name(),
+            Self::quote_columns(columns, ""),
+            vec!["?"; columns.len()].join(",")
         );
-        println!("insert sql is : {}", insert_sql);
-        let arg = Self::covert_object_to_arg(values).await?;
-        let rows_affected = sqlx::query_with(insert_sql.as_str(), arg)
-            .execute(pool)
-            .await?
-            .rows_affected();
+        let params = Self::audited::<T>(columns, entity.insert_params());
+        Statement::new(insert_sql, params)
+    }
 
-        println!("{}", rows_affected);
-        Ok(rows_affected > 0)
+    ///the params of `columns` with the values of the audit columns: the time, the actor,
+    ///not deleted and the first version
+    fn audited<T: Entity>(columns: &[&str], mut params: Vec<Param>) -> Vec<Param> {
+        let audit = T::audit();
+        let now = audit::now();
+        for (column, param) in columns.iter().zip(params.iter_mut()) {
+            let column = Some(*column);
+            if column == audit.created_at || column == audit.updated_at {
+                *param = Param::from(now);
+            } else if column == audit.created_by {
+                *param = Param::from(audit::actor());
+            } else if column == audit.deleted {
+                *param = Param::from(false);
+            } else if column == audit.version {
+                *param = Param::from(1);
+            }
+        }
+        params
     }
 
-    pub async fn update<T>(entity: &T, pool: &MySqlPool) -> Result<bool>
-    where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
-    {
-        let table_name = T::table_name().await?;
-        let id_name = T::id_name().await?;
-        let json = serde_json::json!(entity);
-        if !json.is_object() {
-            return Err(anyhow!("save error , not a entity object"));
+    ///the id of an entity as text, for messages
+    fn id_text<T: Entity>(entity: &T) -> String {
+        match entity.column_value(T::id_name()) {
+            Some(Value::String(id)) => id,
+            Some(id) => id.to_string(),
+            None => String::new(),
         }
+    }
+
+    ///multi-row inserts of at most `BATCH_SIZE` rows each, `suffix` is appended to every one
+    fn insert_rows<T: Entity>(entities: &[T], suffix: &str) -> Vec<Statement> {
+        let columns = T::insert_columns();
+        let row = format!("({})", vec!["?"; columns.len()].join(","));
+        entities
+            .chunks(chunk_size::<DB>(columns.len()))
+            .map(|chunk| {
+                let insert_sql = format!(
+                    "insert into {} ({}) values {}{}",
+                    T::table_name(),
+                    Self::quote_columns(columns, ""),
+                    vec![row.as_str(); chunk.len()].join(","),
+                    suffix
+                );
+                let params = chunk
+                    .iter()
+                    .flat_map(|entity| Self::audited::<T>(columns, entity.insert_params()))
+                    .collect();
+                Statement::new(insert_sql, params)
+            })
+            .collect()
+    }
+
+    pub(crate) fn insert_all_statements<T: Entity>(entities: &[T]) -> Vec<Statement> {
+        Self::insert_rows(entities, "")
+    }
 
-        let object = json.as_object().unwrap();
-        let id_value_op = object.get(&id_name);
-        if id_value_op == None {
-            return Err(anyhow!("save error , {} is null", id_name));
+    ///insert the rows, or update the columns of the rows whose primary key exists.
+    ///a deleted row is restored, the version is incremented but not checked
+    pub(crate) fn upsert_statements<T: Entity>(entities: &[T]) -> Vec<Statement> {
+        let audit = T::audit();
+        let mut columns = T::update_columns().to_vec();
+        columns.extend(audit.deleted);
+        let suffix = DB::upsert(&T::table_name(), T::id_name(), &columns, audit.version);
+        Self::insert_rows(entities, &suffix)
+    }
+
+    ///the update of a versioned entity matches the version which was read and increments it,
+    ///a deleted row is not updated
+    pub(crate) fn update_statement<T: Entity>(entity: &T) -> Statement {
+        let audit = T::audit();
+        let columns = T::update_columns();
+        let mut assignments: Vec<String> = columns.iter().map(|c| format!("`{}` = ?", c)).collect();
+        let mut condition = format!("`{}` = ?", T::id_name());
+        let mut params = Self::audited::<T>(columns, entity.update_params());
+        params.push(entity.id_param());
+        if let Some(deleted) = audit.deleted {
+            condition.push_str(&format!(" and `{}` = ?", deleted));
+            params.push(Param::from(false));
+        }
+        let mut checked = None;
+        if let Some(version) = audit.version {
+            assignments.push(format!("`{0}` = `{0}` + 1", version));
+            condition.push_str(&format!(" and `{}` = ?", version));
+            params.push(Param::from(
+                &entity.column_value(version).unwrap_or_default(),
+            ));
+            checked = Some(StaleEntity {
+                table: T::table_name(),
+                id: Self::id_text(entity),
+            });
+        }
+        let update_sql = format!(
+            "update {} set {} where {}",
+            T::table_name(),
+            assignments.join(","),
+            condition
+        );
+        Statement {
+            checked,
+            ..Statement::new(update_sql, params)
         }
-        let id_value = id_value_op.unwrap();
-        let mut columns = String::new();
-        let mut values: Vec<&Value> = vec![];
-        for (column, value) in object {
-            if column.eq(&id_name) {
-                continue;
+    }
+
+    ///delete the rows of the condition, or flag them when the entity has a `deleted` column
+    fn delete_sql<T: Entity>(condition: String, params: Vec<Param>) -> Statement {
+        let audit = T::audit();
+        let deleted = match audit.deleted {
+            Some(deleted) => deleted,
+            None => {
+                let delete_sql = format!("delete from {} where {}", T::table_name(), condition);
+                return Statement::new(delete_sql, params);
             }
-            columns.push_str(column);
-            columns.push_str(" = ?,");
-            values.push(value);
+        };
+        let mut assignments = vec![format!("`{}` = ?", deleted)];
+        let mut set_params = vec![Param::from(true)];
+        if let Some(updated_at) = audit.updated_at {
+            assignments.push(format!("`{}` = ?", updated_at));
+            set_params.push(Param::from(audit::now()));
         }
-        columns.pop();
-        values.push(id_value);
-        let insert_sql = format!(
-            "update {} set {} where {} = ? ",
-            table_name, columns, id_name
+        if let Some(version) = audit.version {
+            assignments.push(format!("`{0}` = `{0}` + 1", version));
+        }
+        set_params.extend(params);
+        set_params.push(Param::from(false));
+        let delete_sql = format!(
+            "update {} set {} where ({}) and `{}` = ?",
+            T::table_name(),
+            assignments.join(","),
+            condition,
+            deleted
         );
-        println!("insert sql is : {}", insert_sql);
-        let arg = Self::covert_object_to_arg(values).await?;
-        let rows_affected = sqlx::query_with(insert_sql.as_str(), arg)
-            .execute(pool)
-            .await?
-            .rows_affected();
-        println!("{}", rows_affected);
-        Ok(rows_affected > 0)
+        Statement::new(delete_sql, set_params)
+    }
+
+    pub(crate) fn delete_statement<T: Entity>(id: &str) -> Statement {
+        let condition = format!("`{}` = ?", T::id_name());
+        Self::delete_sql::<T>(condition, vec![Param::from(id)])
+    }
+
+    ///delete the rows matching the criteria, an empty criteria is rejected
+    ///rather than deleting every row
+    pub(crate) fn delete_where_statement<T: Entity>(criteria: &Criteria) -> Result<Statement> {
+        if criteria.is_empty() {
+            return Err(anyhow!(
+                "delete from {} without a condition",
+                T::table_name()
+            ));
+        }
+        let (condition, values) = criteria.to_sql(T::columns())?;
+        let params = values.into_iter().map(Param::from).collect();
+        Ok(Self::delete_sql::<T>(condition, params))
+    }
+
+    ///deletes of at most `BATCH_SIZE` ids each, empty ids are skipped
+    pub(crate) fn delete_by_ids_statements<T: Entity>(ids: &[String]) -> Result<Vec<Statement>> {
+        let ids: Vec<&str> = ids
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !id.is_empty())
+            .collect();
+        ids.chunks(chunk_size::<DB>(1))
+            .map(|chunk| {
+                let criteria = Criteria::all().is_in(T::id_name(), chunk.to_vec());
+                Self::delete_where_statement::<T>(&criteria)
+            })
+            .collect()
+    }
+
+    pub async fn add<'c, T>(entity: &T, executor: impl Executor<'c, Database = DB>) -> Result<bool>
+    where
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+    {
+        let statement = Self::insert_statement(entity);
+        Ok(Self::execute(statement, executor).await? > 0)
+    }
+
+    ///insert the entity and return the id generated by the database, such as an auto
+    ///increment id which is `read_only` in the entity
+    pub async fn add_returning_id<'c, T>(
+        entity: &T,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<i64>
+    where
+        T: Entity,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+    {
+        let Statement { sql, params, .. } = Self::insert_statement(entity);
+        match DB::returning(T::id_name()) {
+            Some(returning) => {
+                let sql = format!("{}{}", sql, returning);
+                let sql = DB::translate(&sql);
+                let arg = Self::arguments(params)?;
+                let id = sqlx::query_scalar_with::<DB, i64, _>(&sql, arg)
+                    .fetch_one(executor)
+                    .await?;
+                Ok(id)
+            }
+            None => {
+                let sql = DB::translate(&sql);
+                let arg = Self::arguments(params)?;
+                let result = sqlx::query_with::<DB, _>(&sql, arg)
+                    .execute(executor)
+                    .await?;
+                DB::last_insert_id(&result)
+                    .ok_or_else(|| anyhow!("no id is generated for {}", T::table_name()))
+            }
+        }
+    }
+
+    ///false when the row does not exist, a versioned entity which was changed since it was
+    ///read is a `StaleEntity` error
+    pub async fn update<'c, T>(
+        entity: &T,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<bool>
+    where
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
+    {
+        let statement = Self::update_statement(entity);
+        Ok(Self::execute(statement, executor).await? > 0)
     }
 
-    pub async fn delete_by_id<T>(id: &String, pool: &MySqlPool) -> Result<bool>
+    ///a row with a `deleted` column is kept and flagged, it is excluded from the queries
+    pub async fn delete_by_id<'c, T>(
+        id: &String,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<bool>
     where
-        T: for<'r> FromRow<'r, MySqlRow> + Send + Unpin + Debug + Entity,
+        T: for<'r> FromRow<'r, DB::Row> + Send + Unpin + Debug + Entity,
     {
         if id.is_empty() {
             return Ok(true);
         }
-        let table_name = T::table_name().await?;
-        let id_name = T::id_name().await?;
-        // let columns : Vec<String> = T::columns().await?;
-        let delete_sql = format!("delete from {} where {} = ? ", table_name, id_name);
-        println!("delete sql is : {}", delete_sql);
-        let rows_affected = sqlx::query(delete_sql.as_str())
-            .bind(&id)
-            .execute(pool)
-            .await?
-            .rows_affected();
-        Ok(rows_affected > 0)
+        let statement = Self::delete_statement::<T>(id);
+        Ok(Self::execute(statement, executor).await? > 0)
+    }
+
+    ///insert the entities with multi-row inserts in one transaction, returns the inserted rows
+    pub async fn add_all<'c, T>(entities: &[T], db: impl Acquire<'c, Database = DB>) -> Result<u64>
+    where
+        T: Entity,
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+    {
+        Self::execute_all(Self::insert_all_statements(entities), db).await
+    }
+
+    ///insert the entities or update the existing ones by primary key in one transaction.
+    ///returns the affected rows as the database counts them, mysql counts 1 for an insert,
+    ///2 for an update and 0 for a row which is unchanged
+    pub async fn upsert<'c, T>(entities: &[T], db: impl Acquire<'c, Database = DB>) -> Result<u64>
+    where
+        T: Entity,
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+    {
+        Self::execute_all(Self::upsert_statements(entities), db).await
+    }
+
+    ///delete the rows of the ids in one transaction, returns the deleted rows
+    pub async fn delete_by_ids<'c, T>(
+        ids: &[String],
+        db: impl Acquire<'c, Database = DB>,
+    ) -> Result<u64>
+    where
+        T: Entity,
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+    {
+        Self::execute_all(Self::delete_by_ids_statements::<T>(ids)?, db).await
+    }
+
+    ///delete the rows matching the criteria, returns the deleted rows
+    pub async fn delete_where<'c, T>(
+        criteria: &Criteria,
+        executor: impl Executor<'c, Database = DB>,
+    ) -> Result<u64>
+    where
+        T: Entity,
+    {
+        Self::execute(Self::delete_where_statement::<T>(criteria)?, executor).await
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::entity::{Entity, MySqlRepository, PageRequest};
+    use crate::audit::{self, StaleEntity};
+    use crate::criteria::Criteria;
+    use crate::entity::{order_by, Entity, MySqlRepository, PageRequest, Sort, SqliteRepository};
     use anyhow::Result;
     use async_trait::async_trait;
     use serde::{Deserialize, Serialize};
     use sqlx::mysql::{MySqlArguments, MySqlDatabaseError};
+    use sqlx::sqlite::SqlitePoolOptions;
     use sqlx::Arguments;
     use sqlx::{Encode, MySql, MySqlPool, Type};
     use std::collections::BTreeMap;
@@ -410,7 +803,7 @@ mod tests {
     // insert into t_lighting_good(id , name , size , count) VALUES ('good1' , '洗碗机',100.0 , 2000);
     ///
 
-    #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Clone)]
+    #[derive(sqlx::FromRow, Entity, Debug, Deserialize, Serialize, Clone)]
     struct Good {
         id: String,
         name: String,
@@ -418,7 +811,7 @@ mod tests {
         count: i32,
     }
 
-    impl Entity for Good {}
+    ::async_graphql::scalar!(Good);
 
     #[tokio::test]
     async fn test_crud() -> Result<()> {
@@ -534,6 +927,207 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_add_all() -> Result<()> {
+        dotenv::dotenv().ok();
+        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
+        let db_pool = MySqlPool::connect(&database_url).await?;
+
+        let mut goods: Vec<Good> = (0..300)
+            .map(|i| Good {
+                id: format!("good_batch{}", i),
+                name: "洗衣机".to_string(),
+                size: 89.98,
+                count: i,
+            })
+            .collect();
+        assert_eq!(MySqlRepository::add_all(&goods, &db_pool).await?, 300);
+
+        goods[0].count = 1000;
+        MySqlRepository::upsert(&goods[..2], &db_pool).await?;
+
+        let ids: Vec<String> = goods.iter().map(|good| good.id.clone()).collect();
+        let deleted = MySqlRepository::delete_by_ids::<Good>(&ids[..100], &db_pool).await?;
+        assert_eq!(deleted, 100);
+        let criteria = Criteria::all().contains("id", "good_batch");
+        let deleted = MySqlRepository::delete_where::<Good>(&criteria, &db_pool).await?;
+        assert_eq!(deleted, 200);
+        Ok(())
+    }
+
+    #[derive(sqlx::FromRow, Entity, Debug, Deserialize, Serialize, Clone)]
+    struct Report {
+        id: String,
+        name: String,
+        #[entity(created_at)]
+        created_at: i64,
+        #[entity(updated_at)]
+        updated_at: i64,
+        #[entity(created_by)]
+        created_by: Option<String>,
+        #[entity(deleted)]
+        deleted: bool,
+        #[entity(version)]
+        version: i32,
+    }
+
+    ::async_graphql::scalar!(Report);
+
+    #[tokio::test]
+    async fn test_audit() -> Result<()> {
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        sqlx::query(
+            "CREATE TABLE t_lighting_report (
+                `id` varchar(128) NOT NULL PRIMARY KEY,
+                `name` varchar(128) NOT NULL,
+                `created_at` BIGINT NOT NULL,
+                `updated_at` BIGINT NOT NULL,
+                `created_by` varchar(128),
+                `deleted` BOOLEAN NOT NULL,
+                `version` int NOT NULL
+            )",
+        )
+        .execute(&db_pool)
+        .await?;
+        assert_eq!(Report::update_columns(), &["name", "updated_at"]);
+
+        let report = Report {
+            id: "r1".to_string(),
+            name: "sales".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            created_by: None,
+            deleted: true,
+            version: 7,
+        };
+        audit::with_actor(
+            "analyst".to_string(),
+            SqliteRepository::add(&report, &db_pool),
+        )
+        .await?;
+        let id = "r1".to_string();
+        let read = SqliteRepository::find_by_id::<Report>(&id, &db_pool)
+            .await?
+            .unwrap();
+        assert!(read.created_at > 0 && !read.deleted);
+        assert_eq!(
+            (read.created_by.as_deref(), read.version),
+            (Some("analyst"), 1)
+        );
+
+        // the first editor wins, the second one read the same version
+        let mut first = read.clone();
+        first.name = "sales 2021".to_string();
+        assert!(SqliteRepository::update(&first, &db_pool).await?);
+        let error = SqliteRepository::update(&read, &db_pool).await.unwrap_err();
+        assert!(error.downcast_ref::<StaleEntity>().is_some());
+        let read = SqliteRepository::find_by_id::<Report>(&id, &db_pool)
+            .await?
+            .unwrap();
+        assert_eq!((read.name.as_str(), read.version), ("sales 2021", 2));
+        assert_eq!(read.created_by.as_deref(), Some("analyst"));
+
+        assert!(SqliteRepository::delete_by_id::<Report>(&id, &db_pool).await?);
+        assert!(!SqliteRepository::delete_by_id::<Report>(&id, &db_pool).await?);
+        assert!(SqliteRepository::find_by_id::<Report>(&id, &db_pool)
+            .await?
+            .is_none());
+        assert!(
+            SqliteRepository::query::<Report>(&Criteria::all(), &db_pool)
+                .await?
+                .is_empty()
+        );
+        assert!(SqliteRepository::update(&read, &db_pool).await.is_err());
+        let deleted = Criteria::all().eq("deleted", true);
+        let rows = SqliteRepository::query::<Report>(&deleted, &db_pool).await?;
+        assert_eq!(rows[0].version, 3);
+
+        SqliteRepository::upsert(&[report], &db_pool).await?;
+        let restored = SqliteRepository::find_by_id::<Report>(&id, &db_pool)
+            .await?
+            .unwrap();
+        assert_eq!((restored.name.as_str(), restored.version), ("sales", 4));
+        assert_eq!(restored.created_at, read.created_at);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sqlite() -> Result<()> {
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        sqlx::query(
+            "CREATE TABLE t_lighting_good (
+                `id` varchar(128) NOT NULL PRIMARY KEY,
+                `name` varchar(128) NOT NULL,
+                `size` double,
+                `count` int
+            )",
+        )
+        .execute(&db_pool)
+        .await?;
+
+        let mut goods: Vec<Good> = (0..5)
+            .map(|i| Good {
+                id: format!("good{}", i),
+                name: "洗衣机".to_string(),
+                size: 89.5,
+                count: i,
+            })
+            .collect();
+        assert_eq!(SqliteRepository::add_all(&goods, &db_pool).await?, 5);
+        goods[0].count = 100;
+        SqliteRepository::upsert(&goods[..2], &db_pool).await?;
+        let good = SqliteRepository::find_by_id::<Good>(&"good0".to_string(), &db_pool)
+            .await?
+            .unwrap();
+        assert_eq!(good.count, 100);
+        let mut tx = db_pool.begin().await?;
+        let good = SqliteRepository::lock_by_id::<Good>("good1", &mut tx).await?;
+        assert_eq!(good.map(|good| good.count), Some(1));
+        tx.commit().await?;
+
+        let criteria = Criteria::all().gt("count", 2);
+        let page = PageRequest {
+            size: 2,
+            num: 0,
+            sort: vec![Sort::desc("count")],
+        };
+        let page = SqliteRepository::query_page::<Good>(&criteria, &page, &db_pool).await?;
+        assert_eq!(page.count, 3);
+        let counts: Vec<i32> = page.context.iter().map(|good| good.count).collect();
+        assert_eq!(counts, vec![100, 4]);
+        for (size, num) in &[(0, 0), (-2, 0), (2, -1), (2, i64::MAX)] {
+            let page = PageRequest {
+                size: *size,
+                num: *num,
+                sort: vec![],
+            };
+            assert!(
+                SqliteRepository::query_page::<Good>(&criteria, &page, &db_pool)
+                    .await
+                    .is_err()
+            );
+        }
+        let page = PageRequest {
+            size: i64::MAX,
+            num: 0,
+            sort: vec![],
+        };
+        let page = SqliteRepository::query_page::<Good>(&criteria, &page, &db_pool).await?;
+        assert_eq!((page.count, page.total_page), (3, 1));
+
+        let deleted = SqliteRepository::delete_where::<Good>(&criteria, &db_pool).await?;
+        assert_eq!(deleted, 3);
+        let rest = SqliteRepository::query::<Good>(&Criteria::all(), &db_pool).await?;
+        assert_eq!(rest.len(), 2);
+        Ok(())
+    }
+
     // fn addParam<'q, T: 'q + Send + Encode<'q, Database> + Type<Database>>( params: &mut Vec<T> , param:&T){
     //     params.push(param);
     // }
@@ -566,11 +1160,11 @@ mod tests {
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
         let db_pool = MySqlPool::connect(&database_url).await?;
 
-        let mut params = BTreeMap::new();
-        params.insert(String::from("id"), String::from("good1"));
-        params.insert(String::from("count"), String::from("2000"));
+        let criteria = Criteria::all()
+            .eq("id", "good1")
+            .between("count", 1000, 3000);
 
-        let goods = MySqlRepository::query::<Good>(&params, &db_pool).await?;
+        let goods = MySqlRepository::query::<Good>(&criteria, &db_pool).await?;
         println!("{:?}", goods);
         Ok(())
     }
@@ -585,8 +1179,12 @@ mod tests {
         params.insert(String::from("id"), String::from("good1"));
         params.insert(String::from("count"), String::from("2000"));
 
-        let goods =
-            MySqlRepository::query_count(&"t_lighting_good".to_string(), &params, &db_pool).await?;
+        let goods = MySqlRepository::query_count(
+            &"t_lighting_good".to_string(),
+            &Criteria::from(&params),
+            &db_pool,
+        )
+        .await?;
         println!("{:?}", goods);
         Ok(())
     }
@@ -597,18 +1195,30 @@ mod tests {
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
         let db_pool = MySqlPool::connect(&database_url).await?;
 
-        let mut params = BTreeMap::new();
-        params.insert(String::from("id"), String::from("good1"));
-        params.insert(String::from("count"), String::from("2000"));
+        let criteria = Criteria::any()
+            .contains("name", "洗")
+            .is_in("id", vec!["good1", "good2"]);
 
         let request = PageRequest {
             size: 10,
             num: 0,
-            sort: "id desc".to_string(),
+            sort: vec![Sort::desc("count"), Sort::asc("id")],
         };
 
-        let goods = MySqlRepository::query_page::<Good>(&params, &request, &db_pool).await?;
+        let goods = MySqlRepository::query_page::<Good>(&criteria, &request, &db_pool).await?;
         println!("{:?}", goods);
         Ok(())
     }
+    #[test]
+    fn test_order_by() -> Result<()> {
+        let columns = ["id", "count"];
+        assert_eq!(
+            order_by(&[Sort::desc("count"), Sort::asc("id")], &columns)?,
+            " order by `count` desc,`id` asc"
+        );
+        assert_eq!(order_by(&[], &columns)?, "");
+        assert!(order_by(&[Sort::asc("name")], &columns).is_err());
+        assert!(order_by(&[Sort::asc("id; drop table t_lighting_good")], &[]).is_err());
+        Ok(())
+    }
 }
@@ -1,5 +1,16 @@
+pub mod audit;
+pub mod backend;
+pub mod criteria;
+pub mod cursor;
 pub mod entity;
+pub mod migration;
+pub mod unit_of_work;
 
+// the derive refers to `::crud_crait`, also inside of this crate
+extern crate self as crud_crait;
+
+#[doc(hidden)]
+pub use serde_json;
 
 use anyhow::Result;
 use async_trait::async_trait;
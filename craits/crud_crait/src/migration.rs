@@ -0,0 +1,355 @@
+use crate::backend::Backend;
+use crate::entity::namespace;
+use anyhow::{anyhow, Result};
+use sqlx::database::HasArguments;
+use sqlx::{Acquire, Executor, FromRow, IntoArguments};
+
+///the placeholder of the table prefix in the sql of a migration,
+///`${TABLE_NAMESPACE}_dataset` is created as `t_lighting_dataset` by default
+pub const NAMESPACE_PLACEHOLDER: &str = "${TABLE_NAMESPACE}";
+
+///a versioned change of the metadata schema, embedded with `include_str!`.
+///a migration must not be edited once it is applied, add a new one instead
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    pub fn new(version: i64, name: &'static str, sql: &'static str) -> Self {
+        Migration { version, name, sql }
+    }
+
+    ///fnv-1a of the sql before the namespace is replaced, so the checksum
+    ///is the same for every namespace
+    pub fn checksum(&self) -> String {
+        let hash = self.sql.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+        format!("{:016x}", hash)
+    }
+
+    ///the statements separated by `;` at the end of a line, with the namespace replaced.
+    ///comments of whole lines are removed
+    pub fn statements(&self, namespace: &str) -> Vec<String> {
+        let sql: Vec<&str> = self
+            .sql
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("--"))
+            .collect();
+        sql.join("\n")
+            .split(";\n")
+            .map(|statement| statement.trim().trim_end_matches(';').trim())
+            .filter(|statement| !statement.is_empty())
+            .map(|statement| statement.replace(NAMESPACE_PLACEHOLDER, namespace))
+            .collect()
+    }
+}
+
+///a row of the schema version table
+#[derive(Debug, Clone, FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+}
+
+///applies the migrations which are not recorded in the schema version table yet.
+///
+/// ```ignore
+/// let migrator = Migrator::new(vec![Migration::new(1, "init", include_str!("0001_init.sql"))])?;
+/// for migration in migrator.run(&pool, false).await? {
+///     println!("applied {} {}", migration.version, migration.name);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+    namespace: String,
+}
+
+impl Migrator {
+    ///the versions should be positive and increasing, the namespace is `TABLE_NAMESPACE`
+    pub fn new(migrations: Vec<Migration>) -> Result<Self> {
+        let mut previous = 0;
+        for migration in &migrations {
+            if migration.version <= previous {
+                return Err(anyhow!(
+                    "migration {} {} should be after version {}",
+                    migration.version,
+                    migration.name,
+                    previous
+                ));
+            }
+            previous = migration.version;
+        }
+        Ok(Migrator {
+            migrations,
+            namespace: namespace(),
+        })
+    }
+
+    pub fn with_namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
+
+    ///the table which records the applied migrations
+    pub fn version_table(&self) -> String {
+        format!("{}_schema_version", self.namespace)
+    }
+
+    ///the recorded migrations, none when the version table does not exist yet
+    pub async fn applied<'c, DB: Backend>(
+        &self,
+        db: impl Acquire<'c, Database = DB>,
+    ) -> Result<Vec<AppliedMigration>>
+    where
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+        for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+        for<'q> String: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+        AppliedMigration: for<'r> FromRow<'r, DB::Row>,
+    {
+        let mut conn = db.acquire().await?;
+        self.recorded(&mut *conn).await
+    }
+
+    async fn recorded<DB: Backend>(
+        &self,
+        conn: &mut DB::Connection,
+    ) -> Result<Vec<AppliedMigration>>
+    where
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+        for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+        for<'q> String: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+        AppliedMigration: for<'r> FromRow<'r, DB::Row>,
+    {
+        let exists: i64 = sqlx::query_scalar(&DB::translate(DB::table_exists()))
+            .bind(self.version_table())
+            .fetch_one(&mut *conn)
+            .await?;
+        if exists == 0 {
+            return Ok(vec![]);
+        }
+        let sql = format!(
+            "select version, name, checksum from {} order by version",
+            self.version_table()
+        );
+        Ok(sqlx::query_as::<DB, AppliedMigration>(&sql)
+            .fetch_all(&mut *conn)
+            .await?)
+    }
+
+    ///the migrations after the applied ones. an applied migration which was edited,
+    ///or which is unknown to this version of the application, is an error
+    pub fn pending(&self, applied: &[AppliedMigration]) -> Result<Vec<&Migration>> {
+        for record in applied {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|m| m.version == record.version)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "schema version {} {} is unknown, the database is newer than the application",
+                        record.version,
+                        record.name
+                    )
+                })?;
+            if migration.checksum() != record.checksum {
+                return Err(anyhow!(
+                    "migration {} {} was changed after it was applied, checksum {} != {}",
+                    migration.version,
+                    migration.name,
+                    migration.checksum(),
+                    record.checksum
+                ));
+            }
+        }
+        let current = applied.iter().map(|r| r.version).max().unwrap_or(0);
+        Ok(self
+            .migrations
+            .iter()
+            .filter(|m| m.version > current)
+            .collect())
+    }
+
+    ///apply the pending migrations in order and return them, with `dry_run` they are
+    ///only returned. mysql commits ddl at once, a migration which fails halfway has
+    ///to be repaired by hand before it is run again
+    pub async fn run<'c, DB: Backend>(
+        &self,
+        db: impl Acquire<'c, Database = DB>,
+        dry_run: bool,
+    ) -> Result<Vec<&Migration>>
+    where
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+        for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+        for<'q> String: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        for<'q> i64: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        for<'q> &'q str: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+        AppliedMigration: for<'r> FromRow<'r, DB::Row>,
+    {
+        let mut conn = db.acquire().await?;
+        if dry_run {
+            let applied = self.recorded(&mut *conn).await?;
+            return self.pending(&applied);
+        }
+        let lock = self.version_table();
+        DB::lock(&mut *conn, &lock).await?;
+        let result = self.apply(&mut *conn).await;
+        DB::unlock(&mut *conn, &lock).await?;
+        result
+    }
+
+    async fn apply<DB: Backend>(&self, conn: &mut DB::Connection) -> Result<Vec<&Migration>>
+    where
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+        for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+        for<'q> String: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        for<'q> i64: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        for<'q> &'q str: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        (i64,): for<'r> FromRow<'r, DB::Row>,
+        AppliedMigration: for<'r> FromRow<'r, DB::Row>,
+    {
+        let create = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                `version` BIGINT NOT NULL,
+                `name` varchar(255) NOT NULL,
+                `checksum` varchar(64) NOT NULL,
+                `applied_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (`version`)
+            )",
+            self.version_table()
+        );
+        sqlx::query(&DB::translate(&create))
+            .execute(&mut *conn)
+            .await?;
+
+        let applied = self.recorded(&mut *conn).await?;
+        let pending = self.pending(&applied)?;
+        let insert = format!(
+            "insert into {} (version, name, checksum) values (?, ?, ?)",
+            self.version_table()
+        );
+        for migration in &pending {
+            for statement in migration.statements(&self.namespace) {
+                sqlx::query(&DB::translate(&statement))
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(|e| {
+                        anyhow!(
+                            "migration {} {} failed: {}",
+                            migration.version,
+                            migration.name,
+                            e
+                        )
+                    })?;
+            }
+            sqlx::query(&DB::translate(&insert))
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(migration.checksum())
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const INIT: &str = "-- the first tables
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_good (
+    `id` varchar(128) NOT NULL,
+    PRIMARY KEY (`id`)
+)ENGINE=InnoDB;
+
+CREATE INDEX idx_good ON ${TABLE_NAMESPACE}_good (`id`);
+";
+
+    fn record(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_string(),
+            checksum: migration.checksum(),
+        }
+    }
+
+    #[test]
+    fn test_statements() {
+        let migration = Migration::new(1, "init", INIT);
+        let statements = migration.statements("t_shop");
+        assert_eq!(statements.len(), 2);
+        assert!(statements[0].starts_with("CREATE TABLE IF NOT EXISTS t_shop_good ("));
+        assert!(statements[0].ends_with(")ENGINE=InnoDB"));
+        assert_eq!(statements[1], "CREATE INDEX idx_good ON t_shop_good (`id`)");
+
+        assert_eq!(
+            migration.checksum(),
+            Migration::new(2, "copy", INIT).checksum()
+        );
+        assert_ne!(
+            migration.checksum(),
+            Migration::new(1, "init", "CREATE TABLE t (id int);").checksum()
+        );
+    }
+
+    #[test]
+    fn test_pending() -> Result<()> {
+        let init = Migration::new(1, "init", INIT);
+        let size = Migration::new(
+            2,
+            "good_size",
+            "ALTER TABLE ${TABLE_NAMESPACE}_good ADD `size` double;",
+        );
+        let migrator = Migrator::new(vec![init.clone(), size.clone()])?.with_namespace("t_shop");
+        assert_eq!(migrator.version_table(), "t_shop_schema_version");
+
+        assert_eq!(migrator.pending(&[])?, vec![&init, &size]);
+        assert_eq!(migrator.pending(&[record(&init)])?, vec![&size]);
+        assert!(migrator
+            .pending(&[record(&init), record(&size)])?
+            .is_empty());
+
+        let mut edited = record(&init);
+        edited.checksum = String::from("0");
+        assert!(migrator.pending(&[edited]).is_err());
+        let newer = Migration::new(3, "unknown", "");
+        assert!(migrator.pending(&[record(&newer)]).is_err());
+
+        assert!(Migrator::new(vec![size, init]).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_sqlite() -> Result<()> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        let init = Migration::new(
+            1,
+            "init",
+            "CREATE TABLE ${TABLE_NAMESPACE}_good (`id` varchar(128) NOT NULL PRIMARY KEY);
+CREATE INDEX idx_good ON ${TABLE_NAMESPACE}_good (`id`);",
+        );
+        let migrator = Migrator::new(vec![init])?.with_namespace("t_shop");
+        assert_eq!(migrator.run(&pool, true).await?.len(), 1);
+        assert_eq!(migrator.run(&pool, false).await?.len(), 1);
+        assert!(migrator.run(&pool, false).await?.is_empty());
+        assert_eq!(migrator.applied(&pool).await?[0].name, "init");
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::backend::{Backend, Metadata};
+use crate::criteria::Criteria;
+use crate::entity::{Entity, Repository, Statement};
+use anyhow::Result;
+use sqlx::database::HasArguments;
+use sqlx::{Acquire, Executor, IntoArguments};
+use std::marker::PhantomData;
+
+///collects the changes of several entities and writes them in one transaction,
+///so that either all of them are saved or none
+///
+/// ```ignore
+/// let mut work = UnitOfWork::<MySql>::new();
+/// work.add(&dataset);
+/// for field in &fields {
+///     work.add(field);
+/// }
+/// work.commit(pool).await?;
+/// ```
+#[derive(Debug)]
+pub struct UnitOfWork<DB: Backend = Metadata> {
+    statements: Vec<Statement>,
+    backend: PhantomData<DB>,
+}
+
+impl<DB: Backend> Default for UnitOfWork<DB> {
+    fn default() -> Self {
+        UnitOfWork {
+            statements: vec![],
+            backend: PhantomData,
+        }
+    }
+}
+
+impl<DB: Backend> UnitOfWork<DB>
+where
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<T: Entity>(&mut self, entity: &T) {
+        let statement = Repository::<DB>::insert_statement(entity);
+        self.statements.push(statement);
+    }
+
+    pub fn update<T: Entity>(&mut self, entity: &T) {
+        let statement = Repository::<DB>::update_statement(entity);
+        self.statements.push(statement);
+    }
+
+    pub fn delete_by_id<T: Entity>(&mut self, id: &str) {
+        if !id.is_empty() {
+            let statement = Repository::<DB>::delete_statement::<T>(id);
+            self.statements.push(statement);
+        }
+    }
+
+    ///insert the entities with multi-row inserts
+    pub fn add_all<T: Entity>(&mut self, entities: &[T]) {
+        let statements = Repository::<DB>::insert_all_statements(entities);
+        self.statements.extend(statements);
+    }
+
+    ///insert the entities or update the existing ones by primary key
+    pub fn upsert<T: Entity>(&mut self, entities: &[T]) {
+        let statements = Repository::<DB>::upsert_statements(entities);
+        self.statements.extend(statements);
+    }
+
+    pub fn delete_by_ids<T: Entity>(&mut self, ids: &[String]) -> Result<()> {
+        let statements = Repository::<DB>::delete_by_ids_statements::<T>(ids)?;
+        self.statements.extend(statements);
+        Ok(())
+    }
+
+    pub fn delete_where<T: Entity>(&mut self, criteria: &Criteria) -> Result<()> {
+        let statement = Repository::<DB>::delete_where_statement::<T>(criteria)?;
+        self.statements.push(statement);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    ///write the changes in the order they were collected and return the affected rows.
+    ///on the first error the transaction is rolled back. when `db` is a transaction
+    ///already, the changes are written in a savepoint of it
+    pub async fn commit<'c>(self, db: impl Acquire<'c, Database = DB>) -> Result<u64>
+    where
+        for<'e> &'e mut DB::Connection: Executor<'e, Database = DB>,
+    {
+        Repository::<DB>::execute_all(self.statements, db).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Param;
+    use serde::{Deserialize, Serialize};
+    use sqlx::MySql;
+
+    #[derive(sqlx::FromRow, Entity, Debug, Deserialize, Serialize, Clone)]
+    struct Good {
+        id: String,
+        name: String,
+        count: Option<i32>,
+    }
+
+    ::async_graphql::scalar!(Good);
+
+    #[test]
+    fn test_statements() {
+        let good = Good {
+            id: "good1".to_string(),
+            name: "洗碗机".to_string(),
+            count: None,
+        };
+        let mut work = UnitOfWork::<MySql>::new();
+        work.add(&good);
+        work.update(&good);
+        work.delete_by_id::<Good>("good1");
+        work.delete_by_id::<Good>("");
+        assert_eq!(work.len(), 3);
+
+        let sqls: Vec<&str> = work.statements.iter().map(|s| s.sql.trim()).collect();
+        assert_eq!(
+            sqls,
+            vec![
+                "insert into t_lighting_good (`id`,`name`,`count`) values (?,?,?)",
+                "update t_lighting_good set `name` = ?,`count` = ? where `id` = ?",
+                "delete from t_lighting_good where `id` = ?",
+            ]
+        );
+        assert_eq!(
+            work.statements[1].params,
+            vec![
+                Param::from("洗碗机"),
+                Param::I32(None),
+                Param::from("good1")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batch_statements() -> Result<()> {
+        let goods: Vec<Good> = (0..2500)
+            .map(|i| Good {
+                id: format!("good{}", i),
+                name: "洗碗机".to_string(),
+                count: Some(i),
+            })
+            .collect();
+        let mut work = UnitOfWork::<MySql>::new();
+        work.add_all(&goods);
+        assert_eq!(work.len(), 3);
+        assert!(work.statements[2].sql.ends_with("(?,?,?),(?,?,?)"));
+        assert_eq!(work.statements[2].sql.matches('(').count(), 501);
+
+        let mut work = UnitOfWork::<MySql>::new();
+        work.upsert(&goods[..2]);
+        work.delete_by_ids::<Good>(&["good1".to_string(), "".to_string(), "good2".to_string()])?;
+        work.delete_where::<Good>(&Criteria::all().lt("count", 10))?;
+        work.add_all::<Good>(&[]);
+        let sqls: Vec<&str> = work.statements.iter().map(|s| s.sql.trim()).collect();
+        assert_eq!(
+            sqls,
+            vec![
+                "insert into t_lighting_good (`id`,`name`,`count`) values (?,?,?),(?,?,?) \
+                 on duplicate key update `name` = values(`name`),`count` = values(`count`)",
+                "delete from t_lighting_good where `id` in (?,?)",
+                "delete from t_lighting_good where `count` < ?",
+            ]
+        );
+        assert!(work.delete_where::<Good>(&Criteria::all()).is_err());
+        assert!(work
+            .delete_where::<Good>(&Criteria::all().eq("size", 1))
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+///`#[derive(Entity)]` implements `crud_crait::entity::Entity` for a struct with named fields.
+///
+///on the struct:
+///- `#[entity(table = "name")]` the table after the namespace prefix, the snake case type name by default
+///- `#[entity(id = "column")]` the primary key, `id` by default
+///
+///on a field:
+///- `#[entity(rename = "column")]` the column of the field, add `#[sqlx(rename)]` to read it
+///- `#[entity(skip)]` not a column, add `#[sqlx(default)]` to read the entity
+///- `#[entity(read_only)]` read but never written, such as an auto increment id
+///- `#[entity(created_at)]`, `#[entity(updated_at)]`, `#[entity(created_by)]`, `#[entity(deleted)]`,
+///  `#[entity(version)]` maintained by the repository, see `crud_crait::audit::Audit`
+#[proc_macro_derive(Entity, attributes(entity))]
+pub fn derive_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+///the roles of `crud_crait::audit::Audit`
+const AUDIT: [&str; 5] = [
+    "created_at",
+    "updated_at",
+    "created_by",
+    "deleted",
+    "version",
+];
+
+struct Column {
+    field: syn::Ident,
+    name: String,
+    read_only: bool,
+    ///one of `AUDIT`
+    audit: Option<&'static str>,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(error(Span::call_site(), "entity fields should be named")),
+        },
+        _ => return Err(error(Span::call_site(), "entity should be a struct")),
+    };
+
+    let mut table = to_snake_name(&input.ident.to_string());
+    let mut id_name = String::from("id");
+    for meta in entity_attributes(&input.attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("table") => {
+                table = string_value(&nv.lit)?;
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("id") => {
+                id_name = string_value(&nv.lit)?;
+            }
+            meta => return Err(error_at(&meta, "expected `table` or `id`")),
+        }
+    }
+
+    let mut columns = vec![];
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        let mut column = Column {
+            name: ident.to_string(),
+            field: ident,
+            read_only: false,
+            audit: None,
+        };
+        let mut skip = false;
+        for meta in entity_attributes(&field.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => {
+                    column.name = string_value(&nv.lit)?;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => skip = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("read_only") => {
+                    column.read_only = true;
+                }
+                NestedMeta::Meta(Meta::Path(path))
+                    if AUDIT.iter().any(|role| path.is_ident(role)) =>
+                {
+                    let role = AUDIT.iter().find(|role| path.is_ident(role)).copied();
+                    if columns.iter().any(|c: &Column| c.audit == role) {
+                        return Err(error_at(&path, "the role is given to another column"));
+                    }
+                    column.audit = role;
+                }
+                meta => {
+                    return Err(error_at(
+                        &meta,
+                        "expected `rename`, `skip`, `read_only` or a role of audit",
+                    ))
+                }
+            }
+        }
+        if !skip {
+            columns.push(column);
+        }
+    }
+    let id = columns
+        .iter()
+        .find(|c| c.name == id_name)
+        .ok_or_else(|| error(Span::call_site(), &format!("no id column {}", id_name)))?;
+    let id_field = &id.field;
+
+    let names: Vec<&String> = columns.iter().map(|c| &c.name).collect();
+    let fields: Vec<&syn::Ident> = columns.iter().map(|c| &c.field).collect();
+    let insert: Vec<&Column> = columns.iter().filter(|c| !c.read_only).collect();
+    let insert_names: Vec<&String> = insert.iter().map(|c| &c.name).collect();
+    let insert_fields: Vec<&syn::Ident> = insert.iter().map(|c| &c.field).collect();
+    // created_at, created_by and deleted are written on insert, the version is incremented
+    let update: Vec<&&Column> = insert
+        .iter()
+        .filter(|c| c.name != id_name)
+        .filter(|c| matches!(c.audit, None | Some("updated_at")))
+        .collect();
+    let update_names: Vec<&String> = update.iter().map(|c| &c.name).collect();
+    let update_fields: Vec<&syn::Ident> = update.iter().map(|c| &c.field).collect();
+
+    let audit: Vec<proc_macro2::TokenStream> = AUDIT
+        .iter()
+        .map(|role| {
+            let field = syn::Ident::new(role, Span::call_site());
+            match columns.iter().find(|c| c.audit == Some(*role)) {
+                Some(column) => {
+                    let name = &column.name;
+                    quote! { #field: Some(#name) }
+                }
+                None => quote! { #field: None },
+            }
+        })
+        .collect();
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::crud_crait::entity::Entity for #name #ty_generics #where_clause {
+            fn table_name() -> String {
+                ::crud_crait::entity::namespaced(#table)
+            }
+
+            fn id_name() -> &'static str {
+                #id_name
+            }
+
+            fn columns() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+
+            fn insert_columns() -> &'static [&'static str] {
+                &[#(#insert_names),*]
+            }
+
+            fn update_columns() -> &'static [&'static str] {
+                &[#(#update_names),*]
+            }
+
+            fn insert_params(&self) -> Vec<::crud_crait::backend::Param> {
+                vec![#(::crud_crait::backend::Param::from(self.#insert_fields.clone())),*]
+            }
+
+            fn update_params(&self) -> Vec<::crud_crait::backend::Param> {
+                vec![#(::crud_crait::backend::Param::from(self.#update_fields.clone())),*]
+            }
+
+            fn id_param(&self) -> ::crud_crait::backend::Param {
+                ::crud_crait::backend::Param::from(self.#id_field.clone())
+            }
+
+            fn audit() -> ::crud_crait::audit::Audit {
+                ::crud_crait::audit::Audit {
+                    #(#audit),*
+                }
+            }
+
+            fn column_value(&self, column: &str) -> Option<::crud_crait::serde_json::Value> {
+                match column {
+                    #(#names => ::crud_crait::serde_json::to_value(&self.#fields).ok(),)*
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
+fn entity_attributes(attrs: &[syn::Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut metas = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("entity")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => metas.extend(list.nested),
+            meta => return Err(error_at(&meta, "expected #[entity(...)]")),
+        }
+    }
+    Ok(metas)
+}
+
+fn string_value(lit: &Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        lit => Err(error_at(lit, "expected a string")),
+    }
+}
+
+fn error(span: Span, message: &str) -> syn::Error {
+    syn::Error::new(span, message)
+}
+
+fn error_at<T: quote::ToTokens>(tokens: &T, message: &str) -> syn::Error {
+    syn::Error::new_spanned(tokens, message)
+}
+
+///the same names as the runtime conversion of earlier versions:
+///`SavedQuery` is `saved_query`, an upper case last letter is not separated
+fn to_snake_name(name: &str) -> String {
+    let len = name.chars().count();
+    let mut snake = String::new();
+    for (index, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if index != 0 && index + 1 != len {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
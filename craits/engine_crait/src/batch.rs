@@ -0,0 +1,230 @@
+use crate::block::DataBlock;
+use crate::cache::QueryCache;
+use crate::limits::QueryOptions;
+use crate::registry::EngineRegistry;
+use futures::future;
+use futures::stream::{self, StreamExt};
+use query::QueryBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+///每个引擎默认同时执行的查询数
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+///批量查询中的一个查询, 如仪表盘的一个组件
+#[derive(Debug, Clone)]
+pub struct BatchQuery {
+    pub id: String,
+    ///注册的引擎连接名
+    pub engine: String,
+    pub query: QueryBuilder,
+}
+
+///按查询id返回结果, 失败的查询记录在 `errors` 中, 不影响其他查询
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BatchResult {
+    pub blocks: BTreeMap<String, DataBlock>,
+    pub errors: BTreeMap<String, String>,
+}
+
+///并发执行一批查询, 相同引擎上相同的查询只执行一次, 每个引擎分别限制并发数
+#[derive(Debug, Clone)]
+pub struct BatchExecutor {
+    concurrency: usize,
+    limits: HashMap<String, usize>,
+}
+
+impl Default for BatchExecutor {
+    fn default() -> Self {
+        BatchExecutor::new(DEFAULT_CONCURRENCY)
+    }
+}
+
+impl BatchExecutor {
+    pub fn new(concurrency: usize) -> Self {
+        BatchExecutor {
+            concurrency: concurrency.max(1),
+            limits: HashMap::new(),
+        }
+    }
+
+    ///单独设置引擎的并发数
+    pub fn limit(mut self, engine: &str, concurrency: usize) -> Self {
+        self.limits.insert(engine.to_string(), concurrency.max(1));
+        self
+    }
+
+    pub fn concurrency(&self, engine: &str) -> usize {
+        self.limits.get(engine).copied().unwrap_or(self.concurrency)
+    }
+
+    ///`options` 的限制作用于每个查询, `query_id` 不为空时第n个执行的查询使用 `{query_id}-{n}`
+    pub async fn run(
+        &self,
+        registry: &EngineRegistry,
+        queries: Vec<BatchQuery>,
+        options: &QueryOptions,
+    ) -> BatchResult {
+        let mut distinct: Vec<(String, QueryBuilder, Vec<String>)> = vec![];
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for query in queries {
+            let key = QueryCache::key(&query.engine, &query.query);
+            match index.get(&key) {
+                Some(&i) => distinct[i].2.push(query.id),
+                None => {
+                    index.insert(key, distinct.len());
+                    distinct.push((query.engine, query.query, vec![query.id]));
+                }
+            }
+        }
+
+        let mut groups: BTreeMap<&str, Vec<(usize, QueryBuilder)>> = BTreeMap::new();
+        for (i, (engine, query, _)) in distinct.iter().enumerate() {
+            groups
+                .entry(engine.as_str())
+                .or_default()
+                .push((i, query.clone()));
+        }
+
+        let runs = groups.into_iter().map(|(name, group)| {
+            let engine = registry.get(name);
+            let concurrency = self.concurrency(name);
+            stream::iter(group)
+                .map(move |(i, query)| {
+                    let engine = engine.clone();
+                    let mut options = options.clone();
+                    if !options.query_id.is_empty() {
+                        options.query_id = format!("{}-{}", options.query_id, i);
+                    }
+                    async move {
+                        let result = match engine {
+                            Some(engine) => engine
+                                .query(query, &options)
+                                .await
+                                .map_err(|e| e.to_string()),
+                            None => Err(format!("engine {} is not registered", name)),
+                        };
+                        (i, result)
+                    }
+                })
+                .buffer_unordered(concurrency)
+                .collect::<Vec<_>>()
+        });
+
+        let mut result = BatchResult::default();
+        for (i, outcome) in future::join_all(runs).await.into_iter().flatten() {
+            for id in &distinct[i].2 {
+                match &outcome {
+                    Ok(block) => {
+                        result.blocks.insert(id.clone(), block.clone());
+                    }
+                    Err(e) => {
+                        result.errors.insert(id.clone(), e.clone());
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::ColumnData;
+    use crate::engine::{EngineType, QueryEngine};
+    use async_trait::async_trait;
+    use query::SchemaChange;
+    use std::error::Error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    ///记录执行次数和最大并发数, 查询 `bad` 表时失败
+    #[derive(Default)]
+    struct SlowEngine {
+        queries: AtomicUsize,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl QueryEngine for SlowEngine {
+        fn engine_type(&self) -> EngineType {
+            EngineType::Memory
+        }
+
+        async fn ddl(&self, _ddl: &str) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn alter_schema(&self, _change: SchemaChange) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            query_builder: QueryBuilder,
+            _options: &QueryOptions,
+        ) -> Result<DataBlock, Box<dyn Error>> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            let table = query_builder.get_table().clone();
+            if table == "bad" {
+                return Err(format!("table {} is broken", table).into());
+            }
+            Ok(DataBlock::new().column("table", ColumnData::Text(vec![Some(table)])))
+        }
+    }
+
+    fn batch_query(id: &str, engine: &str, table: &str) -> BatchQuery {
+        BatchQuery {
+            id: id.to_string(),
+            engine: engine.to_string(),
+            query: QueryBuilder::new().table(table.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let default = Arc::new(SlowEngine::default());
+        let local = Arc::new(SlowEngine::default());
+        let registry = EngineRegistry::new()
+            .register("default", default.clone())
+            .register("local", local.clone());
+
+        let mut queries: Vec<BatchQuery> = (0..8)
+            .map(|i| batch_query(&format!("w{}", i), "default", &format!("t_{}", i)))
+            .collect();
+        queries.push(batch_query("same", "default", "t_0"));
+        queries.push(batch_query("bad", "default", "bad"));
+        queries.push(batch_query("other", "local", "t_0"));
+        queries.push(batch_query("missing", "remote", "t_0"));
+
+        let executor = BatchExecutor::new(3).limit("local", 1);
+        let result = executor.run(&registry, queries, &QueryOptions::new()).await;
+
+        assert_eq!(result.blocks.len(), 10);
+        assert_eq!(result.blocks["same"], result.blocks["w0"]);
+        assert_eq!(
+            result.errors,
+            vec![
+                (String::from("bad"), String::from("table bad is broken")),
+                (
+                    String::from("missing"),
+                    String::from("engine remote is not registered")
+                ),
+            ]
+            .into_iter()
+            .collect()
+        );
+        // the duplicate of w0 is not executed again
+        assert_eq!(default.queries.load(Ordering::SeqCst), 9);
+        assert_eq!(default.max_running.load(Ordering::SeqCst), 3);
+        assert_eq!(local.queries.load(Ordering::SeqCst), 1);
+    }
+}
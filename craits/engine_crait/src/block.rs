@@ -0,0 +1,264 @@
+use query::{DataType, Value};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+///列数据, 日期按 `yyyy-MM-dd` 文本存储
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ColumnData {
+    Number(Vec<Option<f64>>),
+    Text(Vec<Option<String>>),
+}
+
+impl ColumnData {
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnData::Number(v) => v.len(),
+            ColumnData::Text(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///`len` 个空值, 日期和文本都为文本列
+    pub fn nulls(data_type: DataType, len: usize) -> Self {
+        match data_type {
+            DataType::Number => ColumnData::Number(vec![None; len]),
+            DataType::Text | DataType::Date => ColumnData::Text(vec![None; len]),
+        }
+    }
+
+    ///转换为 `data_type` 的列, 不能转为数字的文本为空值
+    pub fn cast(self, data_type: DataType) -> Self {
+        match (self, data_type) {
+            (ColumnData::Text(v), DataType::Number) => ColumnData::Number(
+                v.into_iter()
+                    .map(|s| s.and_then(|s| s.trim().parse().ok()))
+                    .collect(),
+            ),
+            (ColumnData::Number(v), DataType::Text | DataType::Date) => {
+                ColumnData::Text(v.into_iter().map(|n| n.map(|n| n.to_string())).collect())
+            }
+            (data, _) => data,
+        }
+    }
+
+    ///数字按8字节, 文本按字节数估算
+    pub fn byte_size(&self) -> usize {
+        match self {
+            ColumnData::Number(v) => v.len() * 8,
+            ColumnData::Text(v) => v.iter().flatten().map(|s| s.len()).sum(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            ColumnData::Number(v) => v.clear(),
+            ColumnData::Text(v) => v.clear(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Value {
+        match self {
+            ColumnData::Number(v) => match v.get(index) {
+                Some(Some(n)) => Value::Number(*n),
+                _ => Value::Null,
+            },
+            ColumnData::Text(v) => match v.get(index) {
+                Some(Some(s)) => Value::Text(s.clone()),
+                _ => Value::Null,
+            },
+        }
+    }
+
+    ///追加另一列的数据, 类型不同时转为文本列
+    pub fn extend(&mut self, other: ColumnData) {
+        let other = match (&mut *self, other) {
+            (ColumnData::Number(v), ColumnData::Number(o)) => return v.extend(o),
+            (ColumnData::Text(v), ColumnData::Text(o)) => return v.extend(o),
+            (_, other) => other,
+        };
+        let mut values: Vec<Value> = (0..self.len()).map(|i| self.get(i)).collect();
+        values.extend((0..other.len()).map(|i| other.get(i)));
+        *self = ColumnData::from_values(values);
+    }
+
+    ///全部为数字或空值时为数字列, 否则转为文本列
+    pub fn from_values(values: Vec<Value>) -> Self {
+        let is_number = values
+            .iter()
+            .all(|v| matches!(v, Value::Number(_) | Value::Null));
+        if is_number {
+            ColumnData::Number(
+                values
+                    .into_iter()
+                    .map(|v| match v {
+                        Value::Number(n) => Some(n),
+                        _ => None,
+                    })
+                    .collect(),
+            )
+        } else {
+            ColumnData::Text(
+                values
+                    .into_iter()
+                    .map(|v| match v {
+                        Value::Number(n) => Some(n.to_string()),
+                        Value::Text(s) => Some(s),
+                        Value::Null => None,
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    pub data: ColumnData,
+}
+
+///引擎通用的查询结果, 按列存储
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DataBlock {
+    columns: Vec<Column>,
+}
+
+impl DataBlock {
+    pub fn new() -> Self {
+        DataBlock { columns: vec![] }
+    }
+
+    pub fn column(mut self, name: &str, data: ColumnData) -> Self {
+        self.columns.push(Column {
+            name: name.to_string(),
+            data,
+        });
+        self
+    }
+
+    ///按行构建, 每列的类型由值推断
+    pub fn from_rows(names: Vec<String>, rows: Vec<Vec<Value>>) -> Self {
+        let mut values: Vec<Vec<Value>> = names.iter().map(|_| vec![]).collect();
+        for row in rows {
+            for (i, value) in row.into_iter().enumerate().take(names.len()) {
+                values[i].push(value);
+            }
+        }
+        let columns = names
+            .into_iter()
+            .zip(values)
+            .map(|(name, values)| Column {
+                name,
+                data: ColumnData::from_values(values),
+            })
+            .collect();
+        DataBlock { columns }
+    }
+
+    pub fn columns(&self) -> &Vec<Column> {
+        &self.columns
+    }
+
+    pub fn columns_mut(&mut self) -> &mut Vec<Column> {
+        &mut self.columns
+    }
+
+    pub fn get_column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map(|c| c.data.len()).unwrap_or(0)
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.columns.iter().map(|c| c.data.byte_size()).sum()
+    }
+
+    pub fn row(&self, index: usize) -> Vec<Value> {
+        self.columns.iter().map(|c| c.data.get(index)).collect()
+    }
+
+    ///按列名追加另一个块的行, 两个块的列必须相同
+    pub fn append(&mut self, other: DataBlock) -> Result<(), Box<dyn Error>> {
+        if self.columns.is_empty() {
+            self.columns = other.columns;
+            return Ok(());
+        }
+        let mut other = other.columns;
+        if other.len() != self.columns.len() {
+            return Err(format!(
+                "expected {} columns, got {}",
+                self.columns.len(),
+                other.len()
+            )
+            .into());
+        }
+        for column in self.columns.iter_mut() {
+            let index = other
+                .iter()
+                .position(|c| c.name == column.name)
+                .ok_or_else(|| format!("missing column {}", column.name))?;
+            column.data.extend(other.swap_remove(index).data);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_rows() {
+        let block = DataBlock::from_rows(
+            vec![String::from("name"), String::from("amount")],
+            vec![
+                vec![Value::from("foo"), Value::from(1.5)],
+                vec![Value::Null, Value::Null],
+                vec![Value::from(3_i64), Value::from(2_i64)],
+            ],
+        );
+
+        assert_eq!(block.row_count(), 3);
+        assert_eq!(
+            block.get_column("name").unwrap().data,
+            ColumnData::Text(vec![
+                Some(String::from("foo")),
+                None,
+                Some(String::from("3"))
+            ])
+        );
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Number(vec![Some(1.5), None, Some(2.0)])
+        );
+        assert_eq!(block.row(0), vec![Value::from("foo"), Value::from(1.5)]);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut block = DataBlock::new()
+            .column("name", ColumnData::Text(vec![Some(String::from("foo"))]))
+            .column("amount", ColumnData::Number(vec![Some(1.0)]));
+        block
+            .append(
+                DataBlock::new()
+                    .column("amount", ColumnData::Text(vec![Some(String::from("n/a"))]))
+                    .column("name", ColumnData::Text(vec![None])),
+            )
+            .unwrap();
+
+        assert_eq!(block.row_count(), 2);
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Text(vec![Some(String::from("1")), Some(String::from("n/a"))])
+        );
+        assert!(block
+            .append(DataBlock::new().column("name", ColumnData::Text(vec![None])))
+            .is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::block::DataBlock;
+use crate::engine::{BlockStream, EngineType, QueryEngine};
+use crate::limits::QueryOptions;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use query::{PreAggregation, QueryBuilder, SchemaChange};
+use serde::Serialize;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+struct Entry {
+    table: String,
+    block: DataBlock,
+    bytes: usize,
+    created: Instant,
+}
+
+///缓存的命中统计
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+///进程内的查询结果缓存, 按引擎和查询模型缓存, 超过 `ttl` 过期,
+///超过 `max_bytes` 时淘汰最早的结果
+pub struct QueryCache {
+    entries: DashMap<String, Entry>,
+    ///每张表被清除缓存的次数, 查询期间变化时结果不缓存
+    epochs: DashMap<String, u64>,
+    cleared: AtomicU64,
+    ttl: Duration,
+    max_bytes: usize,
+    bytes: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl QueryCache {
+    pub fn new(ttl: Duration, max_bytes: usize) -> Self {
+        QueryCache {
+            entries: DashMap::new(),
+            epochs: DashMap::new(),
+            cleared: AtomicU64::new(0),
+            ttl,
+            max_bytes,
+            bytes: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    ///查询模型序列化后作为缓存的键, 同一个查询的json总是相同的
+    pub fn key(engine_name: &str, query_builder: &QueryBuilder) -> String {
+        let query = serde_json::to_string(query_builder).unwrap_or_default();
+        format!("{}/{}", engine_name, query)
+    }
+
+    pub fn get(&self, key: &str) -> Option<DataBlock> {
+        let expired = match self.entries.get(key) {
+            Some(entry) if entry.created.elapsed() <= self.ttl => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Some(entry.block.clone());
+            }
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            self.remove(key);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    ///表的缓存被清除的次数, 在查询前读取并传给 `put`
+    pub fn epoch(&self, table: &str) -> u64 {
+        let epoch = self.epochs.get(table).map(|e| *e).unwrap_or_default();
+        self.cleared.load(Ordering::SeqCst) + epoch
+    }
+
+    ///超过内存预算的结果不缓存, `epoch` 之后表的缓存被清除过时也不缓存,
+    ///否则会缓存清除前查到的旧结果
+    pub fn put(&self, key: &str, table: &str, epoch: u64, block: DataBlock) {
+        let bytes = block.byte_size();
+        if bytes > self.max_bytes || self.epoch(table) != epoch {
+            return;
+        }
+        self.remove(key);
+        while self.bytes.load(Ordering::Relaxed) + bytes > self.max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|entry| entry.created)
+                .map(|entry| entry.key().clone());
+            match oldest {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        let entry = Entry {
+            table: table.to_string(),
+            block,
+            bytes,
+            created: Instant::now(),
+        };
+        if let Some(old) = self.entries.insert(key.to_string(), entry) {
+            self.bytes.fetch_sub(old.bytes, Ordering::Relaxed);
+        }
+        // invalidated while the entry was being inserted
+        if self.epoch(table) != epoch {
+            self.remove(key);
+        }
+    }
+
+    ///表的数据或结构变化后, 删除该表的所有结果
+    pub fn invalidate(&self, table: &str) {
+        *self.epochs.entry(table.to_string()).or_insert(0) += 1;
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.table == table)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.cleared.fetch_add(1, Ordering::SeqCst);
+        self.entries.clear();
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.len(),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        if let Some((_, entry)) = self.entries.remove(key) {
+            self.bytes.fetch_sub(entry.bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+///先查缓存的引擎, 结构变更和写入后删除相关表的缓存. 流式查询不缓存
+pub struct CachedEngine {
+    name: String,
+    engine: Arc<dyn QueryEngine>,
+    cache: Arc<QueryCache>,
+}
+
+impl CachedEngine {
+    pub fn new(name: &str, engine: Arc<dyn QueryEngine>, cache: Arc<QueryCache>) -> Self {
+        CachedEngine {
+            name: name.to_string(),
+            engine,
+            cache,
+        }
+    }
+}
+
+#[async_trait]
+impl QueryEngine for CachedEngine {
+    fn engine_type(&self) -> EngineType {
+        self.engine.engine_type()
+    }
+
+    async fn ping(&self) -> Result<Duration, Box<dyn Error>> {
+        self.engine.ping().await
+    }
+
+    ///不知道ddl修改了哪些表, 清空全部缓存
+    async fn ddl(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
+        let result = self.engine.ddl(ddl).await;
+        self.cache.clear();
+        result
+    }
+
+    async fn alter_schema(&self, change: SchemaChange) -> Result<(), Box<dyn Error>> {
+        let tables: Vec<String> = change.tables().into_iter().map(String::from).collect();
+        let result = self.engine.alter_schema(change).await;
+        for table in tables {
+            self.cache.invalidate(&table);
+        }
+        result
+    }
+
+    ///写入失败时也可能写入了部分数据, 同样清除缓存
+    async fn insert(&self, table: &str, block: DataBlock) -> Result<(), Box<dyn Error>> {
+        let result = self.engine.insert(table, block).await;
+        self.cache.invalidate(table);
+        result
+    }
+
+    async fn query(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<DataBlock, Box<dyn Error>> {
+        let key = QueryCache::key(&self.name, &query_builder);
+        if let Some(block) = self.cache.get(&key) {
+            options.check(&block)?;
+            return Ok(block);
+        }
+        let table = query_builder.get_table().to_string();
+        let epoch = self.cache.epoch(&table);
+        let block = self.engine.query(query_builder, options).await?;
+        self.cache.put(&key, &table, epoch, block.clone());
+        Ok(block)
+    }
+
+    async fn stream(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<BlockStream, Box<dyn Error>> {
+        self.engine.stream(query_builder, options).await
+    }
+
+    async fn cancel(&self, query_id: &str) -> Result<bool, Box<dyn Error>> {
+        self.engine.cancel(query_id).await
+    }
+
+    ///汇总表与源表的结果相同, 不需要清除缓存
+    async fn create_pre_aggregation(&self, pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        self.engine.create_pre_aggregation(pre).await
+    }
+
+    async fn register_pre_aggregation(&self, pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        self.engine.register_pre_aggregation(pre).await
+    }
+
+    async fn drop_pre_aggregation(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        self.engine.drop_pre_aggregation(name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::ColumnData;
+    use std::sync::atomic::AtomicUsize;
+
+    fn block(rows: usize) -> DataBlock {
+        DataBlock::new().column("amount", ColumnData::Number(vec![Some(1.0); rows]))
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = QueryCache::new(Duration::from_secs(60), 100);
+        assert_eq!(cache.get("q1"), None);
+        cache.put("q1", "t_1", 0, block(4));
+        cache.put("q2", "t_2", 0, block(4));
+        assert_eq!(cache.get("q1"), Some(block(4)));
+
+        // 超过预算时淘汰最早的结果
+        cache.put("q3", "t_1", 0, block(8));
+        assert_eq!(cache.get("q1"), None);
+        assert_eq!(cache.get("q2"), Some(block(4)));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                entries: 2,
+                bytes: 96,
+            }
+        );
+
+        cache.put("q4", "t_1", 0, block(20));
+        assert_eq!(cache.stats().entries, 2);
+
+        cache.invalidate("t_1");
+        assert_eq!(cache.get("q3"), None);
+        assert_eq!(cache.stats().bytes, 32);
+
+        // 查询期间表被清除过缓存, 结果不缓存
+        assert_eq!(cache.epoch("t_1"), 1);
+        cache.put("q3", "t_1", 0, block(4));
+        assert_eq!(cache.get("q3"), None);
+        cache.put("q3", "t_1", 1, block(4));
+        assert_eq!(cache.get("q3"), Some(block(4)));
+        cache.clear();
+        cache.put("q3", "t_1", 1, block(4));
+        assert_eq!(cache.stats().entries, 0);
+
+        let cache = QueryCache::new(Duration::from_millis(0), 100);
+        cache.put("q1", "t_1", 0, block(1));
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(cache.get("q1"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    struct CountingEngine(AtomicUsize);
+
+    #[async_trait]
+    impl QueryEngine for CountingEngine {
+        fn engine_type(&self) -> EngineType {
+            EngineType::Memory
+        }
+
+        async fn ddl(&self, _ddl: &str) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn alter_schema(&self, _change: SchemaChange) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn insert(&self, _table: &str, _block: DataBlock) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            _query_builder: QueryBuilder,
+            _options: &QueryOptions,
+        ) -> Result<DataBlock, Box<dyn Error>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(block(2))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cached_engine() -> Result<(), Box<dyn Error>> {
+        let engine = Arc::new(CountingEngine(AtomicUsize::new(0)));
+        let cache = Arc::new(QueryCache::new(Duration::from_secs(60), 1024));
+        let cached = CachedEngine::new("default", engine.clone(), cache.clone());
+        let qb = || QueryBuilder::new().table(String::from("t_1"));
+
+        cached.query(qb(), &QueryOptions::new()).await?;
+        cached.query(qb(), &QueryOptions::new()).await?;
+        assert_eq!(engine.0.load(Ordering::Relaxed), 1);
+        assert!(cached
+            .query(qb(), &QueryOptions::new().max_rows(1))
+            .await
+            .is_err());
+
+        cached
+            .alter_schema(SchemaChange::TruncateTable {
+                table: String::from("t_1"),
+            })
+            .await?;
+        cached.query(qb(), &QueryOptions::new()).await?;
+        assert_eq!(engine.0.load(Ordering::Relaxed), 2);
+
+        cached.insert("t_1", block(1)).await?;
+        cached.query(qb(), &QueryOptions::new()).await?;
+        assert_eq!(engine.0.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+}
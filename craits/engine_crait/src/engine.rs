@@ -1,6 +1,15 @@
+use crate::block::DataBlock;
+use crate::limits::QueryOptions;
 use async_trait::async_trait;
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use query::{PreAggregation, QueryBuilder, QueryError, SchemaChange, ValidationError};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::time::Duration;
+
+///流式查询的结果, 错误会跨线程传给http响应
+pub type BlockStream = BoxStream<'static, Result<DataBlock, Box<dyn Error + Send + Sync>>>;
 
 #[async_trait]
 pub trait Engine {
@@ -9,12 +18,83 @@ pub trait Engine {
     async fn ddl_str(&self, ddl: &str) -> Result<(), Box<dyn Error>>;
 
     async fn query_str(&self, sql: &str) -> Result<Self::Block, Box<dyn Error>>;
+
+    async fn query_qb(&self, query_builder: QueryBuilder) -> Result<Self::Block, Box<dyn Error>>;
+}
+
+///按数据集路由时使用的引擎, 结果统一转换为 `DataBlock`
+#[async_trait]
+pub trait QueryEngine: Send + Sync {
+    fn engine_type(&self) -> EngineType;
+
+    ///检查引擎是否可用, 返回往返时间, 嵌入式引擎总是可用
+    async fn ping(&self) -> Result<Duration, Box<dyn Error>> {
+        Ok(Duration::from_secs(0))
+    }
+
+    async fn ddl(&self, ddl: &str) -> Result<(), Box<dyn Error>>;
+
+    ///修改数据集的物理表
+    async fn alter_schema(&self, change: SchemaChange) -> Result<(), Box<dyn Error>>;
+
+    ///向数据集的物理表追加数据, 按列名写入
+    async fn insert(&self, _table: &str, _block: DataBlock) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(unsupported(self.engine_type(), "insert")))
+    }
+
+    ///超过 `options` 的限制时返回 `LimitError`
+    async fn query(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<DataBlock, Box<dyn Error>>;
+
+    ///按批返回结果, 默认查询完成后一次返回
+    async fn stream(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<BlockStream, Box<dyn Error>> {
+        let block = self.query(query_builder, options).await?;
+        Ok(stream::once(future::ready(Ok(block))).boxed())
+    }
+
+    ///取消正在执行的查询, 返回是否找到了该查询
+    async fn cancel(&self, _query_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+
+    ///建好汇总表后, 能回答的查询改写到汇总表上执行
+    async fn create_pre_aggregation(&self, _pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(unsupported(self.engine_type(), "pre-aggregation")))
+    }
+
+    ///使用已经建好的汇总表, 用于重启后恢复
+    async fn register_pre_aggregation(&self, _pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(unsupported(self.engine_type(), "pre-aggregation")))
+    }
+
+    ///删除汇总表, 返回是否找到了该预聚合
+    async fn drop_pre_aggregation(&self, _name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+}
+
+fn unsupported(engine_type: EngineType, feature: &str) -> ValidationError {
+    ValidationError {
+        errors: vec![QueryError::Unsupported {
+            dialect: engine_type.get_type(),
+            feature: feature.to_string(),
+        }],
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Copy, Clone)]
+#[derive(Debug, Deserialize, Serialize, Copy, Clone, PartialEq, Eq)]
 pub enum EngineType {
     ClickHouse,
     ElasticSearch,
+    Sqlite,
+    Memory,
 }
 
 impl EngineType {
@@ -22,6 +102,18 @@ impl EngineType {
         match self {
             EngineType::ClickHouse => "ClickHouse".to_string(),
             EngineType::ElasticSearch => "ElasticSearch".to_string(),
+            EngineType::Sqlite => "Sqlite".to_string(),
+            EngineType::Memory => "Memory".to_string(),
+        }
+    }
+
+    pub fn from_type(engine_type: &str) -> Option<EngineType> {
+        match engine_type {
+            "ClickHouse" => Some(EngineType::ClickHouse),
+            "ElasticSearch" => Some(EngineType::ElasticSearch),
+            "Sqlite" => Some(EngineType::Sqlite),
+            "Memory" => Some(EngineType::Memory),
+            _ => None,
         }
     }
 }
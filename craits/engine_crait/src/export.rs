@@ -0,0 +1,150 @@
+use crate::block::DataBlock;
+use crate::engine::BlockStream;
+use futures::stream::{Stream, StreamExt};
+use query::Value;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+///导出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Csv,
+    ///每行一个json对象
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "csv" => Some(ExportFormat::Csv),
+            "jsonl" => Some(ExportFormat::JsonLines),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::JsonLines => "application/x-ndjson",
+        }
+    }
+
+    ///编码一批结果, 第一批的csv带表头
+    pub fn encode(&self, block: &DataBlock, first: bool) -> String {
+        let names: Vec<&str> = block.columns().iter().map(|c| c.name.as_str()).collect();
+        let mut out = String::new();
+        match self {
+            ExportFormat::Csv => {
+                if first {
+                    let header: Vec<String> = names.iter().map(|name| csv_cell(name)).collect();
+                    out.push_str(&header.join(","));
+                    out.push('\n');
+                }
+                for i in 0..block.row_count() {
+                    let row: Vec<String> = block
+                        .row(i)
+                        .into_iter()
+                        .map(|value| match value {
+                            Value::Null => String::new(),
+                            Value::Number(n) => n.to_string(),
+                            Value::Text(s) => csv_cell(&s),
+                        })
+                        .collect();
+                    out.push_str(&row.join(","));
+                    out.push('\n');
+                }
+            }
+            ExportFormat::JsonLines => {
+                // 按列的顺序输出, serde_json::Map会按键排序
+                let keys: Vec<String> = names
+                    .iter()
+                    .map(|name| serde_json::Value::from(*name).to_string())
+                    .collect();
+                for i in 0..block.row_count() {
+                    let fields: Vec<String> = keys
+                        .iter()
+                        .zip(block.row(i))
+                        .map(|(key, value)| {
+                            let value = match value {
+                                Value::Null => serde_json::Value::Null,
+                                Value::Number(n) => serde_json::Number::from_f64(n)
+                                    .map(serde_json::Value::Number)
+                                    .unwrap_or(serde_json::Value::Null),
+                                Value::Text(s) => serde_json::Value::String(s),
+                            };
+                            format!("{}:{}", key, value)
+                        })
+                        .collect();
+                    out.push('{');
+                    out.push_str(&fields.join(","));
+                    out.push_str("}\n");
+                }
+            }
+        }
+        out
+    }
+}
+
+///含有分隔符, 引号或换行的值加引号
+fn csv_cell(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+///把结果流编码为导出文件的分块
+pub fn export(
+    blocks: BlockStream,
+    format: ExportFormat,
+) -> impl Stream<Item = Result<String, Box<dyn Error + Send + Sync>>> + Send + 'static {
+    blocks
+        .enumerate()
+        .map(move |(i, block)| block.map(|block| format.encode(&block, i == 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::ColumnData;
+    use futures::stream;
+
+    fn block() -> DataBlock {
+        DataBlock::new()
+            .column(
+                "name",
+                ColumnData::Text(vec![Some(String::from("a,\"b\"")), None]),
+            )
+            .column("amount", ColumnData::Number(vec![Some(1.5), Some(2.0)]))
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            ExportFormat::Csv.encode(&block(), true),
+            "name,amount\n\"a,\"\"b\"\"\",1.5\n,2\n"
+        );
+        assert_eq!(
+            ExportFormat::Csv.encode(&block(), false),
+            "\"a,\"\"b\"\"\",1.5\n,2\n"
+        );
+        assert_eq!(
+            ExportFormat::JsonLines.encode(&block(), true),
+            "{\"name\":\"a,\\\"b\\\"\",\"amount\":1.5}\n{\"name\":null,\"amount\":2.0}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let blocks: BlockStream =
+            stream::iter(vec![Ok(block()), Err("connection reset".into())]).boxed();
+        let chunks: Vec<_> = export(blocks, ExportFormat::Csv).collect().await;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].as_ref().unwrap().starts_with("name,amount\n"));
+        assert_eq!(
+            chunks[1].as_ref().unwrap_err().to_string(),
+            "connection reset"
+        );
+    }
+}
@@ -1,3 +1,15 @@
+mod batch;
+mod block;
+mod cache;
 mod engine;
+mod export;
+mod limits;
+mod registry;
 
-pub use self::engine::{Engine, EngineType};
+pub use self::batch::{BatchExecutor, BatchQuery, BatchResult, DEFAULT_CONCURRENCY};
+pub use self::block::{Column, ColumnData, DataBlock};
+pub use self::cache::{CacheStats, CachedEngine, QueryCache};
+pub use self::engine::{BlockStream, Engine, EngineType, QueryEngine};
+pub use self::export::{export, ExportFormat};
+pub use self::limits::{LimitError, QueryOptions};
+pub use self::registry::{EngineRegistry, DEFAULT_ENGINE};
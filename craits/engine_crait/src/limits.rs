@@ -0,0 +1,120 @@
+use crate::block::DataBlock;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+///单次查询的限制, `None` 表示不限制
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryOptions {
+    ///用于取消查询, 为空时查询不能取消
+    pub query_id: String,
+    pub timeout: Option<Duration>,
+    pub max_rows: Option<usize>,
+    ///按 `DataBlock::byte_size` 估算
+    pub max_bytes: Option<usize>,
+}
+
+impl QueryOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query_id(mut self, query_id: &str) -> Self {
+        self.query_id = query_id.to_string();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    ///检查查询结果是否超过限制
+    pub fn check(&self, block: &DataBlock) -> Result<(), LimitError> {
+        if let Some(max_rows) = self.max_rows {
+            if block.row_count() > max_rows {
+                return Err(LimitError::TooManyRows(max_rows));
+            }
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            if block.byte_size() > max_bytes {
+                return Err(LimitError::TooManyBytes(max_bytes));
+            }
+        }
+        Ok(())
+    }
+}
+
+///查询超过限制或被取消
+#[derive(Debug, Clone, PartialEq)]
+pub enum LimitError {
+    Timeout(Duration),
+    TooManyRows(usize),
+    TooManyBytes(usize),
+    Cancelled(String),
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Timeout(timeout) => {
+                write!(f, "query timed out after {}ms", timeout.as_millis())
+            }
+            LimitError::TooManyRows(max_rows) => {
+                write!(f, "query result exceeds the limit of {} rows", max_rows)
+            }
+            LimitError::TooManyBytes(max_bytes) => {
+                write!(f, "query result exceeds the limit of {} bytes", max_bytes)
+            }
+            LimitError::Cancelled(query_id) => write!(f, "query {} was cancelled", query_id),
+        }
+    }
+}
+
+impl Error for LimitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::ColumnData;
+
+    #[test]
+    fn test_check() {
+        let block = DataBlock::new()
+            .column("amount", ColumnData::Number(vec![Some(1.0), None]))
+            .column(
+                "name",
+                ColumnData::Text(vec![Some(String::from("foo")), None]),
+            );
+        assert_eq!(block.byte_size(), 19);
+
+        assert!(QueryOptions::new().check(&block).is_ok());
+        assert!(QueryOptions::new()
+            .max_rows(2)
+            .max_bytes(19)
+            .check(&block)
+            .is_ok());
+        assert_eq!(
+            QueryOptions::new().max_rows(1).check(&block),
+            Err(LimitError::TooManyRows(1))
+        );
+        assert_eq!(
+            QueryOptions::new()
+                .max_bytes(10)
+                .check(&block)
+                .unwrap_err()
+                .to_string(),
+            "query result exceeds the limit of 10 bytes"
+        );
+    }
+}
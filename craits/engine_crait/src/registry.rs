@@ -0,0 +1,127 @@
+use crate::cache::{CachedEngine, QueryCache};
+use crate::engine::{EngineType, QueryEngine};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+///数据集未指定连接时使用的连接名
+pub const DEFAULT_ENGINE: &str = "default";
+
+///启动时配置的引擎连接, 按名称查找
+#[derive(Clone, Default)]
+pub struct EngineRegistry {
+    engines: HashMap<String, Arc<dyn QueryEngine>>,
+    cache: Option<Arc<QueryCache>>,
+}
+
+impl EngineRegistry {
+    pub fn new() -> Self {
+        EngineRegistry {
+            engines: HashMap::new(),
+            cache: None,
+        }
+    }
+
+    pub fn register(mut self, name: &str, engine: Arc<dyn QueryEngine>) -> Self {
+        let engine: Arc<dyn QueryEngine> = match &self.cache {
+            Some(cache) => Arc::new(CachedEngine::new(name, engine, cache.clone())),
+            None => engine,
+        };
+        self.engines.insert(name.to_string(), engine);
+        self
+    }
+
+    ///缓存所有引擎的查询结果, 包括之后注册的引擎
+    pub fn cached(mut self, cache: Arc<QueryCache>) -> Self {
+        for (name, engine) in self.engines.iter_mut() {
+            *engine = Arc::new(CachedEngine::new(name, engine.clone(), cache.clone()));
+        }
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn cache(&self) -> Option<&Arc<QueryCache>> {
+        self.cache.as_ref()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn QueryEngine>> {
+        self.engines.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.engines.keys().collect();
+        names.sort();
+        names
+    }
+
+    ///按数据集绑定的连接查找引擎, 空的连接名使用默认连接, 引擎类型必须一致
+    pub fn route(&self, name: &str, engine_type: &str) -> Result<Arc<dyn QueryEngine>, String> {
+        let name = if name.is_empty() {
+            DEFAULT_ENGINE
+        } else {
+            name
+        };
+        let engine = self
+            .get(name)
+            .ok_or_else(|| format!("engine {} is not registered", name))?;
+        match EngineType::from_type(engine_type) {
+            Some(t) if t == engine.engine_type() => Ok(engine),
+            _ => Err(format!(
+                "engine {} is {}, not {}",
+                name,
+                engine.engine_type().get_type(),
+                engine_type
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::DataBlock;
+    use crate::limits::QueryOptions;
+    use async_trait::async_trait;
+    use query::{QueryBuilder, SchemaChange};
+    use std::error::Error;
+
+    struct NoopEngine(EngineType);
+
+    #[async_trait]
+    impl QueryEngine for NoopEngine {
+        fn engine_type(&self) -> EngineType {
+            self.0
+        }
+
+        async fn ddl(&self, _ddl: &str) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn alter_schema(&self, _change: SchemaChange) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            _query_builder: QueryBuilder,
+            _options: &QueryOptions,
+        ) -> Result<DataBlock, Box<dyn Error>> {
+            Ok(DataBlock::new())
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let registry = EngineRegistry::new()
+            .register(DEFAULT_ENGINE, Arc::new(NoopEngine(EngineType::ClickHouse)))
+            .register("preview", Arc::new(NoopEngine(EngineType::Memory)));
+
+        assert_eq!(registry.names(), vec!["default", "preview"]);
+        assert!(registry.route("", "ClickHouse").is_ok());
+        assert!(registry.route("preview", "Memory").is_ok());
+        assert_eq!(
+            registry.route("preview", "ClickHouse").err().unwrap(),
+            "engine preview is Memory, not ClickHouse"
+        );
+        assert!(registry.route("archive", "ClickHouse").is_err());
+    }
+}
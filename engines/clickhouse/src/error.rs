@@ -0,0 +1,185 @@
+use clickhouse_rs::errors::{DriverError, Error as DriverErrorKind};
+use engine_craits::{LimitError, QueryOptions};
+use query::ValidationError;
+use std::error::Error;
+use std::fmt;
+
+///服务端错误码中可以重试的: 并发过多, 没有空闲连接, socket超时, 网络错误
+const TRANSIENT_CODES: [u32; 4] = [202, 203, 209, 210];
+
+const TIMEOUT_EXCEEDED: u32 = 159;
+const QUERY_WAS_CANCELLED: u32 = 394;
+const TOO_MANY_ROWS_OR_BYTES: u32 = 396;
+
+///ClickHouse引擎的错误, 服务端错误保留错误码
+#[derive(Debug)]
+pub enum ClickHouseError {
+    Server {
+        code: u32,
+        name: String,
+        message: String,
+    },
+    Timeout,
+    Network(String),
+    Query(ValidationError),
+    Limit(LimitError),
+    Other(String),
+}
+
+impl ClickHouseError {
+    ///网络错误和超时可以重试
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ClickHouseError::Server { code, .. } => TRANSIENT_CODES.contains(code),
+            ClickHouseError::Timeout | ClickHouseError::Network(_) => true,
+            ClickHouseError::Query(_) | ClickHouseError::Limit(_) | ClickHouseError::Other(_) => {
+                false
+            }
+        }
+    }
+
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            ClickHouseError::Server { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    ///服务端按 `options` 的settings中断查询时, 转换为对应的 `LimitError`
+    pub fn with_limits(self, options: &QueryOptions) -> Self {
+        let limit = match &self {
+            ClickHouseError::Server { code, message, .. } => match *code {
+                TIMEOUT_EXCEEDED => options.timeout.map(LimitError::Timeout),
+                QUERY_WAS_CANCELLED => Some(LimitError::Cancelled(options.query_id.clone())),
+                TOO_MANY_ROWS_OR_BYTES if message.contains("bytes") => {
+                    options.max_bytes.map(LimitError::TooManyBytes)
+                }
+                TOO_MANY_ROWS_OR_BYTES => options.max_rows.map(LimitError::TooManyRows),
+                _ => None,
+            },
+            _ => None,
+        };
+        match limit {
+            Some(limit) => ClickHouseError::Limit(limit),
+            None => self,
+        }
+    }
+}
+
+impl fmt::Display for ClickHouseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClickHouseError::Server {
+                code,
+                name,
+                message,
+            } => write!(f, "clickhouse error {} ({}): {}", code, name, message),
+            ClickHouseError::Timeout => write!(f, "clickhouse timeout"),
+            ClickHouseError::Network(message) => write!(f, "clickhouse network error: {}", message),
+            ClickHouseError::Query(e) => write!(f, "{}", e),
+            ClickHouseError::Limit(e) => write!(f, "{}", e),
+            ClickHouseError::Other(message) => write!(f, "clickhouse error: {}", message),
+        }
+    }
+}
+
+impl Error for ClickHouseError {}
+
+impl From<DriverErrorKind> for ClickHouseError {
+    fn from(e: DriverErrorKind) -> Self {
+        match e {
+            DriverErrorKind::Server(e) => ClickHouseError::Server {
+                code: e.code,
+                name: e.name,
+                message: e.message,
+            },
+            DriverErrorKind::Driver(DriverError::Timeout) => ClickHouseError::Timeout,
+            DriverErrorKind::Io(e) => ClickHouseError::Network(e.to_string()),
+            DriverErrorKind::Connection(e) => ClickHouseError::Network(e.to_string()),
+            e => ClickHouseError::Other(e.to_string()),
+        }
+    }
+}
+
+impl From<ValidationError> for ClickHouseError {
+    fn from(e: ValidationError) -> Self {
+        ClickHouseError::Query(e)
+    }
+}
+
+impl From<LimitError> for ClickHouseError {
+    fn from(e: LimitError) -> Self {
+        ClickHouseError::Limit(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clickhouse_rs::errors::ServerError;
+    use std::io;
+    use std::time::Duration;
+
+    #[test]
+    fn test_from_driver_error() {
+        let e = ClickHouseError::from(DriverErrorKind::Server(ServerError {
+            code: 60,
+            name: String::from("DB::Exception"),
+            message: String::from("Table default.payment doesn't exist."),
+            stack_trace: String::new(),
+        }));
+        assert_eq!(e.code(), Some(60));
+        assert!(!e.is_transient());
+        assert_eq!(
+            e.to_string(),
+            "clickhouse error 60 (DB::Exception): Table default.payment doesn't exist."
+        );
+
+        let e = ClickHouseError::from(DriverErrorKind::Io(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "reset",
+        )));
+        assert!(e.is_transient());
+        assert!(
+            ClickHouseError::from(DriverErrorKind::Driver(DriverError::Timeout)).is_transient()
+        );
+    }
+
+    #[test]
+    fn test_with_limits() {
+        let server_error = |code: u32, message: &str| {
+            ClickHouseError::from(DriverErrorKind::Server(ServerError {
+                code,
+                name: String::from("DB::Exception"),
+                message: message.to_string(),
+                stack_trace: String::new(),
+            }))
+        };
+        let options = QueryOptions::new()
+            .query_id("q1")
+            .timeout(Duration::from_secs(3))
+            .max_rows(1000)
+            .max_bytes(1 << 20);
+
+        let e = server_error(396, "Limit for result exceeded, max rows: 1.00 thousand")
+            .with_limits(&options);
+        assert_eq!(e.to_string(), "query result exceeds the limit of 1000 rows");
+        let e = server_error(396, "Limit for result exceeded, max bytes: 1.00 MiB")
+            .with_limits(&options);
+        assert!(matches!(
+            e,
+            ClickHouseError::Limit(LimitError::TooManyBytes(1_048_576))
+        ));
+        let e = server_error(159, "Timeout exceeded: elapsed 3.001 seconds").with_limits(&options);
+        assert!(!e.is_transient());
+        assert_eq!(e.to_string(), "query timed out after 3000ms");
+        let e = server_error(394, "Query was cancelled").with_limits(&options);
+        assert_eq!(e.to_string(), "query q1 was cancelled");
+        assert_eq!(
+            server_error(60, "Table doesn't exist")
+                .with_limits(&options)
+                .code(),
+            Some(60)
+        );
+    }
+}
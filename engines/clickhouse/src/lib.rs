@@ -1,12 +1,37 @@
+mod config;
+mod error;
+mod pre_aggregation;
+
+pub use self::config::ClickHouseConfig;
+pub use self::error::ClickHouseError;
+
 use async_trait::async_trait;
-use clickhouse_rs::types::Complex;
+use chrono::{DateTime, NaiveDate};
+use clickhouse_rs::types::{ColumnType, Complex, Query, SqlType};
 use clickhouse_rs::{Block, Pool};
-use engine_craits::Engine;
-use query::{MeasureFn, QueryBuilder};
+use engine_craits::{
+    BlockStream, ColumnData, DataBlock, Engine, EngineType, LimitError, QueryEngine, QueryOptions,
+};
+use futures::stream::{self, StreamExt};
+use query::{
+    is_identifier, ClickHouseDialect, PreAggregation, QueryBuilder, QueryError, SchemaChange,
+    SqlDialect, ValidationError, Value,
+};
 use std::error::Error;
+use std::future::Future;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+///流式查询时缓存的批数, 消费方跟不上时暂停读取
+const STREAM_BUFFER: usize = 4;
 
 pub struct ClickHouseEngine {
     pool: Pool,
+    retries: u32,
+    retry_backoff: Duration,
+    ///按汇总表的行数从小到大排列
+    pre_aggregations: RwLock<Vec<(u64, PreAggregation)>>,
 }
 
 #[async_trait]
@@ -14,91 +39,467 @@ impl Engine for ClickHouseEngine {
     type Block = Block<Complex>;
 
     async fn ddl_str(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
-        let mut client = self.pool.get_handle().await?;
-        client.execute(ddl).await?;
-        Ok(())
+        Ok(self.execute(ddl).await?)
     }
 
     async fn query_str(&self, sql: &str) -> Result<Block<Complex>, Box<dyn Error>> {
-        let mut client = self.pool.get_handle().await?;
-        let block = client.query(sql).fetch_all().await?;
+        Ok(self.fetch_all(sql).await?)
+    }
+
+    async fn query_qb(
+        &self,
+        query_builder: QueryBuilder,
+    ) -> Result<Block<Complex>, Box<dyn Error>> {
+        let sql = self.transfer_to_sql(&query_builder)?;
+        let block = self.query_str(sql.as_str()).await?;
         Ok(block)
     }
 }
 
+#[async_trait]
+impl QueryEngine for ClickHouseEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::ClickHouse
+    }
+
+    async fn ping(&self) -> Result<Duration, Box<dyn Error>> {
+        Ok(ClickHouseEngine::ping(self).await?)
+    }
+
+    async fn ddl(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
+        self.ddl_str(ddl).await
+    }
+
+    async fn alter_schema(&self, change: SchemaChange) -> Result<(), Box<dyn Error>> {
+        Ok(ClickHouseEngine::alter_schema(self, &change).await?)
+    }
+
+    ///插入失败时不重试, 避免重复写入
+    async fn insert(&self, table: &str, block: DataBlock) -> Result<(), Box<dyn Error>> {
+        if block.row_count() == 0 {
+            return Ok(());
+        }
+        Ok(self.execute(&insert_sql(table, &block)?).await?)
+    }
+
+    async fn query(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<DataBlock, Box<dyn Error>> {
+        let sql = self.transfer_to_sql(&query_builder)?;
+        let block = to_data_block(&self.fetch_with(&sql, options).await?)?;
+        options.check(&block)?;
+        Ok(block)
+    }
+
+    async fn stream(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<BlockStream, Box<dyn Error>> {
+        let sql = self.transfer_to_sql(&query_builder)?;
+        Ok(self.stream_sql(&sql, options))
+    }
+
+    async fn cancel(&self, query_id: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(ClickHouseEngine::cancel(self, query_id).await?)
+    }
+
+    async fn create_pre_aggregation(&self, pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        Ok(ClickHouseEngine::create_pre_aggregation(self, pre).await?)
+    }
+
+    async fn register_pre_aggregation(&self, pre: PreAggregation) -> Result<(), Box<dyn Error>> {
+        Ok(ClickHouseEngine::register_pre_aggregation(self, pre).await?)
+    }
+
+    async fn drop_pre_aggregation(&self, name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(ClickHouseEngine::drop_pre_aggregation(self, name).await?)
+    }
+}
+
 impl ClickHouseEngine {
     pub fn new(database_url: &str) -> Self {
         let pool = Pool::new(database_url);
-        ClickHouseEngine { pool }
+        let config = ClickHouseConfig::default();
+        ClickHouseEngine {
+            pool,
+            retries: config.retries,
+            retry_backoff: config.retry_backoff(),
+            pre_aggregations: RwLock::new(vec![]),
+        }
+    }
+
+    pub fn from_config(config: &ClickHouseConfig) -> Result<Self, ClickHouseError> {
+        let pool = Pool::new(config.to_options()?);
+        Ok(ClickHouseEngine {
+            pool,
+            retries: config.retries,
+            retry_backoff: config.retry_backoff(),
+            pre_aggregations: RwLock::new(vec![]),
+        })
+    }
+
+    ///检查服务是否可用, 返回往返时间
+    pub async fn ping(&self) -> Result<Duration, ClickHouseError> {
+        retry(self.retries, self.retry_backoff, || async {
+            let start = Instant::now();
+            let mut client = self.pool.get_handle().await?;
+            client.ping().await?;
+            Ok(start.elapsed())
+        })
+        .await
     }
 
-    pub async fn insert_block(&self, table_name: &str, block: Block) -> Result<(), Box<dyn Error>> {
+    ///DDL不一定是幂等的, 不重试
+    pub async fn execute(&self, ddl: &str) -> Result<(), ClickHouseError> {
         let mut client = self.pool.get_handle().await?;
-        client.insert(table_name, block).await?;
+        client.execute(ddl).await?;
         Ok(())
     }
 
-    fn do_transfer_to_sql<T>(&self, fields: Vec<T>, call: Box<dyn Fn(&T) -> String>) -> String {
-        let mut d_fields = String::new();
+    ///网络错误时按配置重试
+    pub async fn fetch_all(&self, sql: &str) -> Result<Block<Complex>, ClickHouseError> {
+        retry(self.retries, self.retry_backoff, || async {
+            let mut client = self.pool.get_handle().await?;
+            Ok(client.query(sql).fetch_all().await?)
+        })
+        .await
+    }
 
-        fields.iter().for_each(|x| {
-            let s = call(x);
-            d_fields.push_str(&(s + ","));
+    ///按 `options` 限制查询, 超时后取消服务端的查询
+    pub async fn fetch_with(
+        &self,
+        sql: &str,
+        options: &QueryOptions,
+    ) -> Result<Block<Complex>, ClickHouseError> {
+        let sql = with_settings(sql, options);
+        let fetch = retry(self.retries, self.retry_backoff, || async {
+            let mut client = self.pool.get_handle().await?;
+            let query = Query::new(&sql).id(&options.query_id);
+            Ok(client.query(query).fetch_all().await?)
         });
-        d_fields.pop();
-        d_fields
+        let result = match options.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, fetch).await {
+                Ok(result) => result,
+                Err(_) => {
+                    if !options.query_id.is_empty() {
+                        self.cancel(&options.query_id).await.ok();
+                    }
+                    Err(LimitError::Timeout(timeout).into())
+                }
+            },
+            None => fetch.await,
+        };
+        result.map_err(|e| e.with_limits(options))
     }
 
-    fn transfer_to_sql(&self, mut qb: QueryBuilder) -> String {
-        let rows_and_cols = qb.get_rows_and_cols();
+    ///按服务端返回的批次读取结果, 结果流被丢弃或超过限制时取消查询.
+    ///已经返回的批次不能撤回, 所以不重试
+    pub fn stream_sql(&self, sql: &str, options: &QueryOptions) -> BlockStream {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let pool = self.pool.clone();
+        let sql = with_settings(sql, options);
+        let options = options.clone();
+        tokio::spawn(async move {
+            let send = send_blocks(&pool, &sql, &options, &tx);
+            let result = match options.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, send)
+                    .await
+                    .unwrap_or_else(|_| Err(LimitError::Timeout(timeout).into())),
+                None => send.await,
+            };
+            if let Err(e) = result {
+                let e = e.with_limits(&options);
+                if matches!(e, ClickHouseError::Limit(_)) && !options.query_id.is_empty() {
+                    kill_query(&pool, &options.query_id).await.ok();
+                }
+                tx.send(Err(Box::new(e))).await.ok();
+            }
+        });
+        stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|block| (block, rx))
+        })
+        .boxed()
+    }
 
-        let select = self.do_transfer_to_sql(
-            rows_and_cols.to_vec(),
-            Box::new(|d| d.field.field_name.clone()),
-        );
+    ///返回是否找到了正在执行的查询
+    pub async fn cancel(&self, query_id: &str) -> Result<bool, ClickHouseError> {
+        kill_query(&self.pool, query_id).await
+    }
 
-        let group = select.clone();
-
-        let select = format!("select {}", select);
-        // println!("select: {}", select);
-
-        let meas = self.do_transfer_to_sql(
-            qb.get_meas().to_vec(),
-            Box::new(|d| {
-                let f = d.field.field_name.clone();
-                match d.measure_type {
-                    MeasureFn::SUM => format!("sum({}) as {}", f, f),
-                    MeasureFn::MAX => format!("max({}) as {}", f, f),
-                    MeasureFn::MIN => format!("min({}) as {}", f, f),
-                    MeasureFn::AVG => format!("avg({}) as {}", f, f),
-                    MeasureFn::COUNT => format!("count({}) as {}", f, f),
+    ///修改表结构, 删除依赖被修改字段的汇总表, 清空表时同时清空汇总表
+    pub async fn alter_schema(&self, change: &SchemaChange) -> Result<(), ClickHouseError> {
+        for pre in self.pre_aggregations() {
+            if pre.is_broken_by(change) {
+                self.drop_pre_aggregation(&pre.name).await?;
+            }
+        }
+        self.execute(&change.to_sql(&ClickHouseDialect)?).await?;
+        if let SchemaChange::TruncateTable { table } = change {
+            for pre in self.pre_aggregations() {
+                if pre.table == *table {
+                    let truncate = SchemaChange::TruncateTable { table: pre.name };
+                    self.execute(&truncate.to_sql(&ClickHouseDialect)?).await?;
                 }
-            }),
+            }
+        }
+        Ok(())
+    }
+
+    ///创建汇总表和物化视图并回填数据, 之后的查询自动改写
+    pub async fn create_pre_aggregation(&self, pre: PreAggregation) -> Result<(), ClickHouseError> {
+        for sql in pre_aggregation::create_sql(&pre)? {
+            self.execute(&sql).await?;
+        }
+        self.register_pre_aggregation(pre).await
+    }
+
+    ///使用已经建好的汇总表, 按当前行数排序
+    pub async fn register_pre_aggregation(
+        &self,
+        pre: PreAggregation,
+    ) -> Result<(), ClickHouseError> {
+        pre.check()?;
+        let sql = format!(
+            "select count() from {}",
+            ClickHouseDialect.quote_identifier(&pre.name)
         );
+        let rows: u64 = self.fetch_all(&sql).await?.get(0, 0)?;
+        let mut pre_aggregations = self.pre_aggregations.write().unwrap();
+        pre_aggregations.retain(|(_, p)| p.name != pre.name);
+        pre_aggregations.push((rows, pre));
+        pre_aggregations.sort_by_key(|(rows, _)| *rows);
+        Ok(())
+    }
+
+    ///返回是否找到了该预聚合
+    pub async fn drop_pre_aggregation(&self, name: &str) -> Result<bool, ClickHouseError> {
+        let pre = {
+            let mut pre_aggregations = self.pre_aggregations.write().unwrap();
+            match pre_aggregations.iter().position(|(_, p)| p.name == name) {
+                Some(index) => pre_aggregations.remove(index).1,
+                None => return Ok(false),
+            }
+        };
+        for sql in pre_aggregation::drop_sql(&pre) {
+            self.execute(&sql).await?;
+        }
+        Ok(true)
+    }
 
-        let table = qb.get_table();
-        let table = format!(" from {}", table);
-        let group = format!(" group by {}", group);
-        let sql = select + "," + &meas + table.as_str() + " " + group.as_str();
-        println!("sql: {}", sql);
-        sql
+    pub fn pre_aggregations(&self) -> Vec<PreAggregation> {
+        let pre_aggregations = self.pre_aggregations.read().unwrap();
+        pre_aggregations.iter().map(|(_, p)| p.clone()).collect()
     }
 
-    pub async fn query_qb(
+    ///插入失败时不重试, 避免重复写入
+    pub async fn insert_block(
         &self,
-        query_builder: QueryBuilder,
-    ) -> Result<Block<Complex>, Box<dyn Error>> {
-        let sql = self.transfer_to_sql(query_builder);
-        let block = self.query_str(sql.as_str()).await?;
-        Ok(block)
+        table_name: &str,
+        block: Block,
+    ) -> Result<(), ClickHouseError> {
+        let mut client = self.pool.get_handle().await?;
+        client.insert(table_name, block).await?;
+        Ok(())
+    }
+
+    ///有能回答查询的汇总表时改写到最小的汇总表
+    fn transfer_to_sql(&self, qb: &QueryBuilder) -> Result<String, ValidationError> {
+        let routed = {
+            let pre_aggregations = self.pre_aggregations.read().unwrap();
+            qb.route(pre_aggregations.iter().map(|(_, p)| p))
+        };
+        Ok(routed
+            .as_ref()
+            .unwrap_or(qb)
+            .to_sql(&ClickHouseDialect)?
+            .sql)
+    }
+}
+
+async fn send_blocks(
+    pool: &Pool,
+    sql: &str,
+    options: &QueryOptions,
+    tx: &mpsc::Sender<Result<DataBlock, Box<dyn Error + Send + Sync>>>,
+) -> Result<(), ClickHouseError> {
+    let mut client = pool.get_handle().await?;
+    let query = Query::new(sql).id(&options.query_id);
+    let mut blocks = client.query(query).stream_blocks();
+    let (mut rows, mut bytes) = (0, 0);
+    while let Some(block) = blocks.next().await {
+        let block = to_data_block(&block?).map_err(|e| ClickHouseError::Other(e.to_string()))?;
+        rows += block.row_count();
+        bytes += block.byte_size();
+        match (options.max_rows, options.max_bytes) {
+            (Some(max_rows), _) if rows > max_rows => {
+                return Err(LimitError::TooManyRows(max_rows).into())
+            }
+            (_, Some(max_bytes)) if bytes > max_bytes => {
+                return Err(LimitError::TooManyBytes(max_bytes).into())
+            }
+            _ => {}
+        }
+        if tx.send(Ok(block)).await.is_err() {
+            // 结果流已被丢弃
+            return Err(LimitError::Cancelled(options.query_id.clone()).into());
+        }
+    }
+    Ok(())
+}
+
+async fn kill_query(pool: &Pool, query_id: &str) -> Result<bool, ClickHouseError> {
+    let sql = format!(
+        "kill query where query_id = {}",
+        ClickHouseDialect.quote_literal(query_id)
+    );
+    let mut client = pool.get_handle().await?;
+    let block = client.query(sql).fetch_all().await?;
+    Ok(block.row_count() > 0)
+}
+
+///追加查询的settings, 结果超过限制时服务端报错而不是截断
+fn with_settings(sql: &str, options: &QueryOptions) -> String {
+    let mut settings = vec![];
+    if let Some(timeout) = options.timeout {
+        // max_execution_time 的单位为秒
+        let seconds = (timeout.as_millis() as u64).div_ceil(1000);
+        settings.push(format!("max_execution_time = {}", seconds.max(1)));
+    }
+    if let Some(max_rows) = options.max_rows {
+        settings.push(format!("max_result_rows = {}", max_rows));
+    }
+    if let Some(max_bytes) = options.max_bytes {
+        settings.push(format!("max_result_bytes = {}", max_bytes));
+    }
+    if settings.is_empty() {
+        return sql.to_string();
+    }
+    if options.max_rows.is_some() || options.max_bytes.is_some() {
+        settings.push(String::from("result_overflow_mode = 'throw'"));
+    }
+    format!("{} settings {}", sql, settings.join(", "))
+}
+
+///可以重试的错误按 `backoff` 翻倍等待后重试, 最多 `retries` 次
+async fn retry<T, F, Fut>(retries: u32, backoff: Duration, mut f: F) -> Result<T, ClickHouseError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ClickHouseError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Err(e) if e.is_transient() && attempt < retries => {
+                tokio::time::sleep(backoff * 2_u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+macro_rules! cells {
+    ($block:expr, $col:expr, $nullable:expr, $t:ty, $map:expr) => {{
+        let mut values = vec![];
+        for row in 0..$block.row_count() {
+            let value: Option<$t> = if $nullable {
+                $block.get(row, $col)?
+            } else {
+                Some($block.get(row, $col)?)
+            };
+            values.push(value.map($map));
+        }
+        values
+    }};
+}
+
+///按列名写入的insert语句, 日期为文本, 由服务端转换
+fn insert_sql(table: &str, block: &DataBlock) -> Result<String, ValidationError> {
+    let dialect = ClickHouseDialect;
+    let mut names = vec![table];
+    names.extend(block.columns().iter().map(|c| c.name.as_str()));
+    if let Some(name) = names.iter().find(|name| !is_identifier(name)) {
+        return Err(ValidationError {
+            errors: vec![QueryError::InvalidIdentifier(name.to_string())],
+        });
+    }
+    let columns: Vec<String> = block
+        .columns()
+        .iter()
+        .map(|c| dialect.quote_identifier(&c.name))
+        .collect();
+    let rows: Vec<String> = (0..block.row_count())
+        .map(|i| {
+            let values: Vec<String> = block
+                .row(i)
+                .into_iter()
+                .map(|value| match value {
+                    Value::Null => String::from("null"),
+                    Value::Number(n) => n.to_string(),
+                    Value::Text(s) => dialect.quote_literal(&s),
+                })
+                .collect();
+            format!("({})", values.join(","))
+        })
+        .collect();
+    Ok(format!(
+        "insert into {} ({}) values {}",
+        dialect.quote_identifier(table),
+        columns.join(","),
+        rows.join(",")
+    ))
+}
+
+///转换为引擎通用的 `DataBlock`, 日期转为 `yyyy-MM-dd` 格式的文本
+pub fn to_data_block<K: ColumnType>(block: &Block<K>) -> Result<DataBlock, Box<dyn Error>> {
+    let mut data_block = DataBlock::new();
+    for (col, column) in block.columns().iter().enumerate() {
+        let sql_type = column.sql_type();
+        let (nullable, sql_type) = match &sql_type {
+            SqlType::Nullable(inner) => (true, *inner),
+            sql_type => (false, sql_type),
+        };
+        let data = match sql_type {
+            SqlType::UInt8 => ColumnData::Number(cells!(block, col, nullable, u8, f64::from)),
+            SqlType::UInt16 => ColumnData::Number(cells!(block, col, nullable, u16, f64::from)),
+            SqlType::UInt32 => ColumnData::Number(cells!(block, col, nullable, u32, f64::from)),
+            SqlType::UInt64 => ColumnData::Number(cells!(block, col, nullable, u64, |v| v as f64)),
+            SqlType::Int8 => ColumnData::Number(cells!(block, col, nullable, i8, f64::from)),
+            SqlType::Int16 => ColumnData::Number(cells!(block, col, nullable, i16, f64::from)),
+            SqlType::Int32 => ColumnData::Number(cells!(block, col, nullable, i32, f64::from)),
+            SqlType::Int64 => ColumnData::Number(cells!(block, col, nullable, i64, |v| v as f64)),
+            SqlType::Float32 => ColumnData::Number(cells!(block, col, nullable, f32, f64::from)),
+            SqlType::Float64 => ColumnData::Number(cells!(block, col, nullable, f64, |v| v)),
+            SqlType::String | SqlType::FixedString(_) => {
+                ColumnData::Text(cells!(block, col, nullable, String, |v| v))
+            }
+            SqlType::Date => ColumnData::Text(cells!(block, col, nullable, NaiveDate, |v| v
+                .format("%Y-%m-%d")
+                .to_string())),
+            SqlType::DateTime(_) => {
+                ColumnData::Text(cells!(block, col, nullable, DateTime<_>, |v| v
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()))
+            }
+            sql_type => {
+                return Err(
+                    format!("unsupported column type {} of {}", sql_type, column.name()).into(),
+                )
+            }
+        };
+        data_block = data_block.column(column.name(), data);
     }
+    Ok(data_block)
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use query::{DataType, Dimension, Field, Measure, Order};
+    use query::{DataType, Dimension, Field, Measure, MeasureFn, Order};
 
     async fn print_row(block: Block<Complex>) -> Result<(), Box<dyn Error>> {
         println!("count:{} ", block.rows().count());
@@ -139,6 +540,80 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_retry() {
+        let mut attempts = 0;
+        let result: Result<(), ClickHouseError> = retry(2, Duration::from_millis(1), || {
+            attempts += 1;
+            async { Err(ClickHouseError::Timeout) }
+        })
+        .await;
+        assert!(matches!(result, Err(ClickHouseError::Timeout)));
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let result = retry(2, Duration::from_millis(1), || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                match attempt {
+                    1 => Err(ClickHouseError::Network(String::from("reset"))),
+                    _ => Ok(attempt),
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+
+        let mut attempts = 0;
+        let result: Result<(), ClickHouseError> = retry(2, Duration::from_millis(1), || {
+            attempts += 1;
+            async { Err(ClickHouseError::Other(String::from("syntax error"))) }
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_with_settings() {
+        let sql = "select `org` from `t_1`";
+        assert_eq!(with_settings(sql, &QueryOptions::new()), sql);
+        assert_eq!(
+            with_settings(
+                sql,
+                &QueryOptions::new()
+                    .timeout(Duration::from_millis(1500))
+                    .max_rows(1000)
+            ),
+            "select `org` from `t_1` settings max_execution_time = 2, max_result_rows = 1000, \
+             result_overflow_mode = 'throw'"
+        );
+    }
+
+    #[test]
+    fn test_to_data_block() -> Result<(), Box<dyn Error>> {
+        let block = Block::new()
+            .column("customer_id", vec![1_u32, 3])
+            .column("amount", vec![Some(2.5_f64), None])
+            .column("account_name", vec!["foo", "bar"]);
+
+        let block = to_data_block(&block)?;
+        assert_eq!(
+            block.get_column("customer_id").unwrap().data,
+            ColumnData::Number(vec![Some(1.0), Some(3.0)])
+        );
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Number(vec![Some(2.5), None])
+        );
+        assert_eq!(
+            block.get_column("account_name").unwrap().data,
+            ColumnData::Text(vec![Some(String::from("foo")), Some(String::from("bar"))])
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_to_sql() -> Result<(), Box<dyn Error>> {
         let f1 = Field::new(String::from("field1"), DataType::Text, String::from("单位"));
@@ -162,13 +637,13 @@ mod tests {
 
         let qb = QueryBuilder::new()
             .table(String::from("table1"))
-            .row(&mut vec![Dimension::new_row(f1), Dimension::new_row(f3)])
-            .col(&mut vec![Dimension::new_col(f2), Dimension::new_col(f4)])
-            .meas(&mut vec![
+            .row(vec![Dimension::new_row(f1.clone()), Dimension::new_row(f3)])
+            .col(vec![Dimension::new_col(f2), Dimension::new_col(f4)])
+            .meas(vec![
                 Measure::new(f5, MeasureFn::MAX),
                 Measure::new(f6.clone(), MeasureFn::SUM),
             ])
-            .order(&mut vec![Order::new(f6)]);
+            .order(vec![Order::new(f6.clone())]);
 
         //  transfer_to_sql1(qb);
         // transfer_to_sql(Box::new(|qb| {}));
@@ -176,12 +651,51 @@ mod tests {
         let database_url = "tcp://10.37.129.9:9000/default?compression=lz4&ping_timeout=42ms";
 
         let ce = ClickHouseEngine::new(database_url);
-        let sql = ce.transfer_to_sql(qb);
-        // println!("sql: {}", sql);
+        let sql = ce.transfer_to_sql(&qb)?;
+        assert_eq!(
+            sql,
+            "select `field1`,`field3`,`field2`,`field4`,`__m0` as `field5`,\
+             `__m1` as `field6` from (select `field1`,`field3`,`field2`,`field4`,\
+             max(`field5`) as `__m0`,sum(`field6`) as `__m1` from `table1` \
+             group by `field1`,`field3`,`field2`,`field4`) as __result order by `__m1` asc"
+        );
+
+        let pre = PreAggregation::new(
+            String::from("table1_by_field1"),
+            String::from("table1"),
+            vec![Dimension::new_row(f1.clone())],
+            vec![Measure::new(f6.clone(), MeasureFn::SUM)],
+        );
+        ce.pre_aggregations.write().unwrap().push((10, pre));
+        let qb = QueryBuilder::new()
+            .table(String::from("table1"))
+            .row(vec![Dimension::new_row(f1)])
+            .meas(vec![Measure::new(f6, MeasureFn::SUM)]);
+        assert_eq!(
+            ce.transfer_to_sql(&qb)?,
+            "select `field1`,`__m0` as `field6` from (select `field1`,\
+             sum(`sum_field6`) as `__m0` from `table1_by_field1` \
+             group by `field1`) as __result"
+        );
 
         Ok(())
     }
 
+    #[test]
+    fn test_insert_sql() {
+        let block = DataBlock::new()
+            .column(
+                "name",
+                ColumnData::Text(vec![Some(String::from("O'Hare")), None]),
+            )
+            .column("amount", ColumnData::Number(vec![Some(1.5), Some(2.0)]));
+        assert_eq!(
+            insert_sql("payment", &block).unwrap(),
+            "insert into `payment` (`name`,`amount`) values ('O\\'Hare',1.5),(null,2)"
+        );
+        assert!(insert_sql("payment; drop table payment", &block).is_err());
+    }
+
     #[tokio::test]
     async fn test_query_qb() -> Result<(), Box<dyn Error>> {
         let f1 = Field::new(
@@ -202,9 +716,9 @@ mod tests {
 
         let qb = QueryBuilder::new()
             .table(String::from("payment1"))
-            .row(&mut vec![Dimension::new_row(f1)])
-            .col(&mut vec![Dimension::new_col(f2)])
-            .meas(&mut vec![Measure::new(f3, MeasureFn::SUM)]);
+            .row(vec![Dimension::new_row(f1)])
+            .col(vec![Dimension::new_col(f2)])
+            .meas(vec![Measure::new(f3, MeasureFn::SUM)]);
 
         let database_url = "tcp://10.37.129.9:9000/default?compression=lz4&ping_timeout=42ms";
         let ce = ClickHouseEngine::new(database_url);
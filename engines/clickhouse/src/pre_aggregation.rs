@@ -0,0 +1,156 @@
+use query::{ClickHouseDialect, MeasureFn, PreAggregation, SqlDialect, ValidationError};
+
+///物化视图名, 写入源表时增量汇总到汇总表
+pub fn view_name(pre: &PreAggregation) -> String {
+    format!("{}_mv", pre.name)
+}
+
+///创建汇总表, 物化视图, 并回填已有数据.
+///回填期间写入源表的数据会被重复汇总, 应在没有写入时创建
+pub fn create_sql(pre: &PreAggregation) -> Result<Vec<String>, ValidationError> {
+    pre.check()?;
+    let dialect = ClickHouseDialect;
+
+    let mut columns = vec![];
+    let mut order_by = vec![];
+    for dimension in &pre.dimensions {
+        let column = dialect.quote_identifier(&dimension.field.field_name);
+        columns.push(format!(
+            "{} {}",
+            column,
+            dialect.column_type(dimension.field.field_type)
+        ));
+        order_by.push(column);
+    }
+    // unmerged parts are merged again by the query, so simple aggregates are enough
+    for measure in &pre.measures {
+        let column_type = match measure.measure_type {
+            MeasureFn::COUNT => String::from("SimpleAggregateFunction(sum, UInt64)"),
+            MeasureFn::SUM => format!(
+                "SimpleAggregateFunction(sum, {})",
+                dialect.column_type(measure.field.field_type)
+            ),
+            _ => format!(
+                "SimpleAggregateFunction({}, {})",
+                format!("{:?}", measure.measure_type).to_lowercase(),
+                dialect.column_type(measure.field.field_type)
+            ),
+        };
+        columns.push(format!(
+            "{} {}",
+            dialect.quote_identifier(&PreAggregation::measure_column(measure)),
+            column_type
+        ));
+    }
+
+    let table = dialect.quote_identifier(&pre.name);
+    let order_by = if order_by.is_empty() {
+        String::from("tuple()")
+    } else {
+        format!("({}) settings allow_nullable_key = 1", order_by.join(","))
+    };
+    let select = pre.source_query().to_sql(&dialect)?.sql;
+    Ok(vec![
+        format!(
+            "create table if not exists {} ({}) engine = AggregatingMergeTree() order by {}",
+            table,
+            columns.join(","),
+            order_by
+        ),
+        format!(
+            "create materialized view if not exists {} to {} as {}",
+            dialect.quote_identifier(&view_name(pre)),
+            table,
+            select
+        ),
+        format!("insert into {} {}", table, select),
+    ])
+}
+
+///先删除物化视图, 避免写入已删除的汇总表
+pub fn drop_sql(pre: &PreAggregation) -> Vec<String> {
+    let dialect = ClickHouseDialect;
+    vec![
+        format!(
+            "drop table if exists {}",
+            dialect.quote_identifier(&view_name(pre))
+        ),
+        format!(
+            "drop table if exists {}",
+            dialect.quote_identifier(&pre.name)
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query::{DataType, DateUnit, Dimension, Field, Measure};
+
+    fn pre_aggregation() -> PreAggregation {
+        let amount = Field::new(
+            String::from("amount"),
+            DataType::Number,
+            String::from("金额"),
+        );
+        PreAggregation::new(
+            String::from("sales_region_month"),
+            String::from("sales"),
+            vec![
+                Dimension::new_row(Field::new(
+                    String::from("region"),
+                    DataType::Text,
+                    String::new(),
+                )),
+                Dimension::new_row(Field::new(
+                    String::from("day"),
+                    DataType::Date,
+                    String::new(),
+                ))
+                .trunc(DateUnit::Month),
+            ],
+            vec![
+                Measure::new(amount.clone(), MeasureFn::SUM),
+                Measure::new(amount.clone(), MeasureFn::COUNT),
+                Measure::new(amount, MeasureFn::MAX),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_create_sql() {
+        let select = "select `region`,`__d1` as `day`,`__m0` as `sum_amount`,\
+                      `__m1` as `count_amount`,`__m2` as `max_amount` from \
+                      (select `region`,toStartOfMonth(`day`) as `__d1`,\
+                      sum(`amount`) as `__m0`,count(`amount`) as `__m1`,\
+                      max(`amount`) as `__m2` from `sales` \
+                      group by `region`,toStartOfMonth(`day`)) as __result";
+        assert_eq!(
+            create_sql(&pre_aggregation()).unwrap(),
+            vec![
+                String::from(
+                    "create table if not exists `sales_region_month` \
+                     (`region` Nullable(String),`day` Nullable(Date),\
+                     `sum_amount` SimpleAggregateFunction(sum, Nullable(Float64)),\
+                     `count_amount` SimpleAggregateFunction(sum, UInt64),\
+                     `max_amount` SimpleAggregateFunction(max, Nullable(Float64))) \
+                     engine = AggregatingMergeTree() order by (`region`,`day`) \
+                     settings allow_nullable_key = 1"
+                ),
+                format!(
+                    "create materialized view if not exists `sales_region_month_mv` \
+                     to `sales_region_month` as {}",
+                    select
+                ),
+                format!("insert into `sales_region_month` {}", select),
+            ]
+        );
+
+        let mut pre = pre_aggregation();
+        pre.measures.push(Measure::new(
+            Field::new(String::from("amount"), DataType::Number, String::new()),
+            MeasureFn::AVG,
+        ));
+        assert!(create_sql(&pre).is_err());
+    }
+}
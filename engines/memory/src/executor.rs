@@ -0,0 +1,454 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use engine_craits::{ColumnData, DataBlock, LimitError};
+use query::{
+    DateUnit, Dimension, Filter, FilterOp, Measure, MeasureFn, OrderType, QueryBuilder, QueryError,
+    Totals, ValidationError, Value,
+};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::Range;
+use std::time::{Duration as StdDuration, Instant};
+
+pub(crate) const ENGINE_NAME: &str = "Memory";
+
+///每处理这么多行检查一次是否超时
+const CHECK_ROWS: usize = 4096;
+
+///分组键, 数字按位比较
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Null,
+    Number(u64),
+    Text(String),
+}
+
+impl From<Value> for Key {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Key::Null,
+            // -0.0 and 0.0 are the same group
+            Value::Number(n) => Key::Number((n + 0.0).to_bits()),
+            Value::Text(s) => Key::Text(s),
+        }
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Null => Value::Null,
+            Key::Number(bits) => Value::Number(f64::from_bits(bits)),
+            Key::Text(s) => Value::Text(s),
+        }
+    }
+}
+
+///度量的中间结果, 分块计算后合并
+#[derive(Debug, Clone)]
+enum Acc {
+    Sum(Option<f64>),
+    Count(u64),
+    Avg(f64, u64),
+    Max(Value),
+    Min(Value),
+}
+
+impl Acc {
+    fn new(measure_fn: MeasureFn) -> Self {
+        match measure_fn {
+            MeasureFn::SUM => Acc::Sum(None),
+            MeasureFn::COUNT => Acc::Count(0),
+            MeasureFn::AVG => Acc::Avg(0.0, 0),
+            MeasureFn::MAX => Acc::Max(Value::Null),
+            MeasureFn::MIN => Acc::Min(Value::Null),
+        }
+    }
+
+    ///和sql一样忽略空值
+    fn add(&mut self, value: Value) {
+        if let Value::Null = value {
+            return;
+        }
+        match self {
+            Acc::Sum(sum) => {
+                if let Value::Number(n) = value {
+                    *sum = Some(sum.unwrap_or(0.0) + n);
+                }
+            }
+            Acc::Count(count) => *count += 1,
+            Acc::Avg(sum, count) => {
+                if let Value::Number(n) = value {
+                    *sum += n;
+                    *count += 1;
+                }
+            }
+            Acc::Max(max) => {
+                if let Value::Null = max {
+                    *max = value;
+                } else if sort_cmp(&value, max) == Ordering::Greater {
+                    *max = value;
+                }
+            }
+            Acc::Min(min) => {
+                if let Value::Null = min {
+                    *min = value;
+                } else if sort_cmp(&value, min) == Ordering::Less {
+                    *min = value;
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn merge(&mut self, other: Acc) {
+        match (self, other) {
+            (Acc::Sum(sum), Acc::Sum(Some(n))) => *sum = Some(sum.unwrap_or(0.0) + n),
+            (Acc::Count(count), Acc::Count(other)) => *count += other,
+            (Acc::Avg(sum, count), Acc::Avg(other_sum, other_count)) => {
+                *sum += other_sum;
+                *count += other_count;
+            }
+            (acc @ Acc::Max(_), Acc::Max(other)) | (acc @ Acc::Min(_), Acc::Min(other)) => {
+                acc.add(other)
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Acc::Sum(sum) => sum.map(Value::Number).unwrap_or(Value::Null),
+            Acc::Count(count) => Value::Number(count as f64),
+            Acc::Avg(_, 0) => Value::Null,
+            Acc::Avg(sum, count) => Value::Number(sum / count as f64),
+            Acc::Max(value) | Acc::Min(value) => value,
+        }
+    }
+}
+
+///分组结果, 按分组第一次出现的顺序保存
+#[derive(Debug, Default)]
+pub(crate) struct Groups {
+    index: HashMap<Vec<Key>, usize>,
+    keys: Vec<Vec<Key>>,
+    accs: Vec<Vec<Acc>>,
+}
+
+impl Groups {
+    fn group(&mut self, key: Vec<Key>, measures: &[(&Measure, &ColumnData)]) -> &mut Vec<Acc> {
+        let index = match self.index.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = self.keys.len();
+                self.index.insert(key.clone(), index);
+                self.keys.push(key);
+                self.accs.push(
+                    measures
+                        .iter()
+                        .map(|(m, _)| Acc::new(m.measure_type))
+                        .collect(),
+                );
+                index
+            }
+        };
+        &mut self.accs[index]
+    }
+
+    ///合并后面分块的结果
+    #[cfg(feature = "parallel")]
+    pub(crate) fn merge(&mut self, other: Groups, measures: &[(&Measure, &ColumnData)]) {
+        for (key, accs) in other.keys.into_iter().zip(other.accs) {
+            for (acc, other) in self.group(key, measures).iter_mut().zip(accs) {
+                acc.merge(other);
+            }
+        }
+    }
+}
+
+///对一个数据块执行查询
+pub(crate) struct Plan<'a> {
+    qb: &'a QueryBuilder,
+    dimensions: Vec<(&'a Dimension, &'a ColumnData)>,
+    pub(crate) measures: Vec<(&'a Measure, &'a ColumnData)>,
+    filters: Vec<(&'a Filter, &'a ColumnData)>,
+    ///查询的截止时间和超时时长
+    deadline: Option<(Instant, StdDuration)>,
+}
+
+impl<'a> Plan<'a> {
+    pub(crate) fn new(qb: &'a QueryBuilder, block: &'a DataBlock) -> Result<Self, ValidationError> {
+        let mut errors = vec![];
+        if !matches!(qb.get_totals(), Totals::None) {
+            errors.push(unsupported("totals"));
+        }
+        if qb.get_top_n().is_some() {
+            errors.push(unsupported("top n"));
+        }
+
+        let mut column = |name: &String| match block.get_column(name) {
+            Some(column) => Some(&column.data),
+            None => {
+                errors.push(QueryError::UnknownField(name.clone()));
+                None
+            }
+        };
+        let dimensions: Vec<_> = qb
+            .get_rows()
+            .iter()
+            .chain(qb.get_cols())
+            .filter_map(|d| column(&d.field.field_name).map(|c| (d, c)))
+            .collect();
+        let measures: Vec<_> = qb
+            .get_meas()
+            .iter()
+            .filter_map(|m| column(&m.field.field_name).map(|c| (m, c)))
+            .collect();
+        let filters: Vec<_> = qb
+            .get_filters()
+            .iter()
+            .filter_map(|f| column(&f.field.field_name).map(|c| (f, c)))
+            .collect();
+
+        if !errors.is_empty() {
+            return Err(ValidationError { errors });
+        }
+        Ok(Plan {
+            qb,
+            dimensions,
+            measures,
+            filters,
+            deadline: None,
+        })
+    }
+
+    ///超过 `timeout` 后中断聚合和排序
+    pub(crate) fn timeout(mut self, timeout: Option<StdDuration>) -> Self {
+        self.deadline =
+            timeout.and_then(|timeout| Some((Instant::now().checked_add(timeout)?, timeout)));
+        self
+    }
+
+    fn check_deadline(&self) -> Result<(), LimitError> {
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(LimitError::Timeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, row: usize) -> bool {
+        self.filters.iter().all(|(filter, column)| {
+            let value = column.get(row);
+            match filter.op {
+                FilterOp::IsNull => value == Value::Null,
+                FilterOp::In => filter
+                    .values
+                    .iter()
+                    .any(|v| compare(&value, v) == Some(Ordering::Equal)),
+                op => {
+                    let ordering = match filter.values.first() {
+                        Some(v) => compare(&value, v),
+                        None => None,
+                    };
+                    match ordering {
+                        Some(ordering) => match op {
+                            FilterOp::Eq => ordering == Ordering::Equal,
+                            FilterOp::Ne => ordering != Ordering::Equal,
+                            FilterOp::Gt => ordering == Ordering::Greater,
+                            FilterOp::Lt => ordering == Ordering::Less,
+                            FilterOp::Ge => ordering != Ordering::Less,
+                            FilterOp::Le => ordering != Ordering::Greater,
+                            FilterOp::In | FilterOp::IsNull => false,
+                        },
+                        None => false,
+                    }
+                }
+            }
+        })
+    }
+
+    ///过滤并聚合一段连续的行
+    pub(crate) fn aggregate(&self, rows: Range<usize>) -> Result<Groups, LimitError> {
+        let mut groups = Groups::default();
+        for start in rows.clone().step_by(CHECK_ROWS) {
+            self.check_deadline()?;
+            let end = (start + CHECK_ROWS).min(rows.end);
+            for row in (start..end).filter(|row| self.matches(*row)) {
+                let key = self
+                    .dimensions
+                    .iter()
+                    .map(|(dimension, column)| {
+                        let value = column.get(row);
+                        match dimension.date_unit {
+                            Some(unit) => Key::from(trunc(value, unit)),
+                            None => Key::from(value),
+                        }
+                    })
+                    .collect();
+                let accs = groups.group(key, &self.measures);
+                for (acc, (_, column)) in accs.iter_mut().zip(&self.measures) {
+                    acc.add(column.get(row));
+                }
+            }
+        }
+        Ok(groups)
+    }
+
+    ///生成结果, 排序并分页
+    pub(crate) fn finish(&self, mut groups: Groups) -> Result<DataBlock, Box<dyn Error>> {
+        // like sql, aggregates without dimensions always return one row
+        if self.dimensions.is_empty() && !self.measures.is_empty() && groups.keys.is_empty() {
+            groups.group(vec![], &self.measures);
+        }
+
+        let names: Vec<String> = self
+            .dimensions
+            .iter()
+            .map(|(d, _)| d.field.field_name.clone())
+            .chain(self.measures.iter().map(|(m, _)| m.name().to_string()))
+            .collect();
+        let mut rows: Vec<Vec<Value>> = groups
+            .keys
+            .into_iter()
+            .zip(groups.accs)
+            .map(|(key, accs)| {
+                key.into_iter()
+                    .map(Value::from)
+                    .chain(accs.into_iter().map(Acc::finish))
+                    .collect()
+            })
+            .collect();
+
+        let mut orders = vec![];
+        for order in self.qb.get_orders() {
+            match names.iter().position(|n| *n == order.field.field_name) {
+                Some(index) => orders.push((index, order.order_type)),
+                None => {
+                    return Err(Box::new(ValidationError {
+                        errors: vec![QueryError::UnknownField(order.field.field_name.clone())],
+                    }))
+                }
+            }
+        }
+        self.check_deadline()?;
+        rows.sort_by(|a, b| {
+            orders
+                .iter()
+                .map(|(index, order_type)| match order_type {
+                    OrderType::ASC => sort_cmp(&a[*index], &b[*index]),
+                    OrderType::DESC => sort_cmp(&b[*index], &a[*index]),
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let rows = rows
+            .into_iter()
+            .skip(self.qb.get_offset() as usize)
+            .take(self.qb.get_limit().unwrap_or(u64::MAX) as usize)
+            .collect();
+        Ok(DataBlock::from_rows(names, rows))
+    }
+}
+
+pub(crate) fn unsupported(feature: &str) -> QueryError {
+    QueryError::Unsupported {
+        dialect: ENGINE_NAME.to_string(),
+        feature: feature.to_string(),
+    }
+}
+
+///过滤时的比较, 空值和任何值都不可比较
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => l.partial_cmp(r),
+        (Value::Text(l), Value::Text(r)) => Some(l.cmp(r)),
+        (Value::Number(l), Value::Text(r)) => r.parse::<f64>().ok().and_then(|r| l.partial_cmp(&r)),
+        (Value::Text(l), Value::Number(r)) => l.parse::<f64>().ok().and_then(|l| l.partial_cmp(r)),
+        _ => None,
+    }
+}
+
+///排序时的比较, 空值最小
+fn sort_cmp(left: &Value, right: &Value) -> Ordering {
+    match (left, right) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Less,
+        (_, Value::Null) => Ordering::Greater,
+        (Value::Number(_), Value::Text(_)) => Ordering::Less,
+        (Value::Text(_), Value::Number(_)) => Ordering::Greater,
+        _ => compare(left, right).unwrap_or(Ordering::Equal),
+    }
+}
+
+///按粒度截断 `yyyy-MM-dd` 格式的日期, 周从周一开始
+fn trunc(value: Value, unit: DateUnit) -> Value {
+    let date = match &value {
+        Value::Text(s) => s
+            .get(0..10)
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()),
+        _ => None,
+    };
+    let date = match date {
+        Some(date) => date,
+        None => return Value::Null,
+    };
+    let date = match unit {
+        DateUnit::Year => date.with_ordinal(1),
+        DateUnit::Quarter => date
+            .with_day(1)
+            .and_then(|d| d.with_month((d.month() - 1) / 3 * 3 + 1)),
+        DateUnit::Month => date.with_day(1),
+        DateUnit::Week => Some(date - Duration::days(date.weekday().num_days_from_monday() as i64)),
+        DateUnit::Day => Some(date),
+    };
+    date.map(|d| Value::Text(d.format("%Y-%m-%d").to_string()))
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trunc() {
+        let day = Value::from("2021-05-27 10:30:00");
+        assert_eq!(
+            trunc(day.clone(), DateUnit::Year),
+            Value::from("2021-01-01")
+        );
+        assert_eq!(
+            trunc(day.clone(), DateUnit::Quarter),
+            Value::from("2021-04-01")
+        );
+        assert_eq!(
+            trunc(day.clone(), DateUnit::Month),
+            Value::from("2021-05-01")
+        );
+        assert_eq!(
+            trunc(day.clone(), DateUnit::Week),
+            Value::from("2021-05-24")
+        );
+        assert_eq!(trunc(day, DateUnit::Day), Value::from("2021-05-27"));
+        assert_eq!(trunc(Value::from("n/a"), DateUnit::Day), Value::Null);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_merge() {
+        let mut acc = Acc::new(MeasureFn::AVG);
+        acc.add(Value::from(1.0));
+        acc.add(Value::Null);
+        let mut other = Acc::new(MeasureFn::AVG);
+        other.add(Value::from(4.0));
+        acc.merge(other);
+        assert_eq!(acc.finish(), Value::from(2.5));
+
+        let mut acc = Acc::new(MeasureFn::MAX);
+        acc.add(Value::from("2021-01-01"));
+        acc.merge(Acc::Max(Value::from("2021-03-01")));
+        assert_eq!(acc.finish(), Value::from("2021-03-01"));
+    }
+}
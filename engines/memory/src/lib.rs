@@ -0,0 +1,544 @@
+mod executor;
+
+use async_trait::async_trait;
+use engine_craits::{
+    Column, ColumnData, DataBlock, Engine, EngineType, LimitError, QueryEngine, QueryOptions,
+};
+use executor::{unsupported, Groups, Plan};
+use query::{QueryBuilder, QueryError, SchemaChange, ValidationError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::RwLock;
+use std::time::Duration;
+
+#[cfg(feature = "parallel")]
+use lighting_core::WorkPool;
+
+///每个并行任务处理的行数
+#[cfg(feature = "parallel")]
+const CHUNK_ROWS: usize = 64 * 1024;
+
+///The in-memory engine, for uploaded previews and datasets of a few million rows at most
+pub struct MemoryEngine {
+    tables: RwLock<HashMap<String, DataBlock>>,
+    #[cfg(feature = "parallel")]
+    workers: usize,
+}
+
+#[async_trait]
+impl Engine for MemoryEngine {
+    type Block = DataBlock;
+
+    async fn ddl_str(&self, _ddl: &str) -> Result<(), Box<dyn Error>> {
+        Err(Box::new(ValidationError {
+            errors: vec![unsupported("sql")],
+        }))
+    }
+
+    async fn query_str(&self, _sql: &str) -> Result<DataBlock, Box<dyn Error>> {
+        Err(Box::new(ValidationError {
+            errors: vec![unsupported("sql")],
+        }))
+    }
+
+    async fn query_qb(&self, query_builder: QueryBuilder) -> Result<DataBlock, Box<dyn Error>> {
+        self.run(&query_builder, None)
+    }
+}
+
+#[async_trait]
+impl QueryEngine for MemoryEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Memory
+    }
+
+    async fn ddl(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
+        self.ddl_str(ddl).await
+    }
+
+    async fn alter_schema(&self, change: SchemaChange) -> Result<(), Box<dyn Error>> {
+        change.check()?;
+        let mut tables = self.tables.write().map_err(|_| "poisoned memory tables")?;
+        let unknown_table = |table: &str| ValidationError {
+            errors: vec![QueryError::UnknownTable(table.to_string())],
+        };
+        let unknown_field = |column: &str| ValidationError {
+            errors: vec![QueryError::UnknownField(column.to_string())],
+        };
+
+        match change {
+            SchemaChange::CreateTable { table, fields, .. } => {
+                let mut block = DataBlock::new();
+                for field in fields {
+                    block = block.column(&field.field_name, ColumnData::nulls(field.field_type, 0));
+                }
+                tables.entry(table).or_insert(block);
+            }
+            SchemaChange::AddColumn { table, field } => {
+                let block = tables
+                    .get_mut(&table)
+                    .ok_or_else(|| unknown_table(&table))?;
+                if block.get_column(&field.field_name).is_some() {
+                    return Err(format!("column {} already exists", field.field_name).into());
+                }
+                let data = ColumnData::nulls(field.field_type, block.row_count());
+                block.columns_mut().push(Column {
+                    name: field.field_name,
+                    data,
+                });
+            }
+            SchemaChange::DropColumn { table, column } => {
+                let block = tables
+                    .get_mut(&table)
+                    .ok_or_else(|| unknown_table(&table))?;
+                let index = block
+                    .columns()
+                    .iter()
+                    .position(|c| c.name == column)
+                    .ok_or_else(|| unknown_field(&column))?;
+                block.columns_mut().remove(index);
+            }
+            SchemaChange::RenameColumn { table, from, to } => {
+                let block = tables
+                    .get_mut(&table)
+                    .ok_or_else(|| unknown_table(&table))?;
+                if block.get_column(&to).is_some() {
+                    return Err(format!("column {} already exists", to).into());
+                }
+                let column = block
+                    .columns_mut()
+                    .iter_mut()
+                    .find(|c| c.name == from)
+                    .ok_or_else(|| unknown_field(&from))?;
+                column.name = to;
+            }
+            SchemaChange::ModifyColumn { table, field } => {
+                let block = tables
+                    .get_mut(&table)
+                    .ok_or_else(|| unknown_table(&table))?;
+                let column = block
+                    .columns_mut()
+                    .iter_mut()
+                    .find(|c| c.name == field.field_name)
+                    .ok_or_else(|| unknown_field(&field.field_name))?;
+                let data = std::mem::replace(&mut column.data, ColumnData::Number(vec![]));
+                column.data = data.cast(field.field_type);
+            }
+            SchemaChange::RenameTable { from, to } => {
+                if tables.contains_key(&to) {
+                    return Err(format!("table {} already exists", to).into());
+                }
+                let block = tables.remove(&from).ok_or_else(|| unknown_table(&from))?;
+                tables.insert(to, block);
+            }
+            SchemaChange::TruncateTable { table } => {
+                let block = tables
+                    .get_mut(&table)
+                    .ok_or_else(|| unknown_table(&table))?;
+                for column in block.columns_mut() {
+                    column.data.clear();
+                }
+            }
+            SchemaChange::DropTable { table } => {
+                tables.remove(&table);
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert(&self, table: &str, block: DataBlock) -> Result<(), Box<dyn Error>> {
+        self.insert_block(table, block)
+    }
+
+    ///聚合在当前线程完成, 聚合和排序时每隔一段检查是否超时
+    async fn query(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<DataBlock, Box<dyn Error>> {
+        let block = self.run(&query_builder, options.timeout)?;
+        options.check(&block)?;
+        Ok(block)
+    }
+}
+
+impl MemoryEngine {
+    pub fn new() -> Self {
+        MemoryEngine {
+            tables: RwLock::new(HashMap::new()),
+            #[cfg(feature = "parallel")]
+            workers: 1,
+        }
+    }
+
+    ///并行聚合使用的线程数
+    #[cfg(feature = "parallel")]
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    ///追加数据, 表不存在时创建
+    pub fn insert_block(&self, table_name: &str, block: DataBlock) -> Result<(), Box<dyn Error>> {
+        let mut tables = self.tables.write().map_err(|_| "poisoned memory tables")?;
+        match tables.get_mut(table_name) {
+            Some(table) => table.append(block),
+            None => {
+                tables.insert(table_name.to_string(), block);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn drop_table(&self, table_name: &str) -> Result<bool, Box<dyn Error>> {
+        let mut tables = self.tables.write().map_err(|_| "poisoned memory tables")?;
+        Ok(tables.remove(table_name).is_some())
+    }
+
+    fn run(
+        &self,
+        query_builder: &QueryBuilder,
+        timeout: Option<Duration>,
+    ) -> Result<DataBlock, Box<dyn Error>> {
+        let tables = self.tables.read().map_err(|_| "poisoned memory tables")?;
+        let block = tables.get(query_builder.get_table()).ok_or_else(|| {
+            Box::new(ValidationError {
+                errors: vec![QueryError::UnknownTable(
+                    query_builder.get_table().to_string(),
+                )],
+            })
+        })?;
+
+        let plan = Plan::new(query_builder, block)?.timeout(timeout);
+        let groups = self.aggregate(&plan, block.row_count())?;
+        plan.finish(groups)
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn aggregate(&self, plan: &Plan, row_count: usize) -> Result<Groups, LimitError> {
+        plan.aggregate(0..row_count)
+    }
+
+    ///按块分发到WorkPool, 按块的顺序合并结果, 保证分组顺序和单线程一致
+    #[cfg(feature = "parallel")]
+    fn aggregate(&self, plan: &Plan, row_count: usize) -> Result<Groups, LimitError> {
+        if self.workers == 1 || row_count <= CHUNK_ROWS {
+            return plan.aggregate(0..row_count);
+        }
+
+        let pool = WorkPool::new();
+        for (i, start) in (0..row_count).step_by(CHUNK_ROWS).enumerate() {
+            pool.push_work((i, start..(start + CHUNK_ROWS).min(row_count)));
+        }
+
+        let mut partials = crossbeam::scope(|s| {
+            let handles: Vec<_> = (0..self.workers)
+                .map(|_| {
+                    let pool = pool.clone();
+                    s.spawn(move |_| {
+                        let mut partials = vec![];
+                        while let Some((i, rows)) = pool.get_work() {
+                            let partial = plan.aggregate(rows);
+                            let timed_out = partial.is_err();
+                            partials.push((i, partial));
+                            if timed_out {
+                                break;
+                            }
+                        }
+                        partials
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("memory engine worker panicked"))
+                .collect::<Vec<_>>()
+        })
+        .expect("memory engine worker panicked");
+        partials.sort_by_key(|(i, _)| *i);
+
+        let mut groups = Groups::default();
+        for (_, partial) in partials {
+            groups.merge(partial?, &plan.measures);
+        }
+        Ok(groups)
+    }
+}
+
+impl Default for MemoryEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_craits::ColumnData;
+    use query::{
+        DataType, DateUnit, Dimension, Field, Filter, Measure, MeasureFn, Order, OrderType, Value,
+    };
+
+    fn payment_engine() -> MemoryEngine {
+        let block = DataBlock::new()
+            .column(
+                "amount",
+                ColumnData::Number(vec![Some(2.0), Some(4.0), Some(6.0), Some(8.0), None]),
+            )
+            .column(
+                "account_name",
+                ColumnData::Text(vec![
+                    Some(String::from("foo")),
+                    Some(String::from("bar")),
+                    Some(String::from("foo")),
+                    None,
+                    Some(String::from("baz")),
+                ]),
+            )
+            .column(
+                "pay_date",
+                ColumnData::Text(vec![
+                    Some(String::from("2021-01-05")),
+                    Some(String::from("2021-01-20")),
+                    Some(String::from("2021-02-01")),
+                    Some(String::from("2021-02-14")),
+                    Some(String::from("2021-03-31")),
+                ]),
+            );
+        let engine = MemoryEngine::new();
+        engine.insert_block("payment", block).unwrap();
+        engine
+    }
+
+    fn name() -> Field {
+        Field::new(
+            String::from("account_name"),
+            DataType::Text,
+            String::from("账号名称"),
+        )
+    }
+
+    fn pay_date() -> Field {
+        Field::new(
+            String::from("pay_date"),
+            DataType::Date,
+            String::from("支付日期"),
+        )
+    }
+
+    fn amount() -> Field {
+        Field::new(
+            String::from("amount"),
+            DataType::Number,
+            String::from("金额"),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_query_qb() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine();
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(pay_date()).trunc(DateUnit::Month)])
+            .meas(vec![
+                Measure::new(amount(), MeasureFn::SUM),
+                Measure::new(name(), MeasureFn::COUNT),
+            ])
+            .filter(vec![Filter::ne(name(), Value::from("bar"))])
+            .order(vec![Order::new_with_order(pay_date(), OrderType::DESC)]);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(
+            block.get_column("pay_date").unwrap().data,
+            ColumnData::Text(vec![
+                Some(String::from("2021-03-01")),
+                Some(String::from("2021-02-01")),
+                Some(String::from("2021-01-01")),
+            ])
+        );
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Number(vec![None, Some(6.0), Some(2.0)])
+        );
+        assert_eq!(
+            block.get_column("account_name").unwrap().data,
+            ColumnData::Number(vec![Some(1.0), Some(1.0), Some(1.0)])
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .meas(vec![Measure::new(amount(), MeasureFn::AVG)])
+            .filter(vec![Filter::gt(amount(), Value::from(100_i64))]);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(block.row(0), vec![Value::Null]);
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(name())])
+            .meas(vec![Measure::new(amount(), MeasureFn::MAX)])
+            .order(vec![Order::new(name())])
+            .limit(2)
+            .offset(1);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(
+            block.get_column("account_name").unwrap().data,
+            ColumnData::Text(vec![Some(String::from("bar")), Some(String::from("baz"))])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_query() {
+        let engine = payment_engine();
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(name())])
+            .meas(vec![Measure::new(
+                Field::new(String::from("price"), DataType::Number, String::new()),
+                MeasureFn::SUM,
+            )])
+            .rollup();
+        let err = engine.query_qb(qb).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid query: totals is not supported by Memory; unknown field: price"
+        );
+
+        let qb = QueryBuilder::new().table(String::from("orders"));
+        assert!(engine.query_qb(qb).await.is_err());
+        assert!(engine.query_str("select 1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_limits() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine();
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(name())])
+            .meas(vec![Measure::new(amount(), MeasureFn::SUM)]);
+
+        let block = engine
+            .query(qb.clone(), &QueryOptions::new().max_rows(4))
+            .await?;
+        assert_eq!(block.row_count(), 4);
+
+        let err = engine
+            .query(
+                qb.clone(),
+                &QueryOptions::new().timeout(Duration::from_secs(0)),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "query timed out after 0ms");
+
+        let err = engine
+            .query(qb, &QueryOptions::new().max_rows(3))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "query result exceeds the limit of 3 rows");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_alter_schema() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine();
+        engine
+            .alter_schema(SchemaChange::AddColumn {
+                table: String::from("payment"),
+                field: Field::new(String::from("memo"), DataType::Text, String::new()),
+            })
+            .await?;
+        engine
+            .alter_schema(SchemaChange::RenameColumn {
+                table: String::from("payment"),
+                from: String::from("amount"),
+                to: String::from("price"),
+            })
+            .await?;
+        engine
+            .alter_schema(SchemaChange::DropColumn {
+                table: String::from("payment"),
+                column: String::from("pay_date"),
+            })
+            .await?;
+        engine
+            .alter_schema(SchemaChange::ModifyColumn {
+                table: String::from("payment"),
+                field: Field::new(String::from("price"), DataType::Text, String::new()),
+            })
+            .await?;
+        {
+            let tables = engine.tables.read().unwrap();
+            let block = &tables["payment"];
+            let names: Vec<&str> = block.columns().iter().map(|c| c.name.as_str()).collect();
+            assert_eq!(names, vec!["price", "account_name", "memo"]);
+            assert_eq!(
+                block.row(0),
+                vec![Value::from("2"), Value::from("foo"), Value::Null]
+            );
+        }
+
+        engine
+            .alter_schema(SchemaChange::TruncateTable {
+                table: String::from("payment"),
+            })
+            .await?;
+        assert_eq!(engine.tables.read().unwrap()["payment"].row_count(), 0);
+
+        assert!(engine
+            .alter_schema(SchemaChange::DropColumn {
+                table: String::from("payment"),
+                column: String::from("pay_date"),
+            })
+            .await
+            .is_err());
+
+        engine
+            .alter_schema(SchemaChange::DropTable {
+                table: String::from("payment"),
+            })
+            .await?;
+        assert!(!engine.drop_table("payment")?);
+        Ok(())
+    }
+
+    #[cfg(feature = "parallel")]
+    #[tokio::test]
+    async fn test_parallel() -> Result<(), Box<dyn Error>> {
+        let rows = CHUNK_ROWS * 3 + 7;
+        let block = DataBlock::new()
+            .column(
+                "amount",
+                ColumnData::Number((0..rows).map(|i| Some(i as f64)).collect()),
+            )
+            .column(
+                "account_name",
+                ColumnData::Text((0..rows).map(|i| Some((i % 3).to_string())).collect()),
+            );
+        let engine = MemoryEngine::new().workers(4);
+        engine.insert_block("payment", block)?;
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(name())])
+            .meas(vec![Measure::new(amount(), MeasureFn::COUNT)]);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(
+            block.get_column("account_name").unwrap().data,
+            ColumnData::Text(vec![
+                Some(String::from("0")),
+                Some(String::from("1")),
+                Some(String::from("2")),
+            ])
+        );
+        let total: f64 = (0..3)
+            .map(|i| match block.row(i)[1] {
+                Value::Number(n) => n,
+                _ => 0.0,
+            })
+            .sum();
+        assert_eq!(total, rows as f64);
+        Ok(())
+    }
+}
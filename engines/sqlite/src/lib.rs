@@ -0,0 +1,366 @@
+use async_trait::async_trait;
+use engine_craits::{DataBlock, Engine, EngineType, LimitError, QueryEngine, QueryOptions};
+use query::{
+    is_identifier, QueryBuilder, SchemaChange, SqlDialect, SqliteDialect, ValidationError, Value,
+};
+use sqlx::sqlite::{SqlitePoolOptions, SqliteRow};
+use sqlx::{Column, Executor, Row, SqlitePool, TypeInfo, ValueRef};
+use std::error::Error;
+
+///sqlite limits the number of parameters of a statement to 999 by default
+const MAX_PARAMS: usize = 999;
+
+///The embedded engine, for small deployments and tests without a ClickHouse server
+pub struct SqliteEngine {
+    pool: SqlitePool,
+}
+
+#[async_trait]
+impl Engine for SqliteEngine {
+    type Block = DataBlock;
+
+    async fn ddl_str(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
+        self.pool.execute(ddl).await?;
+        Ok(())
+    }
+
+    async fn query_str(&self, sql: &str) -> Result<DataBlock, Box<dyn Error>> {
+        let rows = sqlx::query(sql).fetch_all(&self.pool).await?;
+        Self::to_block(rows)
+    }
+
+    async fn query_qb(&self, query_builder: QueryBuilder) -> Result<DataBlock, Box<dyn Error>> {
+        let compiled = query_builder.to_sql(&SqliteDialect)?;
+        let mut query = sqlx::query(&compiled.sql);
+        for param in compiled.params {
+            query = match param {
+                Value::Null => query.bind(None::<String>),
+                Value::Number(n) => query.bind(n),
+                Value::Text(s) => query.bind(s),
+            };
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+        Self::to_block(rows)
+    }
+}
+
+#[async_trait]
+impl QueryEngine for SqliteEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Sqlite
+    }
+
+    async fn ddl(&self, ddl: &str) -> Result<(), Box<dyn Error>> {
+        self.ddl_str(ddl).await
+    }
+
+    async fn alter_schema(&self, change: SchemaChange) -> Result<(), Box<dyn Error>> {
+        self.ddl_str(&change.to_sql(&SqliteDialect)?).await
+    }
+
+    async fn insert(&self, table: &str, block: DataBlock) -> Result<(), Box<dyn Error>> {
+        self.insert_block(table, block).await
+    }
+
+    async fn query(
+        &self,
+        query_builder: QueryBuilder,
+        options: &QueryOptions,
+    ) -> Result<DataBlock, Box<dyn Error>> {
+        let block = match options.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.query_qb(query_builder))
+                .await
+                .map_err(|_| LimitError::Timeout(timeout))??,
+            None => self.query_qb(query_builder).await?,
+        };
+        options.check(&block)?;
+        Ok(block)
+    }
+}
+
+impl SqliteEngine {
+    ///`sqlite::memory:` keeps the data in a single connection for the lifetime of the engine
+    pub async fn new(database_url: &str) -> Result<Self, Box<dyn Error>> {
+        let mut options = SqlitePoolOptions::new();
+        if database_url.contains(":memory:") {
+            options = options
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None);
+        }
+        let pool = options.connect(database_url).await?;
+        Ok(SqliteEngine { pool })
+    }
+
+    ///insert all rows of the block in one transaction, as multi-row inserts
+    pub async fn insert_block(
+        &self,
+        table_name: &str,
+        block: DataBlock,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut names = vec![table_name];
+        names.extend(block.columns().iter().map(|c| c.name.as_str()));
+        if let Some(name) = names.iter().find(|name| !is_identifier(name)) {
+            return Err(Box::new(ValidationError {
+                errors: vec![query::QueryError::InvalidIdentifier(name.to_string())],
+            }));
+        }
+        if block.columns().is_empty() {
+            return Ok(());
+        }
+
+        let dialect = SqliteDialect;
+        let columns: Vec<String> = block
+            .columns()
+            .iter()
+            .map(|c| dialect.quote_identifier(&c.name))
+            .collect();
+        let row_holder = format!("({})", vec!["?"; columns.len()].join(","));
+        let chunk_size = (MAX_PARAMS / columns.len()).max(1);
+
+        let mut tx = self.pool.begin().await?;
+        let mut start = 0;
+        while start < block.row_count() {
+            let end = (start + chunk_size).min(block.row_count());
+            let insert_sql = format!(
+                "insert into {} ({}) values {}",
+                dialect.quote_identifier(table_name),
+                columns.join(","),
+                vec![row_holder.as_str(); end - start].join(",")
+            );
+            let mut query = sqlx::query(&insert_sql);
+            for i in start..end {
+                for value in block.row(i) {
+                    query = match value {
+                        Value::Null => query.bind(None::<String>),
+                        Value::Number(n) => query.bind(n),
+                        Value::Text(s) => query.bind(s),
+                    };
+                }
+            }
+            query.execute(&mut tx).await?;
+            start = end;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    fn to_block(rows: Vec<SqliteRow>) -> Result<DataBlock, Box<dyn Error>> {
+        let names: Vec<String> = match rows.first() {
+            Some(row) => row.columns().iter().map(|c| c.name().to_string()).collect(),
+            None => return Ok(DataBlock::new()),
+        };
+
+        let mut values = vec![];
+        for row in rows {
+            let mut row_values = vec![];
+            for i in 0..names.len() {
+                let raw = row.try_get_raw(i)?;
+                let value = if raw.is_null() {
+                    Value::Null
+                } else {
+                    match raw.type_info().name() {
+                        "INTEGER" => Value::Number(row.try_get::<i64, _>(i)? as f64),
+                        "REAL" => Value::Number(row.try_get::<f64, _>(i)?),
+                        _ => Value::Text(row.try_get::<String, _>(i)?),
+                    }
+                };
+                row_values.push(value);
+            }
+            values.push(row_values);
+        }
+        Ok(DataBlock::from_rows(names, values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_craits::ColumnData;
+    use query::{
+        DataType, DateUnit, Dimension, Field, Filter, Measure, MeasureFn, Order, OrderType, TopN,
+    };
+
+    async fn payment_engine() -> Result<SqliteEngine, Box<dyn Error>> {
+        let ddl = r"
+        CREATE TABLE IF NOT EXISTS payment (
+            customer_id  INTEGER,
+            amount       REAL,
+            account_name TEXT,
+            pay_date     TEXT
+        )";
+
+        let block = DataBlock::new()
+            .column(
+                "customer_id",
+                ColumnData::Number(vec![Some(1.0), Some(3.0), Some(5.0), Some(7.0), Some(9.0)]),
+            )
+            .column(
+                "amount",
+                ColumnData::Number(vec![Some(2.0), Some(4.0), Some(6.0), Some(8.0), Some(10.0)]),
+            )
+            .column(
+                "account_name",
+                ColumnData::Text(vec![
+                    Some(String::from("foo")),
+                    Some(String::from("bar")),
+                    Some(String::from("foo")),
+                    None,
+                    Some(String::from("baz")),
+                ]),
+            )
+            .column(
+                "pay_date",
+                ColumnData::Text(vec![
+                    Some(String::from("2021-01-05")),
+                    Some(String::from("2021-01-20")),
+                    Some(String::from("2021-02-01")),
+                    Some(String::from("2021-02-14")),
+                    Some(String::from("2021-03-31")),
+                ]),
+            );
+
+        let engine = SqliteEngine::new("sqlite::memory:").await?;
+        engine.ddl_str(ddl).await?;
+        engine.insert_block("payment", block).await?;
+        Ok(engine)
+    }
+
+    #[tokio::test]
+    async fn test_query_str() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine().await?;
+        let block = engine
+            .query_str("SELECT * FROM payment ORDER BY customer_id")
+            .await?;
+        assert_eq!(block.row_count(), 5);
+        assert_eq!(
+            block.row(3),
+            vec![
+                Value::from(7_i64),
+                Value::from(8.0),
+                Value::Null,
+                Value::from("2021-02-14")
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_qb() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine().await?;
+
+        let name = Field::new(
+            String::from("account_name"),
+            DataType::Text,
+            String::from("账号名称"),
+        );
+        let pay_date = Field::new(
+            String::from("pay_date"),
+            DataType::Date,
+            String::from("支付日期"),
+        );
+        let amount = Field::new(
+            String::from("amount"),
+            DataType::Number,
+            String::from("金额"),
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![
+                Dimension::new_row(pay_date.clone()).trunc(DateUnit::Month)
+            ])
+            .meas(vec![Measure::new(amount.clone(), MeasureFn::SUM)])
+            .filter(vec![Filter::gt(amount.clone(), Value::from(2_i64))])
+            .order(vec![Order::new(pay_date)]);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(
+            block.get_column("pay_date").unwrap().data,
+            ColumnData::Text(vec![
+                Some(String::from("2021-01-01")),
+                Some(String::from("2021-02-01")),
+                Some(String::from("2021-03-01")),
+            ])
+        );
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Number(vec![Some(4.0), Some(14.0), Some(10.0)])
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("payment"))
+            .row(vec![Dimension::new_row(name.clone())])
+            .meas(vec![Measure::new(amount.clone(), MeasureFn::SUM)])
+            .top_n(TopN::new(
+                name.clone(),
+                Measure::new(amount, MeasureFn::SUM),
+                1,
+            ))
+            .order(vec![Order::new_with_order(name, OrderType::DESC)]);
+        let block = engine.query_qb(qb).await?;
+        assert_eq!(
+            block.get_column("account_name").unwrap().data,
+            ColumnData::Text(vec![
+                Some(String::from("baz")),
+                Some(String::from("Others"))
+            ])
+        );
+        assert_eq!(
+            block.get_column("amount").unwrap().data,
+            ColumnData::Number(vec![Some(10.0), Some(20.0)])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_invalid_table() -> Result<(), Box<dyn Error>> {
+        let engine = SqliteEngine::new("sqlite::memory:").await?;
+        let block = DataBlock::new().column("a", ColumnData::Number(vec![Some(1.0)]));
+        assert!(engine.insert_block("t; drop", block).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_alter_schema() -> Result<(), Box<dyn Error>> {
+        let engine = payment_engine().await?;
+        engine
+            .alter_schema(SchemaChange::RenameColumn {
+                table: String::from("payment"),
+                from: String::from("amount"),
+                to: String::from("price"),
+            })
+            .await?;
+        engine
+            .alter_schema(SchemaChange::AddColumn {
+                table: String::from("payment"),
+                field: Field::new(String::from("memo"), DataType::Text, String::new()),
+            })
+            .await?;
+        engine
+            .alter_schema(SchemaChange::RenameTable {
+                from: String::from("payment"),
+                to: String::from("t_payment"),
+            })
+            .await?;
+        let block = engine
+            .query_str("SELECT price, memo FROM t_payment ORDER BY customer_id")
+            .await?;
+        assert_eq!(block.row(0), vec![Value::from(2.0), Value::Null]);
+
+        engine
+            .alter_schema(SchemaChange::TruncateTable {
+                table: String::from("t_payment"),
+            })
+            .await?;
+        let block = engine.query_str("SELECT count(*) FROM t_payment").await?;
+        assert_eq!(block.row(0), vec![Value::from(0_i64)]);
+
+        engine
+            .alter_schema(SchemaChange::DropTable {
+                table: String::from("t_payment"),
+            })
+            .await?;
+        assert!(engine.query_str("SELECT * FROM t_payment").await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+-- the id of a user is read as a signed integer, as postgres and sqlite have no unsigned one
+ALTER TABLE ${TABLE_NAMESPACE}_user MODIFY `id` BIGINT NOT NULL AUTO_INCREMENT;
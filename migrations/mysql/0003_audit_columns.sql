@@ -0,0 +1,13 @@
+-- audit columns of datasets and fields, the existing rows are version 1 and have no owner
+ALTER TABLE ${TABLE_NAMESPACE}_dataset
+    ADD `created_at` BIGINT NOT NULL DEFAULT 0,
+    ADD `updated_at` BIGINT NOT NULL DEFAULT 0,
+    ADD `created_by` varchar(128),
+    ADD `deleted` BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD `version` int NOT NULL DEFAULT 1;
+
+ALTER TABLE ${TABLE_NAMESPACE}_field
+    ADD `created_at` BIGINT NOT NULL DEFAULT 0,
+    ADD `updated_at` BIGINT NOT NULL DEFAULT 0,
+    ADD `created_by` varchar(128),
+    ADD `deleted` BOOLEAN NOT NULL DEFAULT FALSE;
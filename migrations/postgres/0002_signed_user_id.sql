@@ -0,0 +1 @@
+-- the id of a user is signed since 0001_init, the version is kept in step with mysql
@@ -0,0 +1,13 @@
+-- audit columns of datasets and fields, the existing rows are version 1 and have no owner
+ALTER TABLE ${TABLE_NAMESPACE}_dataset
+    ADD COLUMN `created_at` BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN `updated_at` BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN `created_by` varchar(128),
+    ADD COLUMN `deleted` BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN `version` int NOT NULL DEFAULT 1;
+
+ALTER TABLE ${TABLE_NAMESPACE}_field
+    ADD COLUMN `created_at` BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN `updated_at` BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN `created_by` varchar(128),
+    ADD COLUMN `deleted` BOOLEAN NOT NULL DEFAULT FALSE;
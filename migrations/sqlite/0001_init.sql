@@ -0,0 +1,45 @@
+-- the metadata tables of sqlite
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_user (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT,
+    `name` text DEFAULT NULL,
+    `age` int
+);
+
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_dataset (
+    `id` varchar(128) NOT NULL ,
+    `name` varchar(128) NOT NULL,
+    `display_name` varchar(128),
+    `engine_type` varchar(128),
+    `engine_name` varchar(128) NOT NULL DEFAULT '',
+    `size` double,
+    `count` int,
+    PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_field (
+    `id` varchar(128) NOT NULL ,
+    `name` varchar(128) NOT NULL,
+    `dataset_id` varchar(128) NOT NULL,
+    `data_type` varchar(128) NOT NULL,
+    `field_type` varchar(128) NOT NULL,
+    `display_name` varchar(128),
+    `formula` varchar(128),
+    PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_saved_query (
+    `id` varchar(128) NOT NULL ,
+    `name` varchar(128) NOT NULL,
+    `dataset_id` varchar(128) NOT NULL,
+    `version` int NOT NULL,
+    `query` text NOT NULL,
+    PRIMARY KEY (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS ${TABLE_NAMESPACE}_saved_pre_aggregation (
+    `id` varchar(128) NOT NULL ,
+    `name` varchar(128) NOT NULL,
+    `dataset_id` varchar(128) NOT NULL,
+    `definition` text NOT NULL,
+    PRIMARY KEY (`id`)
+);
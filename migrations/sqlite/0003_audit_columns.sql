@@ -0,0 +1,12 @@
+-- audit columns of datasets and fields, the existing rows are version 1 and have no owner
+-- sqlite adds one column in a statement
+ALTER TABLE ${TABLE_NAMESPACE}_dataset ADD COLUMN `created_at` BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ${TABLE_NAMESPACE}_dataset ADD COLUMN `updated_at` BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ${TABLE_NAMESPACE}_dataset ADD COLUMN `created_by` varchar(128);
+ALTER TABLE ${TABLE_NAMESPACE}_dataset ADD COLUMN `deleted` BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE ${TABLE_NAMESPACE}_dataset ADD COLUMN `version` int NOT NULL DEFAULT 1;
+
+ALTER TABLE ${TABLE_NAMESPACE}_field ADD COLUMN `created_at` BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ${TABLE_NAMESPACE}_field ADD COLUMN `updated_at` BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE ${TABLE_NAMESPACE}_field ADD COLUMN `created_by` varchar(128);
+ALTER TABLE ${TABLE_NAMESPACE}_field ADD COLUMN `deleted` BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,94 @@
+use crate::dialect::{ParamStyle, SqlDialect};
+use crate::query_builder::{DataType, DateUnit};
+
+pub struct ClickHouseDialect;
+
+impl SqlDialect for ClickHouseDialect {
+    fn name(&self) -> &'static str {
+        "ClickHouse"
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("`{}`", name.replace('`', "``"))
+    }
+
+    fn quote_literal(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+
+    ///clickhouse native协议不支持参数绑定
+    fn param_style(&self) -> ParamStyle {
+        ParamStyle::Inline
+    }
+
+    fn date_trunc(&self, unit: DateUnit, expr: &str) -> String {
+        match unit {
+            DateUnit::Year => format!("toStartOfYear({})", expr),
+            DateUnit::Quarter => format!("toStartOfQuarter({})", expr),
+            DateUnit::Month => format!("toStartOfMonth({})", expr),
+            DateUnit::Week => format!("toMonday({})", expr),
+            DateUnit::Day => format!("toDate({})", expr),
+        }
+    }
+
+    fn cast_to_text(&self, expr: &str) -> String {
+        format!("toString({})", expr)
+    }
+
+    fn column_type(&self, data_type: DataType) -> String {
+        match data_type {
+            DataType::Text => String::from("Nullable(String)"),
+            DataType::Number => String::from("Nullable(Float64)"),
+            DataType::Date => String::from("Nullable(Date)"),
+        }
+    }
+
+    ///MergeTree的排序键, 字段可以为空所以需要 `allow_nullable_key`
+    fn table_options(&self, order_by: &[String]) -> String {
+        if order_by.is_empty() {
+            String::from(" engine = MergeTree() order by tuple()")
+        } else {
+            format!(
+                " engine = MergeTree() order by ({}) settings allow_nullable_key = 1",
+                order_by.join(",")
+            )
+        }
+    }
+
+    fn rollup(&self, keys: &[String]) -> Option<String> {
+        Some(format!("{} with rollup", keys.join(",")))
+    }
+
+    fn cube(&self, keys: &[String]) -> Option<String> {
+        Some(format!("{} with cube", keys.join(",")))
+    }
+
+    fn grouping_sets(&self, sets: &[Vec<String>]) -> Option<String> {
+        let sets: Vec<String> = sets
+            .iter()
+            .map(|set| format!("({})", set.join(",")))
+            .collect();
+        Some(format!("grouping sets ({})", sets.join(",")))
+    }
+
+    ///使用 `limit n by parent` 在每个上级分组内取前N名
+    fn top_n_members(
+        &self,
+        member: &str,
+        partition: Option<&str>,
+        rank_by: &str,
+        from: &str,
+        n: u32,
+    ) -> String {
+        match partition {
+            None => format!(
+                "{} in (select {}{} group by {} order by {} desc limit {})",
+                member, member, from, member, rank_by, n
+            ),
+            Some(parent) => format!(
+                "({},{}) in (select {},{}{} group by {},{} order by {} desc limit {} by {})",
+                parent, member, parent, member, from, parent, member, rank_by, n, parent
+            ),
+        }
+    }
+}
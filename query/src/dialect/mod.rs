@@ -0,0 +1,124 @@
+mod clickhouse;
+mod mysql;
+mod sqlite;
+
+pub use self::clickhouse::ClickHouseDialect;
+pub use self::mysql::MySqlDialect;
+pub use self::sqlite::SqliteDialect;
+
+use crate::query_builder::{DataType, DateUnit, MeasureFn};
+
+///参数在sql中的写法
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParamStyle {
+    ///值转义后直接写入sql
+    Inline,
+    /// ?
+    QuestionMark,
+    /// ?1, ?2 ...
+    Numbered,
+}
+
+///sql方言, 同一个 `QueryBuilder` 可以编译为不同引擎的sql
+pub trait SqlDialect: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn quote_identifier(&self, name: &str) -> String;
+
+    ///字符串常量
+    fn quote_literal(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\'', "''"))
+    }
+
+    fn param_style(&self) -> ParamStyle;
+
+    fn aggregate(&self, measure_fn: MeasureFn, expr: &str) -> String {
+        match measure_fn {
+            MeasureFn::SUM => format!("sum({})", expr),
+            MeasureFn::MAX => format!("max({})", expr),
+            MeasureFn::MIN => format!("min({})", expr),
+            MeasureFn::AVG => format!("avg({})", expr),
+            MeasureFn::COUNT => format!("count({})", expr),
+        }
+    }
+
+    fn date_trunc(&self, unit: DateUnit, expr: &str) -> String;
+
+    fn cast_to_text(&self, expr: &str) -> String;
+
+    ///建表时字段的类型, 字段都允许为空
+    fn column_type(&self, data_type: DataType) -> String;
+
+    ///建表语句的表选项, `order_by` 为已转义的排序键
+    fn table_options(&self, _order_by: &[String]) -> String {
+        String::new()
+    }
+
+    fn rename_table(&self, from: &str, to: &str) -> String {
+        format!("rename table {} to {}", from, to)
+    }
+
+    fn truncate_table(&self, table: &str) -> String {
+        format!("truncate table {}", table)
+    }
+
+    ///修改字段类型, 已有数据转换为新类型, 不支持时返回None
+    fn modify_column(&self, table: &str, column: &str, column_type: &str) -> Option<String> {
+        Some(format!(
+            "alter table {} modify column {} {}",
+            table, column, column_type
+        ))
+    }
+
+    fn limit(&self, limit: Option<u64>, offset: u64) -> String {
+        match (limit, offset) {
+            (None, 0) => String::new(),
+            (Some(limit), 0) => format!(" limit {}", limit),
+            (limit, offset) => format!(" limit {} offset {}", limit.unwrap_or(u64::MAX), offset),
+        }
+    }
+
+    ///按层级小计, 不支持时返回None
+    fn rollup(&self, _keys: &[String]) -> Option<String> {
+        None
+    }
+
+    ///所有维度组合的小计, 不支持时返回None
+    fn cube(&self, _keys: &[String]) -> Option<String> {
+        None
+    }
+
+    ///自定义分组集合, 不支持时返回None
+    fn grouping_sets(&self, _sets: &[Vec<String>]) -> Option<String> {
+        None
+    }
+
+    fn grouping(&self, keys: &[String]) -> String {
+        format!("grouping({})", keys.join(","))
+    }
+
+    ///前N名成员的条件, `from` 包含from和where子句
+    fn top_n_members(
+        &self,
+        member: &str,
+        partition: Option<&str>,
+        rank_by: &str,
+        from: &str,
+        n: u32,
+    ) -> String {
+        match partition {
+            None => format!(
+                "{} in (select __member from (select {} as __member,\
+                 row_number() over (order by {} desc) as __rank{} group by {}) __top_n \
+                 where __rank <= {})",
+                member, member, rank_by, from, member, n
+            ),
+            Some(parent) => format!(
+                "({},{}) in (select __parent,__member from (select {} as __parent,{} as __member,\
+                 row_number() over (partition by {} order by {} desc) as __rank{} group by {},{}) \
+                 __top_n where __rank <= {})",
+                parent, member, parent, member, parent, rank_by, from, parent, member, n
+            ),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use crate::dialect::{ParamStyle, SqlDialect};
+use crate::query_builder::{DataType, DateUnit};
+
+pub struct MySqlDialect;
+
+impl SqlDialect for MySqlDialect {
+    fn name(&self) -> &'static str {
+        "MySQL"
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("`{}`", name.replace('`', "``"))
+    }
+
+    ///默认的sql_mode中反斜杠是转义符
+    fn quote_literal(&self, value: &str) -> String {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "''"))
+    }
+
+    fn param_style(&self) -> ParamStyle {
+        ParamStyle::QuestionMark
+    }
+
+    fn date_trunc(&self, unit: DateUnit, expr: &str) -> String {
+        match unit {
+            DateUnit::Year => format!("makedate(year({}),1)", expr),
+            DateUnit::Quarter => format!(
+                "makedate(year({}),1) + interval (quarter({}) - 1) quarter",
+                expr, expr
+            ),
+            DateUnit::Month => format!("cast(date_format({},'%Y-%m-01') as date)", expr),
+            DateUnit::Week => format!("date_sub(date({}),interval weekday({}) day)", expr, expr),
+            DateUnit::Day => format!("date({})", expr),
+        }
+    }
+
+    fn cast_to_text(&self, expr: &str) -> String {
+        format!("cast({} as char)", expr)
+    }
+
+    fn column_type(&self, data_type: DataType) -> String {
+        match data_type {
+            DataType::Text => String::from("text"),
+            DataType::Number => String::from("double"),
+            DataType::Date => String::from("date"),
+        }
+    }
+
+    fn rollup(&self, keys: &[String]) -> Option<String> {
+        Some(format!("{} with rollup", keys.join(",")))
+    }
+}
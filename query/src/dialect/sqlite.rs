@@ -0,0 +1,68 @@
+use crate::dialect::{ParamStyle, SqlDialect};
+use crate::query_builder::{DataType, DateUnit};
+
+pub struct SqliteDialect;
+
+impl SqlDialect for SqliteDialect {
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    fn param_style(&self) -> ParamStyle {
+        ParamStyle::Numbered
+    }
+
+    fn date_trunc(&self, unit: DateUnit, expr: &str) -> String {
+        match unit {
+            DateUnit::Year => format!("date({},'start of year')", expr),
+            DateUnit::Quarter => format!(
+                "date({},'start of month',\
+                 printf('-%d months',(cast(strftime('%m',{}) as integer) - 1) % 3))",
+                expr, expr
+            ),
+            DateUnit::Month => format!("date({},'start of month')", expr),
+            DateUnit::Week => format!("date({},'weekday 0','-6 days')", expr),
+            DateUnit::Day => format!("date({})", expr),
+        }
+    }
+
+    fn cast_to_text(&self, expr: &str) -> String {
+        format!("cast({} as text)", expr)
+    }
+
+    ///日期按 `yyyy-MM-dd` 文本存储
+    fn column_type(&self, data_type: DataType) -> String {
+        match data_type {
+            DataType::Text | DataType::Date => String::from("text"),
+            DataType::Number => String::from("real"),
+        }
+    }
+
+    fn rename_table(&self, from: &str, to: &str) -> String {
+        format!("alter table {} rename to {}", from, to)
+    }
+
+    ///sqlite没有truncate
+    fn truncate_table(&self, table: &str) -> String {
+        format!("delete from {}", table)
+    }
+
+    ///sqlite不能修改字段类型
+    fn modify_column(&self, _table: &str, _column: &str, _column_type: &str) -> Option<String> {
+        None
+    }
+
+    ///sqlite中 `limit -1` 表示不限制
+    fn limit(&self, limit: Option<u64>, offset: u64) -> String {
+        match (limit, offset) {
+            (None, 0) => String::new(),
+            (Some(limit), 0) => format!(" limit {}", limit),
+            (Some(limit), offset) => format!(" limit {} offset {}", limit, offset),
+            (None, offset) => format!(" limit -1 offset {}", offset),
+        }
+    }
+}
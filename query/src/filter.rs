@@ -0,0 +1,100 @@
+use crate::query_builder::Field;
+use serde::{Deserialize, Serialize};
+
+///过滤条件中的值, 日期使用 `yyyy-MM-dd` 格式的文本
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Value {
+    Null,
+    Number(f64),
+    Text(String),
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Number(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Number(v as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Text(v)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    In,
+    IsNull,
+}
+
+///过滤条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    pub field: Field,
+    pub op: FilterOp,
+    pub values: Vec<Value>,
+}
+
+impl Filter {
+    pub fn new(field: Field, op: FilterOp, values: Vec<Value>) -> Self {
+        Filter { field, op, values }
+    }
+
+    //=
+    pub fn eq(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Eq, vec![value])
+    }
+
+    // !=
+    pub fn ne(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Ne, vec![value])
+    }
+
+    //>
+    pub fn gt(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Gt, vec![value])
+    }
+
+    //<
+    pub fn lt(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Lt, vec![value])
+    }
+
+    //>=
+    pub fn ge(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Ge, vec![value])
+    }
+
+    //<=
+    pub fn le(field: Field, value: Value) -> Self {
+        Self::new(field, FilterOp::Le, vec![value])
+    }
+
+    // in (...)
+    pub fn in_list(field: Field, values: Vec<Value>) -> Self {
+        Self::new(field, FilterOp::In, values)
+    }
+
+    // is null
+    pub fn is_null(field: Field) -> Self {
+        Self::new(field, FilterOp::IsNull, vec![])
+    }
+}
@@ -1,5 +1,18 @@
+mod dialect;
+mod filter;
+mod pre_aggregation;
 mod query_builder;
+mod schema;
+mod sql_builder;
+mod validation;
 
+pub use self::dialect::{ClickHouseDialect, MySqlDialect, ParamStyle, SqlDialect, SqliteDialect};
+pub use self::filter::{Filter, FilterOp, Value};
+pub use self::pre_aggregation::PreAggregation;
 pub use self::query_builder::{
-    DataType, Dimension, Field, Measure, MeasureFn, Order, OrderType, QueryBuilder,
+    DataType, DateUnit, Dimension, Field, Measure, MeasureFn, Order, OrderType, QueryBuilder, TopN,
+    Totals, GROUPING_COLUMN, OTHERS_LABEL, QUERY_MODEL_VERSION,
 };
+pub use self::schema::SchemaChange;
+pub use self::sql_builder::CompiledQuery;
+pub use self::validation::{is_identifier, QueryError, ValidationError};
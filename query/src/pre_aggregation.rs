@@ -0,0 +1,482 @@
+use crate::query_builder::{
+    DataType, DateUnit, Dimension, Field, Measure, MeasureFn, QueryBuilder, Totals,
+};
+use crate::schema::SchemaChange;
+use crate::validation::{is_identifier, QueryError, ValidationError};
+use serde::{Deserialize, Serialize};
+
+///预聚合, 按维度预先汇总数据集的表, 能回答的查询改写到汇总表上执行
+///
+///汇总表中维度列与字段同名, 日期维度保存截断后的值, 度量列为 `函数_字段`, 如 `sum_amount`.
+///AVG不能由汇总结果再次计算, 包含AVG的查询不会改写
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreAggregation {
+    ///汇总表名
+    pub name: String,
+    ///数据集的表
+    pub table: String,
+    pub dimensions: Vec<Dimension>,
+    pub measures: Vec<Measure>,
+}
+
+impl PreAggregation {
+    pub fn new(
+        name: String,
+        table: String,
+        dimensions: Vec<Dimension>,
+        measures: Vec<Measure>,
+    ) -> Self {
+        PreAggregation {
+            name,
+            table,
+            dimensions,
+            measures,
+        }
+    }
+
+    ///以查询的行列维度和度量声明预聚合, 查询的其他部分不使用
+    pub fn from_query(name: String, qb: &QueryBuilder) -> Self {
+        let dimensions = qb.get_rows().iter().chain(qb.get_cols()).cloned().collect();
+        PreAggregation::new(
+            name,
+            qb.get_table().clone(),
+            dimensions,
+            qb.get_meas().clone(),
+        )
+    }
+
+    ///度量在汇总表中的列名
+    pub fn measure_column(measure: &Measure) -> String {
+        format!(
+            "{}_{}",
+            format!("{:?}", measure.measure_type).to_lowercase(),
+            measure.field.field_name
+        )
+    }
+
+    ///汇总表引用的源表字段
+    pub fn fields(&self) -> Vec<&Field> {
+        let mut fields: Vec<&Field> = self.dimensions.iter().map(|d| &d.field).collect();
+        fields.extend(self.measures.iter().map(|m| &m.field));
+        fields
+    }
+
+    ///检查表名, 字段名和度量函数, 建表前必须通过
+    pub fn check(&self) -> Result<(), ValidationError> {
+        let mut errors = vec![];
+        let mut names = vec![self.name.as_str(), self.table.as_str()];
+        names.extend(self.fields().iter().map(|f| f.field_name.as_str()));
+        for name in names {
+            let error = QueryError::InvalidIdentifier(name.to_string());
+            if !is_identifier(name) && !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        if self
+            .measures
+            .iter()
+            .any(|m| m.measure_type == MeasureFn::AVG)
+        {
+            errors.push(QueryError::Unsupported {
+                dialect: String::from("pre-aggregation"),
+                feature: String::from("AVG"),
+            });
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { errors })
+        }
+    }
+
+    ///源表变更后汇总表不能再增量汇总, 需要删除
+    pub fn is_broken_by(&self, change: &SchemaChange) -> bool {
+        let uses = |column: &str| self.fields().iter().any(|f| f.field_name == column);
+        match change {
+            SchemaChange::DropTable { table } | SchemaChange::RenameTable { from: table, .. } => {
+                *table == self.table
+            }
+            SchemaChange::DropColumn { table, column } => *table == self.table && uses(column),
+            SchemaChange::RenameColumn { table, from, .. } => *table == self.table && uses(from),
+            SchemaChange::ModifyColumn { table, field } => {
+                *table == self.table && uses(&field.field_name)
+            }
+            SchemaChange::CreateTable { .. }
+            | SchemaChange::AddColumn { .. }
+            | SchemaChange::TruncateTable { .. } => false,
+        }
+    }
+
+    ///从源表生成汇总数据的查询
+    pub fn source_query(&self) -> QueryBuilder {
+        let measures = self
+            .measures
+            .iter()
+            .map(|m| Measure::new(m.field.clone(), m.measure_type).alias(Self::measure_column(m)))
+            .collect();
+        QueryBuilder::new()
+            .table(self.table.clone())
+            .row(self.dimensions.clone())
+            .meas(measures)
+    }
+
+    fn dimension(&self, field_name: &str) -> Option<&Dimension> {
+        self.dimensions
+            .iter()
+            .find(|d| d.field.field_name == field_name)
+    }
+
+    ///可以直接按原值过滤的维度, 截断过的日期不能用于过滤
+    fn filterable(&self, field: &Field) -> bool {
+        matches!(self.dimension(&field.field_name), Some(d) if d.date_unit.is_none())
+    }
+
+    fn rewrite_dimension(&self, dimension: &Dimension) -> Option<Dimension> {
+        let own = self.dimension(&dimension.field.field_name)?;
+        if !derivable(own.date_unit, dimension.date_unit) {
+            return None;
+        }
+        let mut dimension = dimension.clone();
+        if own.date_unit == dimension.date_unit {
+            dimension.date_unit = None;
+        }
+        Some(dimension)
+    }
+
+    ///对汇总表的度量列再次汇总, COUNT的结果需要求和
+    fn rewrite_measure(&self, measure: &Measure) -> Option<Measure> {
+        let merge = match measure.measure_type {
+            MeasureFn::SUM | MeasureFn::COUNT => MeasureFn::SUM,
+            MeasureFn::MAX => MeasureFn::MAX,
+            MeasureFn::MIN => MeasureFn::MIN,
+            MeasureFn::AVG => return None,
+        };
+        self.measures.iter().find(|m| {
+            m.field.field_name == measure.field.field_name && m.measure_type == measure.measure_type
+        })?;
+        let field_type = match measure.measure_type {
+            MeasureFn::COUNT => DataType::Number,
+            _ => measure.field.field_type,
+        };
+        let field = Field::new(
+            Self::measure_column(measure),
+            field_type,
+            measure.field.display_name.clone(),
+        );
+        Some(Measure::new(field, merge).alias(measure.name().to_string()))
+    }
+
+    ///改写为查询汇总表, 结果的列与原查询相同. 不能由汇总表回答时返回None
+    pub fn rewrite(&self, qb: &QueryBuilder) -> Option<QueryBuilder> {
+        if *qb.get_table() != self.table {
+            return None;
+        }
+        let rows = qb
+            .get_rows()
+            .iter()
+            .map(|d| self.rewrite_dimension(d))
+            .collect::<Option<Vec<_>>>()?;
+        let cols = qb
+            .get_cols()
+            .iter()
+            .map(|d| self.rewrite_dimension(d))
+            .collect::<Option<Vec<_>>>()?;
+        let measures = qb
+            .get_meas()
+            .iter()
+            .map(|m| self.rewrite_measure(m))
+            .collect::<Option<Vec<_>>>()?;
+
+        if !qb.get_filters().iter().all(|f| self.filterable(&f.field)) {
+            return None;
+        }
+
+        // orders and grouping sets refer to the columns of the result
+        let mut names: Vec<&str> = rows
+            .iter()
+            .chain(&cols)
+            .map(|d| d.field.field_name.as_str())
+            .collect();
+        let dimensions = names.len();
+        names.extend(measures.iter().map(|m| m.name()));
+        if !qb
+            .get_orders()
+            .iter()
+            .all(|o| names.contains(&o.field.field_name.as_str()))
+        {
+            return None;
+        }
+        if let Totals::GroupingSets(sets) = qb.get_totals() {
+            if !sets
+                .iter()
+                .flatten()
+                .all(|f| names[..dimensions].contains(&f.field_name.as_str()))
+            {
+                return None;
+            }
+        }
+
+        let mut rewritten = QueryBuilder::new()
+            .table(self.name.clone())
+            .row(rows)
+            .col(cols)
+            .meas(measures)
+            .filter(qb.get_filters().clone())
+            .order(qb.get_orders().clone())
+            .offset(qb.get_offset());
+        if let Some(limit) = qb.get_limit() {
+            rewritten = rewritten.limit(limit);
+        }
+
+        rewritten = match qb.get_totals() {
+            Totals::None => rewritten,
+            Totals::Rollup => rewritten.rollup(),
+            Totals::Cube => rewritten.cube(),
+            Totals::GrandTotal => rewritten.grand_total(),
+            Totals::GroupingSets(sets) => rewritten.grouping_sets(sets.clone()),
+        };
+
+        if let Some(top_n) = qb.get_top_n() {
+            if self.dimension(&top_n.dimension.field_name).is_none()
+                || !top_n.partition.iter().all(|f| self.filterable(f))
+            {
+                return None;
+            }
+            let mut top_n = top_n.clone();
+            top_n.measure = self.rewrite_measure(&top_n.measure)?;
+            rewritten = rewritten.top_n(top_n);
+        }
+        Some(rewritten)
+    }
+}
+
+///能否由 `from` 粒度的日期得到 `to` 粒度, None表示不截断. 周不能由月得到
+fn derivable(from: Option<DateUnit>, to: Option<DateUnit>) -> bool {
+    match (from, to) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(from), Some(to)) if from == to => true,
+        (Some(DateUnit::Day), Some(_)) => true,
+        (Some(DateUnit::Month), Some(DateUnit::Quarter))
+        | (Some(DateUnit::Month), Some(DateUnit::Year))
+        | (Some(DateUnit::Quarter), Some(DateUnit::Year)) => true,
+        _ => false,
+    }
+}
+
+impl QueryBuilder {
+    ///改写到第一个能回答查询的预聚合, 调用方按汇总表从小到大排列
+    pub fn route<'a, I>(&self, pre_aggregations: I) -> Option<QueryBuilder>
+    where
+        I: IntoIterator<Item = &'a PreAggregation>,
+    {
+        pre_aggregations.into_iter().find_map(|p| p.rewrite(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::ClickHouseDialect;
+    use crate::filter::{Filter, Value};
+    use crate::query_builder::{Order, OrderType, TopN};
+
+    fn region() -> Field {
+        Field::new(String::from("region"), DataType::Text, String::from("地区"))
+    }
+
+    fn city() -> Field {
+        Field::new(String::from("city"), DataType::Text, String::from("城市"))
+    }
+
+    fn day() -> Field {
+        Field::new(String::from("day"), DataType::Date, String::from("日期"))
+    }
+
+    fn amount() -> Field {
+        Field::new(
+            String::from("amount"),
+            DataType::Number,
+            String::from("金额"),
+        )
+    }
+
+    fn by_region_month() -> PreAggregation {
+        PreAggregation::new(
+            String::from("sales_region_month"),
+            String::from("sales"),
+            vec![
+                Dimension::new_row(region()),
+                Dimension::new_row(day()).trunc(DateUnit::Month),
+            ],
+            vec![
+                Measure::new(amount(), MeasureFn::SUM),
+                Measure::new(amount(), MeasureFn::COUNT),
+            ],
+        )
+    }
+
+    fn by_city_day() -> PreAggregation {
+        PreAggregation::new(
+            String::from("sales_city_day"),
+            String::from("sales"),
+            vec![
+                Dimension::new_row(region()),
+                Dimension::new_row(city()),
+                Dimension::new_row(day()),
+            ],
+            vec![
+                Measure::new(amount(), MeasureFn::SUM),
+                Measure::new(amount(), MeasureFn::MAX),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_source_query() {
+        let pre = by_region_month();
+        assert_eq!(pre.check(), Ok(()));
+        assert_eq!(
+            pre.source_query().to_sql(&ClickHouseDialect).unwrap().sql,
+            "select `region`,`__d1` as `day`,`__m0` as `sum_amount`,`__m1` as `count_amount` \
+             from (select `region`,toStartOfMonth(`day`) as `__d1`,sum(`amount`) as `__m0`,\
+             count(`amount`) as `__m1` from `sales` \
+             group by `region`,toStartOfMonth(`day`)) as __result"
+        );
+
+        let mut pre = by_city_day();
+        pre.name = String::from("t; drop table t");
+        pre.measures.push(Measure::new(amount(), MeasureFn::AVG));
+        assert_eq!(pre.check().unwrap_err().errors.len(), 2);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(day()).trunc(DateUnit::Quarter)])
+            .meas(vec![
+                Measure::new(amount(), MeasureFn::SUM),
+                Measure::new(amount(), MeasureFn::COUNT).alias(String::from("orders")),
+            ])
+            .filter(vec![Filter::eq(region(), Value::from("east"))])
+            .order(vec![Order::new_with_order(amount(), OrderType::DESC)])
+            .limit(10);
+        let rewritten = by_region_month().rewrite(&qb).unwrap();
+        assert_eq!(
+            rewritten.to_sql(&ClickHouseDialect).unwrap().sql,
+            "select `__d0` as `day`,`__m0` as `amount`,`__m1` as `orders` from \
+             (select toStartOfQuarter(`day`) as `__d0`,sum(`sum_amount`) as `__m0`,\
+             sum(`count_amount`) as `__m1` from `sales_region_month` \
+             where `region` = 'east' group by toStartOfQuarter(`day`)) as __result \
+             order by `__m0` desc limit 10"
+        );
+
+        // the same unit is not truncated again
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![
+                Dimension::new_row(region()),
+                Dimension::new_row(day()).trunc(DateUnit::Month),
+            ])
+            .meas(vec![Measure::new(amount(), MeasureFn::SUM)])
+            .rollup();
+        let rewritten = by_region_month().rewrite(&qb).unwrap();
+        assert_eq!(
+            rewritten.to_sql(&ClickHouseDialect).unwrap().sql,
+            "select `region`,`day`,`__m0` as `amount`,`__grouping` from \
+             (select `region`,`day`,sum(`sum_amount`) as `__m0`,\
+             grouping(`region`,`day`) as `__grouping` from `sales_region_month` \
+             group by `region`,`day` with rollup) as __result"
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(city())])
+            .meas(vec![Measure::new(amount(), MeasureFn::MAX)])
+            .top_n(TopN::new(city(), Measure::new(amount(), MeasureFn::SUM), 3));
+        let rewritten = by_city_day().rewrite(&qb).unwrap();
+        assert_eq!(
+            rewritten.to_sql(&ClickHouseDialect).unwrap().sql,
+            "select `__d0` as `city`,`__m0` as `amount` from (select case when `city` in \
+             (select `city` from `sales_city_day` group by `city` \
+             order by sum(`sum_amount`) desc limit 3) then toString(`city`) else 'Others' end \
+             as `__d0`,max(`max_amount`) as `__m0` from `sales_city_day` \
+             group by case when `city` in (select `city` from `sales_city_day` group by `city` \
+             order by sum(`sum_amount`) desc limit 3) then toString(`city`) else 'Others' end) \
+             as __result"
+        );
+    }
+
+    #[test]
+    fn test_not_rewritten() {
+        let pre = by_region_month();
+        let qb = || {
+            QueryBuilder::new()
+                .table(String::from("sales"))
+                .meas(vec![Measure::new(amount(), MeasureFn::SUM)])
+        };
+        assert!(pre.rewrite(&qb()).is_some());
+        assert!(pre.rewrite(&qb().table(String::from("orders"))).is_none());
+        assert!(pre
+            .rewrite(&qb().row(vec![Dimension::new_row(city())]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().row(vec![Dimension::new_row(day())]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().row(vec![Dimension::new_row(day()).trunc(DateUnit::Week)]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().meas(vec![Measure::new(amount(), MeasureFn::AVG)]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().meas(vec![Measure::new(amount(), MeasureFn::MAX)]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().filter(vec![Filter::ge(day(), Value::from("2021-01-01"))]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().filter(vec![Filter::gt(amount(), Value::from(0_i64))]))
+            .is_none());
+        assert!(pre
+            .rewrite(&qb().order(vec![Order::new(region())]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_is_broken_by() {
+        let pre = by_region_month();
+        let change = |column: &str| SchemaChange::DropColumn {
+            table: String::from("sales"),
+            column: column.to_string(),
+        };
+        assert!(pre.is_broken_by(&change("day")));
+        assert!(!pre.is_broken_by(&change("city")));
+        assert!(pre.is_broken_by(&SchemaChange::RenameTable {
+            from: String::from("sales"),
+            to: String::from("t_sales"),
+        }));
+        assert!(!pre.is_broken_by(&SchemaChange::DropTable {
+            table: String::from("orders"),
+        }));
+    }
+
+    #[test]
+    fn test_route() {
+        let pre_aggregations = vec![by_region_month(), by_city_day()];
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(region())])
+            .meas(vec![Measure::new(amount(), MeasureFn::SUM)]);
+        let routed = qb.route(&pre_aggregations).unwrap();
+        assert_eq!(routed.get_table(), "sales_region_month");
+
+        let qb = qb.filter(vec![Filter::ge(day(), Value::from("2021-01-01"))]);
+        let routed = qb.route(&pre_aggregations).unwrap();
+        assert_eq!(routed.get_table(), "sales_city_day");
+
+        let qb = qb.meas(vec![Measure::new(amount(), MeasureFn::MIN)]);
+        assert!(qb.route(&pre_aggregations).is_none());
+    }
+}
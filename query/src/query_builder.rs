@@ -1,11 +1,20 @@
-#[derive(Debug, Clone)]
+use crate::filter::Filter;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryBuilder {
     rows: Vec<Dimension>,
     columns: Vec<Dimension>,
     measures: Vec<Measure>,
     orders: Vec<Order>,
-    filters: Vec<Measure>,
+    filters: Vec<Filter>,
     table: String,
+    totals: Totals,
+    top_n: Option<TopN>,
+    #[serde(default)]
+    limit: Option<u64>,
+    #[serde(default)]
+    offset: u64,
 }
 
 impl QueryBuilder {
@@ -17,6 +26,10 @@ impl QueryBuilder {
             orders: vec![],
             filters: vec![],
             table: String::new(),
+            totals: Totals::None,
+            top_n: None,
+            limit: None,
+            offset: 0,
         }
     }
 
@@ -25,18 +38,18 @@ impl QueryBuilder {
         self
     }
 
-    pub fn row(mut self, rows: &mut Vec<Dimension>) -> Self {
-        self.rows.append(rows);
+    pub fn row(mut self, mut rows: Vec<Dimension>) -> Self {
+        self.rows.append(&mut rows);
         self
     }
 
-    pub fn col(mut self, columns: &mut Vec<Dimension>) -> Self {
-        self.columns.append(columns);
+    pub fn col(mut self, mut columns: Vec<Dimension>) -> Self {
+        self.columns.append(&mut columns);
         self
     }
 
-    pub fn meas(mut self, measures: &mut Vec<Measure>) -> Self {
-        self.measures.append(measures);
+    pub fn meas(mut self, mut measures: Vec<Measure>) -> Self {
+        self.measures.append(&mut measures);
         self
     }
 
@@ -63,17 +76,131 @@ impl QueryBuilder {
         res
     }
 
-    pub fn order(mut self, orders: &mut Vec<Order>) -> Self {
-        self.orders.append(orders);
+    pub fn order(mut self, mut orders: Vec<Order>) -> Self {
+        self.orders.append(&mut orders);
+        self
+    }
+
+    pub fn get_orders(&self) -> &Vec<Order> {
+        &self.orders
+    }
+
+    ///过滤条件, 多个条件之间为and
+    pub fn filter(mut self, mut filters: Vec<Filter>) -> Self {
+        self.filters.append(&mut filters);
+        self
+    }
+
+    pub fn get_filters(&self) -> &Vec<Filter> {
+        &self.filters
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
         self
     }
 
-    pub fn filter(&self, filters: Vec<&str>) -> &Self {
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
         self
     }
+
+    pub fn get_limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    ///按维度层级生成小计和总计
+    pub fn rollup(mut self) -> Self {
+        self.totals = Totals::Rollup;
+        self
+    }
+
+    ///生成所有维度组合的小计和总计
+    pub fn cube(mut self) -> Self {
+        self.totals = Totals::Cube;
+        self
+    }
+
+    ///只生成总计
+    pub fn grand_total(mut self) -> Self {
+        self.totals = Totals::GrandTotal;
+        self
+    }
+
+    ///自定义分组集合, 空集合表示总计
+    pub fn grouping_sets(mut self, sets: Vec<Vec<Field>>) -> Self {
+        self.totals = Totals::GroupingSets(sets);
+        self
+    }
+
+    pub fn get_totals(&self) -> &Totals {
+        &self.totals
+    }
+
+    ///维度只保留排名前N的成员, 其余合并为一行
+    pub fn top_n(mut self, top_n: TopN) -> Self {
+        self.top_n = Some(top_n);
+        self
+    }
+
+    pub fn get_top_n(&self) -> Option<&TopN> {
+        self.top_n.as_ref()
+    }
+
+    ///查询中引用的所有字段
+    pub fn get_fields(&self) -> Vec<&Field> {
+        let mut fields = vec![];
+        fields.extend(self.rows.iter().map(|d| &d.field));
+        fields.extend(self.columns.iter().map(|d| &d.field));
+        fields.extend(self.measures.iter().map(|m| &m.field));
+        fields.extend(self.orders.iter().map(|o| &o.field));
+        fields.extend(self.filters.iter().map(|f| &f.field));
+        if let Totals::GroupingSets(sets) = &self.totals {
+            sets.iter().for_each(|set| fields.extend(set.iter()));
+        }
+        if let Some(top_n) = &self.top_n {
+            fields.push(&top_n.dimension);
+            fields.push(&top_n.measure.field);
+            fields.extend(top_n.partition.iter());
+        }
+        fields
+    }
+
+    ///序列化为带版本号的json, 用于保存查询
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&VersionedQuery {
+            version: QUERY_MODEL_VERSION,
+            query: self.clone(),
+        })
+    }
+
+    ///从保存的json加载查询, 不支持比当前更新的版本
+    pub fn from_json(json: &str) -> serde_json::Result<QueryBuilder> {
+        let versioned: VersionedQuery = serde_json::from_str(json)?;
+        if versioned.version > QUERY_MODEL_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "unsupported query model version {}, expected at most {}",
+                versioned.version, QUERY_MODEL_VERSION
+            )));
+        }
+        Ok(versioned.query)
+    }
 }
 
-#[derive(Debug, Clone)]
+///查询模型的版本, 结构不兼容时递增
+pub const QUERY_MODEL_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct VersionedQuery {
+    version: u32,
+    query: QueryBuilder,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Field {
     pub field_name: String,
     pub field_type: DataType,
@@ -94,10 +221,13 @@ impl Field {
 }
 
 ///维度
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dimension {
     pub dimension_type: DimensionType,
     pub field: Field,
+    ///日期维度按粒度截断
+    #[serde(default)]
+    pub date_unit: Option<DateUnit>,
 }
 
 impl Dimension {
@@ -105,6 +235,7 @@ impl Dimension {
         Dimension {
             dimension_type: DimensionType::Row,
             field,
+            date_unit: None,
         }
     }
 
@@ -112,15 +243,24 @@ impl Dimension {
         Dimension {
             dimension_type: DimensionType::Column,
             field,
+            date_unit: None,
         }
     }
+
+    pub fn trunc(mut self, date_unit: DateUnit) -> Self {
+        self.date_unit = Some(date_unit);
+        self
+    }
 }
 
 ///度量
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Measure {
     pub field: Field,
     pub measure_type: MeasureFn,
+    ///结果中的列名, 默认为字段名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
 }
 
 impl Measure {
@@ -128,14 +268,60 @@ impl Measure {
         Measure {
             field,
             measure_type,
+            alias: None,
         }
     }
+
+    pub fn alias(mut self, alias: String) -> Self {
+        self.alias = Some(alias);
+        self
+    }
+
+    ///结果中的列名
+    pub fn name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.field.field_name)
+    }
+}
+
+pub const OTHERS_LABEL: &str = "Others";
+
+///前N名, 按度量排名, 其余成员归入 `others_label`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopN {
+    pub dimension: Field,
+    pub measure: Measure,
+    pub n: u32,
+    ///在每个上级分组内分别取前N名
+    pub partition: Option<Field>,
+    pub others_label: String,
+}
+
+impl TopN {
+    pub fn new(dimension: Field, measure: Measure, n: u32) -> Self {
+        TopN {
+            dimension,
+            measure,
+            n,
+            partition: None,
+            others_label: OTHERS_LABEL.to_string(),
+        }
+    }
+
+    pub fn partition_by(mut self, parent: Field) -> Self {
+        self.partition = Some(parent);
+        self
+    }
+
+    pub fn others_label(mut self, others_label: String) -> Self {
+        self.others_label = others_label;
+        self
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
-    field: Field,
-    order_type: OrderType,
+    pub field: Field,
+    pub order_type: OrderType,
 }
 
 impl Order {
@@ -146,57 +332,56 @@ impl Order {
         }
     }
 
-    fn new_with_order(field: Field, order_type: OrderType) -> Self {
+    pub fn new_with_order(field: Field, order_type: OrderType) -> Self {
         Order { field, order_type }
     }
 }
 
-pub struct Filter {
-    field: Field,
+///小计和总计的生成方式
+///
+///结果中的小计行和总计行通过 `GROUPING_COLUMN` 列标记: 按维度顺序,
+///被汇总掉的维度对应位为1, 明细行为0
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Totals {
+    None,
+    Rollup,
+    Cube,
+    GrandTotal,
+    GroupingSets(Vec<Vec<Field>>),
 }
 
-impl Filter {
-    //=
-    fn eq(self, f1: Field, f2: Field) {}
-    // !=
-    fn ne(self, f1: Field, f2: Field) {}
-
-    //>
-    fn gt(self, f1: Field, f2: Field) {}
-    //<
-    fn lt(self, f1: Field, f2: Field) {}
-
-    //>=
-    fn ge(self, f1: Field, f2: Field) {}
-
-    //<=
-    fn le(self, f1: Field, f2: Field) {}
+pub const GROUPING_COLUMN: &str = "__grouping";
 
-    fn and() {}
-
-    fn or() {}
-}
-
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum DimensionType {
     Row,
     Column,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DataType {
     Text,
     Number,
     Date,
 }
 
-#[derive(Debug, Copy, Clone)]
+///日期截断的粒度, 周从周一开始
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateUnit {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum OrderType {
     ASC,
     DESC,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MeasureFn {
     SUM,
     MAX,
@@ -232,14 +417,39 @@ mod tests {
 
         let qb = QueryBuilder::new()
             .table(String::from("tb1"))
-            .row(&mut vec![Dimension::new_row(f1), Dimension::new_row(f3)])
-            .col(&mut vec![Dimension::new_col(f2), Dimension::new_col(f4)])
-            .meas(&mut vec![
+            .row(vec![Dimension::new_row(f1), Dimension::new_row(f3)])
+            .col(vec![Dimension::new_col(f2), Dimension::new_col(f4)])
+            .meas(vec![
                 Measure::new(f5, MeasureFn::SUM),
                 Measure::new(f6.clone(), MeasureFn::MAX),
             ])
-            .order(&mut vec![Order::new(f6)]);
+            .order(vec![Order::new(f6)]);
 
         println!("{:?}", qb);
     }
+
+    #[test]
+    fn test_json() {
+        let f1 = Field::new(String::from("field1"), DataType::Text, String::from("单位"));
+        let f2 = Field::new(
+            String::from("field2"),
+            DataType::Number,
+            String::from("价格"),
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("tb1"))
+            .row(vec![Dimension::new_row(f1.clone())])
+            .meas(vec![Measure::new(f2.clone(), MeasureFn::SUM)])
+            .top_n(TopN::new(f1, Measure::new(f2, MeasureFn::SUM), 5))
+            .rollup();
+
+        let json = qb.to_json().unwrap();
+        let loaded = QueryBuilder::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json().unwrap(), json);
+        assert_eq!(loaded.get_fields().len(), 4);
+
+        let json = json.replacen("\"version\":1", "\"version\":99", 1);
+        assert!(QueryBuilder::from_json(&json).is_err());
+    }
 }
@@ -0,0 +1,277 @@
+use crate::dialect::SqlDialect;
+use crate::query_builder::Field;
+use crate::validation::{is_identifier, QueryError, ValidationError};
+use serde::{Deserialize, Serialize};
+
+///物理表的结构变更
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SchemaChange {
+    ///`order_by` 为排序键, 只有ClickHouse使用
+    CreateTable {
+        table: String,
+        fields: Vec<Field>,
+        order_by: Vec<String>,
+    },
+    AddColumn {
+        table: String,
+        field: Field,
+    },
+    DropColumn {
+        table: String,
+        column: String,
+    },
+    RenameColumn {
+        table: String,
+        from: String,
+        to: String,
+    },
+    ///按 `field` 修改字段类型, 已有数据转换为新类型
+    ModifyColumn {
+        table: String,
+        field: Field,
+    },
+    RenameTable {
+        from: String,
+        to: String,
+    },
+    TruncateTable {
+        table: String,
+    },
+    DropTable {
+        table: String,
+    },
+}
+
+impl SchemaChange {
+    ///变更涉及的表
+    pub fn tables(&self) -> Vec<&str> {
+        match self {
+            SchemaChange::CreateTable { table, .. }
+            | SchemaChange::AddColumn { table, .. }
+            | SchemaChange::DropColumn { table, .. }
+            | SchemaChange::RenameColumn { table, .. }
+            | SchemaChange::ModifyColumn { table, .. }
+            | SchemaChange::TruncateTable { table }
+            | SchemaChange::DropTable { table } => vec![table],
+            SchemaChange::RenameTable { from, to } => vec![from, to],
+        }
+    }
+
+    ///变更涉及的表名和字段名
+    pub fn identifiers(&self) -> Vec<&str> {
+        match self {
+            SchemaChange::CreateTable {
+                table,
+                fields,
+                order_by,
+            } => {
+                let mut names = vec![table.as_str()];
+                names.extend(fields.iter().map(|f| f.field_name.as_str()));
+                names.extend(order_by.iter().map(|c| c.as_str()));
+                names
+            }
+            SchemaChange::AddColumn { table, field }
+            | SchemaChange::ModifyColumn { table, field } => {
+                vec![table, &field.field_name]
+            }
+            SchemaChange::DropColumn { table, column } => vec![table, column],
+            SchemaChange::RenameColumn { table, from, to } => vec![table, from, to],
+            SchemaChange::RenameTable { from, to } => vec![from, to],
+            SchemaChange::TruncateTable { table } | SchemaChange::DropTable { table } => {
+                vec![table]
+            }
+        }
+    }
+
+    ///校验标识符, 并且建表时至少有一个字段
+    pub fn check(&self) -> Result<(), ValidationError> {
+        let mut errors: Vec<QueryError> = self
+            .identifiers()
+            .into_iter()
+            .filter(|name| !is_identifier(name))
+            .map(|name| QueryError::InvalidIdentifier(name.to_string()))
+            .collect();
+        if let SchemaChange::CreateTable { table, fields, .. } = self {
+            if fields.is_empty() {
+                errors.push(QueryError::EmptyTable(table.clone()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { errors })
+        }
+    }
+
+    ///按方言编译为ddl
+    pub fn to_sql(&self, dialect: &dyn SqlDialect) -> Result<String, ValidationError> {
+        self.check()?;
+        let quote = |name: &str| dialect.quote_identifier(name);
+        let sql = match self {
+            SchemaChange::CreateTable {
+                table,
+                fields,
+                order_by,
+            } => {
+                let columns: Vec<String> = fields
+                    .iter()
+                    .map(|f| {
+                        format!(
+                            "{} {}",
+                            quote(&f.field_name),
+                            dialect.column_type(f.field_type)
+                        )
+                    })
+                    .collect();
+                let order_by: Vec<String> = order_by.iter().map(|c| quote(c)).collect();
+                format!(
+                    "create table if not exists {} ({}){}",
+                    quote(table),
+                    columns.join(","),
+                    dialect.table_options(&order_by)
+                )
+            }
+            SchemaChange::AddColumn { table, field } => format!(
+                "alter table {} add column {} {}",
+                quote(table),
+                quote(&field.field_name),
+                dialect.column_type(field.field_type)
+            ),
+            SchemaChange::DropColumn { table, column } => {
+                format!("alter table {} drop column {}", quote(table), quote(column))
+            }
+            SchemaChange::RenameColumn { table, from, to } => format!(
+                "alter table {} rename column {} to {}",
+                quote(table),
+                quote(from),
+                quote(to)
+            ),
+            SchemaChange::ModifyColumn { table, field } => dialect
+                .modify_column(
+                    &quote(table),
+                    &quote(&field.field_name),
+                    &dialect.column_type(field.field_type),
+                )
+                .ok_or_else(|| ValidationError {
+                    errors: vec![QueryError::Unsupported {
+                        dialect: dialect.name().to_string(),
+                        feature: String::from("modify column"),
+                    }],
+                })?,
+            SchemaChange::RenameTable { from, to } => {
+                dialect.rename_table(&quote(from), &quote(to))
+            }
+            SchemaChange::TruncateTable { table } => dialect.truncate_table(&quote(table)),
+            SchemaChange::DropTable { table } => format!("drop table if exists {}", quote(table)),
+        };
+        Ok(sql)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{ClickHouseDialect, MySqlDialect, SqliteDialect};
+    use crate::query_builder::DataType;
+
+    fn create() -> SchemaChange {
+        SchemaChange::CreateTable {
+            table: String::from("t_1"),
+            fields: vec![
+                Field::new(String::from("org"), DataType::Text, String::from("组织")),
+                Field::new(String::from("day"), DataType::Date, String::from("时间")),
+                Field::new(
+                    String::from("amount"),
+                    DataType::Number,
+                    String::from("金额"),
+                ),
+            ],
+            order_by: vec![String::from("day")],
+        }
+    }
+
+    #[test]
+    fn test_create_table() {
+        assert_eq!(
+            create().to_sql(&ClickHouseDialect).unwrap(),
+            "create table if not exists `t_1` (`org` Nullable(String),`day` Nullable(Date),\
+             `amount` Nullable(Float64)) engine = MergeTree() order by (`day`) \
+             settings allow_nullable_key = 1"
+        );
+        assert_eq!(
+            create().to_sql(&MySqlDialect).unwrap(),
+            "create table if not exists `t_1` (`org` text,`day` date,`amount` double)"
+        );
+        assert_eq!(
+            create().to_sql(&SqliteDialect).unwrap(),
+            "create table if not exists \"t_1\" (\"org\" text,\"day\" text,\"amount\" real)"
+        );
+
+        let change = SchemaChange::CreateTable {
+            table: String::from("t_1"),
+            fields: vec![],
+            order_by: vec![],
+        };
+        assert_eq!(
+            change.to_sql(&ClickHouseDialect).unwrap_err().errors,
+            vec![QueryError::EmptyTable(String::from("t_1"))]
+        );
+    }
+
+    #[test]
+    fn test_alter_table() {
+        let rename = SchemaChange::RenameTable {
+            from: String::from("t_1"),
+            to: String::from("t_2"),
+        };
+        assert_eq!(
+            rename.to_sql(&ClickHouseDialect).unwrap(),
+            "rename table `t_1` to `t_2`"
+        );
+        assert_eq!(
+            rename.to_sql(&SqliteDialect).unwrap(),
+            "alter table \"t_1\" rename to \"t_2\""
+        );
+
+        let truncate = SchemaChange::TruncateTable {
+            table: String::from("t_1"),
+        };
+        assert_eq!(
+            truncate.to_sql(&ClickHouseDialect).unwrap(),
+            "truncate table `t_1`"
+        );
+        assert_eq!(
+            truncate.to_sql(&SqliteDialect).unwrap(),
+            "delete from \"t_1\""
+        );
+
+        let rename = SchemaChange::RenameColumn {
+            table: String::from("t_1"),
+            from: String::from("org"),
+            to: String::from("org; drop table t_1"),
+        };
+        assert_eq!(
+            rename.to_sql(&ClickHouseDialect).unwrap_err().errors,
+            vec![QueryError::InvalidIdentifier(String::from(
+                "org; drop table t_1"
+            ))]
+        );
+
+        let modify = SchemaChange::ModifyColumn {
+            table: String::from("t_1"),
+            field: Field::new(String::from("day"), DataType::Date, String::from("时间")),
+        };
+        assert_eq!(
+            modify.to_sql(&ClickHouseDialect).unwrap(),
+            "alter table `t_1` modify column `day` Nullable(Date)"
+        );
+        assert_eq!(
+            modify.to_sql(&MySqlDialect).unwrap(),
+            "alter table `t_1` modify column `day` date"
+        );
+        assert_eq!(
+            modify.to_sql(&SqliteDialect).unwrap_err().to_string(),
+            "invalid query: modify column is not supported by SQLite"
+        );
+    }
+}
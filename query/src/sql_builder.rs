@@ -0,0 +1,512 @@
+use crate::dialect::{ParamStyle, SqlDialect};
+use crate::filter::{Filter, FilterOp, Value};
+use crate::query_builder::{Dimension, Field, OrderType, QueryBuilder, Totals, GROUPING_COLUMN};
+use crate::validation::{QueryError, ValidationError};
+
+///编译后的sql和按顺序绑定的参数
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledQuery {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+struct SqlWriter<'a> {
+    dialect: &'a dyn SqlDialect,
+    params: Vec<Value>,
+}
+
+impl<'a> SqlWriter<'a> {
+    fn column(&self, field: &Field) -> String {
+        self.dialect.quote_identifier(&field.field_name)
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        if let Value::Null = value {
+            return String::from("null");
+        }
+        match self.dialect.param_style() {
+            ParamStyle::Inline => match value {
+                Value::Number(n) => n.to_string(),
+                Value::Text(s) => self.dialect.quote_literal(s),
+                Value::Null => String::from("null"),
+            },
+            ParamStyle::QuestionMark => {
+                self.params.push(value.clone());
+                String::from("?")
+            }
+            ParamStyle::Numbered => {
+                self.params.push(value.clone());
+                format!("?{}", self.params.len())
+            }
+        }
+    }
+
+    fn filter(&mut self, filter: &Filter) -> String {
+        let column = self.column(&filter.field);
+        let op = match filter.op {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "!=",
+            FilterOp::Gt => ">",
+            FilterOp::Lt => "<",
+            FilterOp::Ge => ">=",
+            FilterOp::Le => "<=",
+            FilterOp::IsNull => return format!("{} is null", column),
+            FilterOp::In => {
+                if filter.values.is_empty() {
+                    return String::from("1 = 0");
+                }
+                let values: Vec<String> = filter.values.iter().map(|v| self.value(v)).collect();
+                return format!("{} in ({})", column, values.join(","));
+            }
+        };
+        let value = filter.values.first().cloned().unwrap_or(Value::Null);
+        format!("{} {} {}", column, op, self.value(&value))
+    }
+
+    ///from子句和where子句
+    fn from(&mut self, qb: &QueryBuilder) -> String {
+        let mut sql = format!(" from {}", self.dialect.quote_identifier(qb.get_table()));
+        let filters: Vec<String> = qb.get_filters().iter().map(|f| self.filter(f)).collect();
+        if !filters.is_empty() {
+            sql.push_str(" where ");
+            sql.push_str(&filters.join(" and "));
+        }
+        sql
+    }
+
+    ///维度的表达式, 用于select和group by
+    fn dimension(&mut self, qb: &QueryBuilder, dimension: &Dimension) -> String {
+        let mut expr = self.column(&dimension.field);
+        if let Some(unit) = dimension.date_unit {
+            expr = self.dialect.date_trunc(unit, &expr);
+        }
+
+        let top_n = match qb.get_top_n() {
+            Some(top_n) if top_n.dimension.field_name == dimension.field.field_name => top_n,
+            _ => return expr,
+        };
+        let partition = top_n.partition.as_ref().map(|f| self.column(f));
+        let rank_by = self.dialect.aggregate(
+            top_n.measure.measure_type,
+            &self.column(&top_n.measure.field),
+        );
+        let from = self.from(qb);
+        let keep =
+            self.dialect
+                .top_n_members(&expr, partition.as_deref(), &rank_by, &from, top_n.n);
+        format!(
+            "case when {} then {} else {} end",
+            keep,
+            self.dialect.cast_to_text(&expr),
+            self.dialect.quote_literal(&top_n.others_label)
+        )
+    }
+
+    fn unsupported(&self, feature: &str) -> ValidationError {
+        ValidationError {
+            errors: vec![QueryError::Unsupported {
+                dialect: self.dialect.name().to_string(),
+                feature: feature.to_string(),
+            }],
+        }
+    }
+
+    fn group_by(
+        &self,
+        qb: &QueryBuilder,
+        keys: &[(&Field, String)],
+    ) -> Result<String, ValidationError> {
+        let exprs: Vec<String> = keys.iter().map(|(_, e)| e.clone()).collect();
+        let group = match qb.get_totals() {
+            Totals::None => Some(exprs.join(",")),
+            Totals::Rollup => self.dialect.rollup(&exprs),
+            Totals::Cube => self.dialect.cube(&exprs),
+            Totals::GrandTotal => self.dialect.grouping_sets(&[exprs.clone(), vec![]]),
+            Totals::GroupingSets(sets) => {
+                let sets: Vec<Vec<String>> = sets
+                    .iter()
+                    .map(|set| {
+                        set.iter()
+                            .map(|f| {
+                                keys.iter()
+                                    .find(|(k, _)| k.field_name == f.field_name)
+                                    .map(|(_, e)| e.clone())
+                                    .unwrap_or_else(|| self.column(f))
+                            })
+                            .collect()
+                    })
+                    .collect();
+                self.dialect.grouping_sets(&sets)
+            }
+        };
+        let feature = match qb.get_totals() {
+            Totals::None => "group by",
+            Totals::Rollup => "rollup",
+            Totals::Cube => "cube",
+            Totals::GrandTotal | Totals::GroupingSets(_) => "grouping sets",
+        };
+        group
+            .map(|g| format!(" group by {}", g))
+            .ok_or_else(|| self.unsupported(feature))
+    }
+}
+
+impl QueryBuilder {
+    ///按方言编译为sql, 参数按 `params` 的顺序绑定
+    pub fn to_sql(&self, dialect: &dyn SqlDialect) -> Result<CompiledQuery, ValidationError> {
+        self.check_identifiers()?;
+        let mut writer = SqlWriter {
+            dialect,
+            params: vec![],
+        };
+
+        let dimensions: Vec<&Dimension> = self.get_rows().iter().chain(self.get_cols()).collect();
+        let has_totals = !matches!(self.get_totals(), Totals::None);
+        if has_totals && dimensions.is_empty() {
+            return Err(writer.unsupported("totals without dimensions"));
+        }
+
+        // the expressions are rendered again wherever they appear, so that
+        // positional parameters are bound in the order of the sql text
+        let keys = |writer: &mut SqlWriter| -> Vec<(&Field, String)> {
+            dimensions
+                .iter()
+                .map(|d| (&d.field, writer.dimension(self, d)))
+                .collect()
+        };
+
+        // aggregates are aliased with names which cannot be columns, otherwise
+        // clickhouse would replace the columns in the where clause with the alias.
+        // the outer select restores the names of the result columns
+        let mut select = vec![];
+        let mut columns = vec![];
+        for (i, (field, expr)) in keys(&mut writer).into_iter().enumerate() {
+            let column = writer.column(field);
+            if expr == column {
+                select.push(expr);
+                columns.push((field.field_name.as_str(), column.clone(), column));
+            } else {
+                let alias = dialect.quote_identifier(&format!("__d{}", i));
+                select.push(format!("{} as {}", expr, alias));
+                columns.push((field.field_name.as_str(), alias, column));
+            }
+        }
+
+        for (i, measure) in self.get_meas().iter().enumerate() {
+            let alias = dialect.quote_identifier(&format!("__m{}", i));
+            select.push(format!(
+                "{} as {}",
+                dialect.aggregate(measure.measure_type, &writer.column(&measure.field)),
+                alias
+            ));
+            columns.push((
+                measure.name(),
+                alias,
+                dialect.quote_identifier(measure.name()),
+            ));
+        }
+
+        if has_totals {
+            let exprs: Vec<String> = keys(&mut writer).into_iter().map(|(_, e)| e).collect();
+            let column = dialect.quote_identifier(GROUPING_COLUMN);
+            select.push(format!("{} as {}", dialect.grouping(&exprs), column));
+            columns.push((GROUPING_COLUMN, column.clone(), column));
+        }
+
+        let mut sql = format!("select {}", select.join(","));
+        sql.push_str(&writer.from(self));
+
+        if !dimensions.is_empty() {
+            let keys = keys(&mut writer);
+            sql.push_str(&writer.group_by(self, &keys)?);
+        }
+
+        if columns.iter().any(|(_, alias, column)| alias != column) {
+            let outer: Vec<String> = columns
+                .iter()
+                .map(|(_, alias, column)| {
+                    if alias == column {
+                        alias.clone()
+                    } else {
+                        format!("{} as {}", alias, column)
+                    }
+                })
+                .collect();
+            sql = format!("select {} from ({}) as __result", outer.join(","), sql);
+        }
+
+        let orders: Vec<String> = self
+            .get_orders()
+            .iter()
+            .map(|o| {
+                let order_type = match o.order_type {
+                    OrderType::ASC => "asc",
+                    OrderType::DESC => "desc",
+                };
+                let column = columns
+                    .iter()
+                    .find(|(name, _, _)| *name == o.field.field_name)
+                    .map(|(_, alias, _)| alias.clone())
+                    .unwrap_or_else(|| writer.column(&o.field));
+                format!("{} {}", column, order_type)
+            })
+            .collect();
+        if !orders.is_empty() {
+            sql.push_str(" order by ");
+            sql.push_str(&orders.join(","));
+        }
+
+        sql.push_str(&dialect.limit(self.get_limit(), self.get_offset()));
+
+        Ok(CompiledQuery {
+            sql,
+            params: writer.params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::{ClickHouseDialect, MySqlDialect, SqliteDialect};
+    use crate::query_builder::{DataType, DateUnit, Measure, MeasureFn, Order, TopN};
+
+    fn region() -> Field {
+        Field::new(String::from("region"), DataType::Text, String::from("地区"))
+    }
+
+    fn city() -> Field {
+        Field::new(String::from("city"), DataType::Text, String::from("城市"))
+    }
+
+    fn day() -> Field {
+        Field::new(String::from("day"), DataType::Date, String::from("日期"))
+    }
+
+    fn amount() -> Measure {
+        Measure::new(
+            Field::new(
+                String::from("amount"),
+                DataType::Number,
+                String::from("金额"),
+            ),
+            MeasureFn::SUM,
+        )
+    }
+
+    fn sales() -> QueryBuilder {
+        QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(region())])
+            .col(vec![Dimension::new_col(day()).trunc(DateUnit::Month)])
+            .meas(vec![amount()])
+            .filter(vec![
+                Filter::eq(city(), Value::from("O'Hare")),
+                Filter::in_list(region(), vec![Value::from("east"), Value::from("west")]),
+            ])
+            .order(vec![Order::new_with_order(amount().field, OrderType::DESC)])
+            .limit(100)
+    }
+
+    #[test]
+    fn test_clickhouse() {
+        let compiled = sales().to_sql(&ClickHouseDialect).unwrap();
+        assert_eq!(
+            compiled.sql,
+            "select `region`,`__d1` as `day`,`__m0` as `amount` from (select `region`,\
+             toStartOfMonth(`day`) as `__d1`,sum(`amount`) as `__m0` from `sales` \
+             where `city` = 'O\\'Hare' and `region` in ('east','west') \
+             group by `region`,toStartOfMonth(`day`)) as __result \
+             order by `__m0` desc limit 100"
+        );
+        assert!(compiled.params.is_empty());
+    }
+
+    #[test]
+    fn test_mysql() {
+        let compiled = sales().to_sql(&MySqlDialect).unwrap();
+        assert_eq!(
+            compiled.sql,
+            "select `region`,`__d1` as `day`,`__m0` as `amount` from (select `region`,\
+             cast(date_format(`day`,'%Y-%m-01') as date) as `__d1`,sum(`amount`) as `__m0` \
+             from `sales` where `city` = ? and `region` in (?,?) \
+             group by `region`,cast(date_format(`day`,'%Y-%m-01') as date)) as __result \
+             order by `__m0` desc limit 100"
+        );
+        assert_eq!(
+            compiled.params,
+            vec![
+                Value::from("O'Hare"),
+                Value::from("east"),
+                Value::from("west")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sqlite() {
+        let compiled = sales().offset(200).to_sql(&SqliteDialect).unwrap();
+        assert_eq!(
+            compiled.sql,
+            "select \"region\",\"__d1\" as \"day\",\"__m0\" as \"amount\" from \
+             (select \"region\",date(\"day\",'start of month') as \"__d1\",\
+             sum(\"amount\") as \"__m0\" from \"sales\" where \"city\" = ?1 \
+             and \"region\" in (?2,?3) group by \"region\",date(\"day\",'start of month')) \
+             as __result order by \"__m0\" desc limit 100 offset 200"
+        );
+        assert_eq!(compiled.params.len(), 3);
+    }
+
+    #[test]
+    fn test_totals() {
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(region())])
+            .col(vec![Dimension::new_col(city())])
+            .meas(vec![amount()]);
+
+        let sql = qb.clone().rollup().to_sql(&ClickHouseDialect).unwrap().sql;
+        assert_eq!(
+            sql,
+            "select `region`,`city`,`__m0` as `amount`,`__grouping` from \
+             (select `region`,`city`,sum(`amount`) as `__m0`,\
+             grouping(`region`,`city`) as `__grouping` \
+             from `sales` group by `region`,`city` with rollup) as __result"
+        );
+
+        let sql = qb.clone().cube().to_sql(&ClickHouseDialect).unwrap().sql;
+        assert!(sql.contains(" group by `region`,`city` with cube)"));
+
+        let sql = qb
+            .clone()
+            .grand_total()
+            .to_sql(&ClickHouseDialect)
+            .unwrap()
+            .sql;
+        assert!(sql.contains(" group by grouping sets ((`region`,`city`),()))"));
+
+        let sql = qb
+            .clone()
+            .grouping_sets(vec![vec![region(), city()], vec![region()], vec![]])
+            .to_sql(&ClickHouseDialect)
+            .unwrap()
+            .sql;
+        assert!(sql.contains(" group by grouping sets ((`region`,`city`),(`region`),()))"));
+
+        let sql = qb.clone().rollup().to_sql(&MySqlDialect).unwrap().sql;
+        assert!(sql.contains(" group by `region`,`city` with rollup)"));
+
+        let error = qb.clone().cube().to_sql(&MySqlDialect).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid query: cube is not supported by MySQL"
+        );
+        assert!(qb.rollup().to_sql(&SqliteDialect).is_err());
+    }
+
+    #[test]
+    fn test_top_n() {
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(city())])
+            .meas(vec![amount()])
+            .top_n(TopN::new(city(), amount(), 10));
+        let sql = qb.to_sql(&ClickHouseDialect).unwrap().sql;
+        assert_eq!(
+            sql,
+            "select `__d0` as `city`,`__m0` as `amount` from (select case when `city` in \
+             (select `city` from `sales` group by `city` order by sum(`amount`) desc limit 10) \
+             then toString(`city`) else 'Others' end as `__d0`,sum(`amount`) as `__m0` \
+             from `sales` group by case when `city` in (select `city` from `sales` \
+             group by `city` order by sum(`amount`) desc limit 10) then toString(`city`) \
+             else 'Others' end) as __result"
+        );
+
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![
+                Dimension::new_row(region()),
+                Dimension::new_row(city()),
+            ])
+            .meas(vec![amount()])
+            .filter(vec![Filter::gt(amount().field, Value::from(0.5))])
+            .top_n(
+                TopN::new(city(), amount(), 3)
+                    .partition_by(region())
+                    .others_label(String::from("其他")),
+            );
+        let sql = qb.to_sql(&ClickHouseDialect).unwrap().sql;
+        assert!(sql.starts_with(
+            "select `region`,`__d1` as `city`,`__m0` as `amount` from (select `region`,\
+             case when (`region`,`city`) in (select `region`,`city` \
+             from `sales` where `amount` > 0.5 group by `region`,`city` \
+             order by sum(`amount`) desc limit 3 by `region`) then toString(`city`) \
+             else '其他' end as `__d1`"
+        ));
+
+        let compiled = qb.to_sql(&SqliteDialect).unwrap();
+        assert!(compiled.sql.starts_with(
+            "select \"region\",\"__d1\" as \"city\",\"__m0\" as \"amount\" from \
+             (select \"region\",case when (\"region\",\"city\") in (select __parent,__member \
+             from (select \"region\" as __parent,\"city\" as __member,row_number() over \
+             (partition by \"region\" order by sum(\"amount\") desc) as __rank from \"sales\" \
+             where \"amount\" > ?1 group by \"region\",\"city\") __top_n where __rank <= 3) \
+             then cast(\"city\" as text) else '其他' end as \"__d1\""
+        ));
+        // bound in the select, the where and the group by
+        assert_eq!(compiled.params.len(), 3);
+
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(city())])
+            .meas(vec![amount()])
+            .top_n(TopN::new(city(), amount(), 10).others_label(String::from("\\' or 1=1 -- ")));
+        let sql = qb.to_sql(&MySqlDialect).unwrap().sql;
+        // the backslash is escaped too, the label stays in the string
+        assert_eq!(
+            sql,
+            "select `__d0` as `city`,`__m0` as `amount` from (select case when `city` in \
+             (select __member from (select `city` as __member,row_number() over \
+             (order by sum(`amount`) desc) as __rank from `sales` group by `city`) __top_n \
+             where __rank <= 10) then cast(`city` as char) else '\\\\'' or 1=1 -- ' end \
+             as `__d0`,sum(`amount`) as `__m0` from `sales` group by case when `city` in \
+             (select __member from (select `city` as __member,row_number() over \
+             (order by sum(`amount`) desc) as __rank from `sales` group by `city`) __top_n \
+             where __rank <= 10) then cast(`city` as char) else '\\\\'' or 1=1 -- ' end) \
+             as __result"
+        );
+    }
+
+    #[test]
+    fn test_filter_measure_column() {
+        let qb = QueryBuilder::new()
+            .table(String::from("sales"))
+            .row(vec![Dimension::new_row(day()).trunc(DateUnit::Month)])
+            .meas(vec![amount()])
+            .filter(vec![
+                Filter::gt(amount().field, Value::from(0.5)),
+                Filter::eq(day(), Value::from("2021-01-01")),
+            ])
+            .order(vec![
+                Order::new_with_order(amount().field, OrderType::DESC),
+                Order::new(day()),
+            ]);
+        // the where clause refers to the columns, not to the aggregates
+        assert_eq!(
+            qb.to_sql(&ClickHouseDialect).unwrap().sql,
+            "select `__d0` as `day`,`__m0` as `amount` from (select \
+             toStartOfMonth(`day`) as `__d0`,sum(`amount`) as `__m0` from `sales` \
+             where `amount` > 0.5 and `day` = '2021-01-01' \
+             group by toStartOfMonth(`day`)) as __result \
+             order by `__m0` desc,`__d0` asc"
+        );
+    }
+
+    #[test]
+    fn test_invalid_identifier() {
+        let qb = QueryBuilder::new()
+            .table(String::from("sales; drop table sales"))
+            .meas(vec![amount()]);
+        assert!(qb.to_sql(&MySqlDialect).is_err());
+    }
+}
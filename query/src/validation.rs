@@ -0,0 +1,236 @@
+use crate::query_builder::{DataType, Field, Measure, MeasureFn, QueryBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+///查询校验发现的问题
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum QueryError {
+    ///表名或字段名不是合法的标识符
+    InvalidIdentifier(String),
+    ///查询的表不是数据集的表
+    UnknownTable(String),
+    ///数据集中不存在的字段
+    UnknownField(String),
+    ///度量函数不支持字段的数据类型
+    InvalidMeasure {
+        field: String,
+        measure: MeasureFn,
+        data_type: DataType,
+    },
+    ///目标sql方言不支持的功能
+    Unsupported { dialect: String, feature: String },
+    ///建表时没有字段
+    EmptyTable(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::InvalidIdentifier(name) => write!(f, "invalid identifier: {}", name),
+            QueryError::UnknownTable(name) => write!(f, "unknown table: {}", name),
+            QueryError::UnknownField(name) => write!(f, "unknown field: {}", name),
+            QueryError::InvalidMeasure {
+                field,
+                measure,
+                data_type,
+            } => write!(
+                f,
+                "{:?} is not supported on {} of type {:?}",
+                measure, field, data_type
+            ),
+            QueryError::Unsupported { dialect, feature } => {
+                write!(f, "{} is not supported by {}", feature, dialect)
+            }
+            QueryError::EmptyTable(name) => write!(f, "table {} has no fields", name),
+        }
+    }
+}
+
+///查询校验失败, 包含所有发现的问题
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidationError {
+    pub errors: Vec<QueryError>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "invalid query: {}", messages.join("; "))
+    }
+}
+
+impl Error for ValidationError {}
+
+///标识符只允许字母, 数字和下划线, 且不能以数字开头
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl MeasureFn {
+    ///度量函数是否支持该数据类型
+    pub fn supports(&self, data_type: DataType) -> bool {
+        match self {
+            MeasureFn::SUM | MeasureFn::AVG => data_type == DataType::Number,
+            MeasureFn::MAX | MeasureFn::MIN => data_type != DataType::Text,
+            MeasureFn::COUNT => true,
+        }
+    }
+}
+
+impl QueryBuilder {
+    ///检查表名和字段名, 拼接sql前必须通过
+    pub fn check_identifiers(&self) -> Result<(), ValidationError> {
+        let mut errors = vec![];
+        self.push_identifier_errors(&mut errors);
+        Self::to_result(errors)
+    }
+
+    ///根据数据集的表名和字段校验查询, 字段类型以数据集为准
+    pub fn validate(&self, table: &str, fields: &[Field]) -> Result<(), ValidationError> {
+        let mut errors = vec![];
+        self.push_identifier_errors(&mut errors);
+
+        if self.get_table() != table {
+            errors.push(QueryError::UnknownTable(self.get_table().clone()));
+        }
+
+        let types: HashMap<&str, DataType> = fields
+            .iter()
+            .map(|f| (f.field_name.as_str(), f.field_type))
+            .collect();
+
+        for field in self.get_fields() {
+            let error = QueryError::UnknownField(field.field_name.clone());
+            if !types.contains_key(field.field_name.as_str()) && !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+
+        let mut measures: Vec<&Measure> = self.get_meas().iter().collect();
+        if let Some(top_n) = self.get_top_n() {
+            measures.push(&top_n.measure);
+        }
+        for measure in measures {
+            if let Some(data_type) = types.get(measure.field.field_name.as_str()) {
+                if !measure.measure_type.supports(*data_type) {
+                    errors.push(QueryError::InvalidMeasure {
+                        field: measure.field.field_name.clone(),
+                        measure: measure.measure_type,
+                        data_type: *data_type,
+                    });
+                }
+            }
+        }
+
+        Self::to_result(errors)
+    }
+
+    fn push_identifier_errors(&self, errors: &mut Vec<QueryError>) {
+        let mut names = vec![self.get_table().as_str()];
+        names.extend(self.get_fields().iter().map(|f| f.field_name.as_str()));
+        names.extend(self.get_meas().iter().filter_map(|m| m.alias.as_deref()));
+        for name in names {
+            let error = QueryError::InvalidIdentifier(name.to_string());
+            if !is_identifier(name) && !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+    }
+
+    fn to_result(errors: Vec<QueryError>) -> Result<(), ValidationError> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { errors })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query_builder::Dimension;
+
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new(String::from("org"), DataType::Text, String::from("组织")),
+            Field::new(String::from("day"), DataType::Date, String::from("时间")),
+            Field::new(
+                String::from("amount"),
+                DataType::Number,
+                String::from("金额"),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_is_identifier() {
+        assert!(is_identifier("f_1a2b3c"));
+        assert!(is_identifier("_a"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("1a"));
+        assert!(!is_identifier("a;drop table t"));
+        assert!(!is_identifier("a.b"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let fields = fields();
+        let qb = QueryBuilder::new()
+            .table(String::from("t_1"))
+            .row(vec![Dimension::new_row(fields[0].clone())])
+            .meas(vec![
+                Measure::new(fields[1].clone(), MeasureFn::MAX),
+                Measure::new(fields[2].clone(), MeasureFn::SUM),
+            ]);
+        assert_eq!(qb.validate("t_1", &fields), Ok(()));
+
+        let removed = Field::new(String::from("removed"), DataType::Number, String::new());
+        // the type sent by the client is ignored, org is Text in the dataset
+        let org = Field::new(String::from("org"), DataType::Number, String::new());
+        let qb = QueryBuilder::new()
+            .table(String::from("t_2"))
+            .row(vec![Dimension::new_row(removed.clone())])
+            .meas(vec![
+                Measure::new(org, MeasureFn::SUM),
+                Measure::new(removed, MeasureFn::COUNT),
+            ]);
+        let errors = qb.validate("t_1", &fields).unwrap_err().errors;
+        assert_eq!(
+            errors,
+            vec![
+                QueryError::UnknownTable(String::from("t_2")),
+                QueryError::UnknownField(String::from("removed")),
+                QueryError::InvalidMeasure {
+                    field: String::from("org"),
+                    measure: MeasureFn::SUM,
+                    data_type: DataType::Text,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_identifiers() {
+        let evil = Field::new(
+            String::from("amount) from t; drop table t; --"),
+            DataType::Number,
+            String::new(),
+        );
+        let qb = QueryBuilder::new()
+            .table(String::from("t_1"))
+            .meas(vec![Measure::new(evil.clone(), MeasureFn::SUM)]);
+        let error = qb.check_identifiers().unwrap_err();
+        assert_eq!(
+            error.errors,
+            vec![QueryError::InvalidIdentifier(evil.field_name)]
+        );
+    }
+}
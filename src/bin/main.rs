@@ -3,14 +3,18 @@ extern crate log;
 extern crate pretty_env_logger;
 
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use crud_crait::audit;
+use crud_crait::backend::MetadataPool;
 use dotenv;
 use formula::neo4j_session::Neo4jSession;
 use graphql::RootSchema;
-use lightingbi::handler::{default, query};
-use sqlx::MySqlPool;
+use lightingbi::migrations::create_migrator;
+use lightingbi::registry::{create_batch_executor, create_cache, create_registry};
+use lightingbi::handler::{default, export, query};
 use std::convert::Infallible;
 use std::env;
 use std::net::ToSocketAddrs;
+use std::sync::Arc;
 use warp::http::Response as HttpResponse;
 use warp::Filter;
 
@@ -20,10 +24,39 @@ async fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is not set in .env file");
-    let db_pool = MySqlPool::connect(&database_url).await?;
+    let db_pool = MetadataPool::connect(&database_url).await?;
+
+    // lightingbi migrate [--dry-run] , apply or list the pending migrations and exit
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        let dry_run = args.iter().any(|arg| arg == "--dry-run");
+        return migrate(&db_pool, dry_run).await;
+    }
+    // AUTO_MIGRATE=false , the migrations are applied at startup by default
+    if env::var("AUTO_MIGRATE").as_deref() != Ok("false") {
+        migrate(&db_pool, false).await?;
+    }
+
     let neo4j_graph = Neo4jSession::get_graph().await.unwrap();
 
-    let schema = graphql::create_schema(&db_pool, &neo4j_graph);
+    let engines = env::var("ENGINES").expect("ENGINES is not set in .env file");
+    let mut registry = create_registry(&engines).await?;
+    // QUERY_CACHE=ttl_secs,max_mb , the cache is disabled when not set
+    if let Ok(cache) = env::var("QUERY_CACHE") {
+        registry = registry.cached(Arc::new(create_cache(&cache)?));
+    }
+    let registry = Arc::new(registry);
+    match dataset::PreAggregationResolver::register_all(&db_pool, &registry).await {
+        Ok(count) => info!("{} pre-aggregations registered", count),
+        Err(e) => error!("failed to register pre-aggregations: {}", e),
+    }
+
+    // DASHBOARD_CONCURRENCY=n,name=n , the concurrent queries of a dashboard on each engine
+    let executor = match env::var("DASHBOARD_CONCURRENCY") {
+        Ok(config) => create_batch_executor(&config)?,
+        Err(_) => engine_craits::BatchExecutor::default(),
+    };
+    let schema = graphql::create_schema(&db_pool, &neo4j_graph, &registry, &Arc::new(executor));
 
     let address = env::var("ADDRESS").expect("ADDRESS is not set in .env file");
 
@@ -35,14 +68,20 @@ async fn main() -> anyhow::Result<()> {
         .and(warp::path("static"))
         .and(warp::fs::dir("static"));
 
-    let graphql_post = async_graphql_warp::graphql(schema).and_then(
-        |(schema, request): (RootSchema, async_graphql::Request)| async move {
-            // Execute query
-            let resp = schema.execute(request).await;
-            // Return result
-            Ok::<_, Infallible>(async_graphql_warp::Response::from(resp))
-        },
-    );
+    // the header `x-user` names the user of a request, there is no authentication yet
+    let graphql_post = async_graphql_warp::graphql(schema)
+        .and(warp::header::optional::<String>("x-user"))
+        .and_then(
+            |(schema, request): (RootSchema, async_graphql::Request), user: Option<String>| async move {
+                // Execute query, the rows it inserts are `created_by` the user
+                let resp = match user {
+                    Some(user) => audit::with_actor(user, schema.execute(request)).await,
+                    None => schema.execute(request).await,
+                };
+                // Return result
+                Ok::<_, Infallible>(async_graphql_warp::Response::from(resp))
+            },
+        );
 
     let graphql_playground = warp::get()
         .and(warp::path("playground"))
@@ -56,6 +95,7 @@ async fn main() -> anyhow::Result<()> {
     let routes = graphql_playground
         .or(graphql_post)
         .or(query::route())
+        .or(export::route(db_pool.clone(), registry.clone()))
         // GET /
         .or(index)
         //GET /static/xxx
@@ -70,3 +110,25 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+async fn migrate(db_pool: &MetadataPool, dry_run: bool) -> anyhow::Result<()> {
+    let migrator = create_migrator()?;
+    let migrations = migrator.run(db_pool, dry_run).await?;
+    let action = if dry_run { "pending" } else { "applied" };
+    for migration in &migrations {
+        info!(
+            "migration {} {} {} , checksum {}",
+            migration.version,
+            migration.name,
+            action,
+            migration.checksum()
+        );
+    }
+    info!(
+        "{} migrations {} , schema version table {}",
+        migrations.len(),
+        action,
+        migrator.version_table()
+    );
+    Ok(())
+}
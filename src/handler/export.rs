@@ -0,0 +1,84 @@
+use crud_crait::backend::MetadataPool;
+use dataset::DataSetResolver;
+use engine_craits::{export, EngineRegistry, ExportFormat, QueryOptions};
+use query::QueryBuilder;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::http::{Response, StatusCode};
+use warp::hyper::body::{Body, Bytes};
+use warp::{Filter, Rejection};
+
+#[derive(Debug, Deserialize)]
+struct ExportParams {
+    ///csv or jsonl
+    #[serde(default)]
+    format: String,
+    #[serde(default)]
+    query_id: String,
+    timeout_ms: Option<u64>,
+}
+
+///POST /export/{dataset_id}?format=csv with the query as body,
+///the result is sent in chunks as the engine produces it
+pub fn route(
+    pool: MetadataPool,
+    registry: Arc<EngineRegistry>,
+) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
+    warp::path!("export" / String)
+        .and(warp::post())
+        .and(warp::query::<ExportParams>())
+        .and(warp::body::content_length_limit(1024 * 64))
+        .and(warp::body::bytes())
+        .and(warp::any().map(move || pool.clone()))
+        .and(warp::any().map(move || registry.clone()))
+        .and_then(export_dataset)
+}
+
+async fn export_dataset(
+    dataset_id: String,
+    params: ExportParams,
+    body: Bytes,
+    pool: MetadataPool,
+    registry: Arc<EngineRegistry>,
+) -> Result<Response<Body>, Infallible> {
+    debug!("export dataset {} as {:?}", dataset_id, params);
+    let format = match params.format.as_str() {
+        "" => ExportFormat::Csv,
+        name => match ExportFormat::from_name(name) {
+            Some(format) => format,
+            None => {
+                let message = format!("unsupported export format {}", name);
+                return Ok(error_response(StatusCode::BAD_REQUEST, message));
+            }
+        },
+    };
+    let query_builder = match QueryBuilder::from_json(&String::from_utf8_lossy(&body)) {
+        Ok(query_builder) => query_builder,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.to_string())),
+    };
+    let mut options = QueryOptions::new().query_id(&params.query_id);
+    options.timeout = params.timeout_ms.map(Duration::from_millis);
+
+    let blocks =
+        match DataSetResolver::stream(&dataset_id, query_builder, &options, &pool, &registry).await
+        {
+            Ok(blocks) => blocks,
+            Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, e.to_string())),
+        };
+    // errors after the first chunk abort the chunked response
+    let response = Response::builder()
+        .header("content-type", format.content_type())
+        .body(Body::wrap_stream(export(blocks, format)))
+        .unwrap();
+    Ok(response)
+}
+
+fn error_response(code: StatusCode, message: String) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(Body::from(message))
+        .unwrap()
+}
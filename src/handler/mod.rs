@@ -1,2 +1,3 @@
 pub mod default;
+pub mod export;
 pub mod query;
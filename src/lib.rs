@@ -1,4 +1,6 @@
 #[macro_use]
 extern crate log;
 
+pub mod registry;
 pub mod handler;
+pub mod migrations;
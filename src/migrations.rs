@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use crud_crait::backend::{Backend, Metadata};
+use crud_crait::migration::{Migration, Migrator};
+
+macro_rules! migrations {
+    ($backend:literal) => {
+        vec![
+            Migration::new(
+                1,
+                "init",
+                include_str!(concat!("../migrations/", $backend, "/0001_init.sql")),
+            ),
+            Migration::new(
+                2,
+                "signed_user_id",
+                include_str!(concat!(
+                    "../migrations/",
+                    $backend,
+                    "/0002_signed_user_id.sql"
+                )),
+            ),
+            Migration::new(
+                3,
+                "audit_columns",
+                include_str!(concat!(
+                    "../migrations/",
+                    $backend,
+                    "/0003_audit_columns.sql"
+                )),
+            ),
+        ]
+    };
+}
+
+///the migrations of the metadata database of a backend, embedded in the binary.
+///add a file `migrations/<backend>/NNNN_name.sql` for every backend and append it here,
+///never edit an applied one
+pub fn migrations(backend: &str) -> Result<Vec<Migration>> {
+    match backend {
+        "mysql" => Ok(migrations!("mysql")),
+        "postgres" => Ok(migrations!("postgres")),
+        "sqlite" => Ok(migrations!("sqlite")),
+        _ => Err(anyhow!("no migrations for the backend {}", backend)),
+    }
+}
+
+///the migrator of the configured metadata database
+pub fn create_migrator() -> Result<Migrator> {
+    Migrator::new(migrations(Metadata::NAME)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations() -> Result<()> {
+        for backend in &["mysql", "postgres", "sqlite"] {
+            let migrations = migrations(backend)?;
+            assert_eq!(migrations.len(), 3);
+            let init = migrations[0].statements("t_lighting");
+            for table in &[
+                "user",
+                "dataset",
+                "field",
+                "saved_query",
+                "saved_pre_aggregation",
+            ] {
+                let create = format!("CREATE TABLE IF NOT EXISTS t_lighting_{} (", table);
+                assert!(init.iter().any(|statement| statement.starts_with(&create)));
+            }
+            assert!(init.iter().all(|statement| !statement.contains("${")));
+        }
+        assert!(migrations("oracle").is_err());
+        Ok(())
+    }
+}
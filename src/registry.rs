@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use engine_craits::{BatchExecutor, EngineRegistry, QueryCache};
+use engines::{ClickHouseConfig, ClickHouseEngine};
+use memory_engine::MemoryEngine;
+use sqlite_engine::SqliteEngine;
+use std::sync::Arc;
+use std::time::Duration;
+
+///Create the engines from `name=url` pairs separated by commas, the scheme of the url
+///chooses the engine: `tcp://` for ClickHouse, `sqlite:` for Sqlite and `memory:` for Memory.
+///The options of ClickHouse are the parameters of its url, see `ClickHouseConfig::from_url`
+pub async fn create_registry(config: &str) -> Result<EngineRegistry> {
+    let mut registry = EngineRegistry::new();
+    for connection in config.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let (name, url) = match connection.find('=') {
+            Some(index) => (&connection[..index], &connection[index + 1..]),
+            None => return Err(anyhow!("engine {} should be name=url", connection)),
+        };
+        registry = if url.starts_with("tcp://") {
+            let engine = ClickHouseConfig::from_url(url)
+                .and_then(|config| {
+                    ClickHouseEngine::from_config(&config).map_err(|e| e.to_string())
+                })
+                .map_err(|e| anyhow!(e))?;
+            registry.register(name, Arc::new(engine))
+        } else if url.starts_with("sqlite:") {
+            let engine = SqliteEngine::new(url)
+                .await
+                .map_err(|e| anyhow!(e.to_string()))?;
+            registry.register(name, Arc::new(engine))
+        } else if url.starts_with("memory:") {
+            registry.register(name, Arc::new(MemoryEngine::new()))
+        } else {
+            return Err(anyhow!("unknown engine url {}", url));
+        };
+        info!("engine {} registered", name);
+    }
+    Ok(registry)
+}
+
+///Create the query result cache from `ttl_secs,max_mb`
+pub fn create_cache(config: &str) -> Result<QueryCache> {
+    let parse = |value: Option<&str>| -> Result<u64> {
+        value
+            .map(str::trim)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| anyhow!("query cache {} should be ttl_secs,max_mb", config))
+    };
+    let mut values = config.split(',');
+    let ttl = parse(values.next())?;
+    let max_mb = parse(values.next())?;
+    info!("query cache enabled, ttl {}s, {}MB", ttl, max_mb);
+    Ok(QueryCache::new(
+        Duration::from_secs(ttl),
+        max_mb as usize * 1024 * 1024,
+    ))
+}
+
+///Create the dashboard executor from the default concurrency per engine followed by
+///`name=n` limits of single engines, such as `4,local=1`
+pub fn create_batch_executor(config: &str) -> Result<BatchExecutor> {
+    let error = || anyhow!("dashboard concurrency {} should be n,name=n", config);
+    let parse = |value: &str| -> Result<usize> { value.trim().parse().map_err(|_| error()) };
+    let mut values = config.split(',');
+    let mut executor = BatchExecutor::new(parse(values.next().unwrap_or_default())?);
+    for limit in values.map(str::trim).filter(|l| !l.is_empty()) {
+        let index = limit.find('=').ok_or_else(error)?;
+        executor = executor.limit(limit[..index].trim(), parse(&limit[index + 1..])?);
+    }
+    Ok(executor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine_craits::EngineType;
+
+    #[tokio::test]
+    async fn test_create_registry() -> Result<()> {
+        let registry =
+            create_registry("default=tcp://localhost:9000, preview=memory:, local=sqlite::memory:")
+                .await?;
+        assert_eq!(registry.names(), vec!["default", "local", "preview"]);
+        assert_eq!(
+            registry.get("local").unwrap().engine_type(),
+            EngineType::Sqlite
+        );
+        assert!(create_registry("default").await.is_err());
+        assert!(create_registry("default=tcp://localhost:9000?pool=1")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_cache() {
+        assert_eq!(create_cache("300, 256").unwrap().stats().entries, 0);
+        assert!(create_cache("300").is_err());
+        assert!(create_cache("5m,256").is_err());
+    }
+    #[test]
+    fn test_create_batch_executor() {
+        let executor = create_batch_executor("4, local=1").unwrap();
+        assert_eq!(executor.concurrency("default"), 4);
+        assert_eq!(executor.concurrency("local"), 1);
+        assert!(create_batch_executor("").is_err());
+        assert!(create_batch_executor("4,local").is_err());
+        assert!(create_batch_executor("4,local=x").is_err());
+    }
+}